@@ -1,11 +1,16 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Error, Expr, ExprCall, FnArg, Ident, ItemFn, Token};
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::{parse_macro_input, Block, Error, Expr, ExprCall, FnArg, Ident, ItemFn, LitStr, Token};
 use syn::parse::{Parse, ParseStream};
 use syn::{punctuated::Punctuated, ImplItem, ItemImpl};
+use syn::visit_mut::{self, VisitMut};
 
 /// Mark a Label in a [`testable!`] function, that the `MainController` can [`run_to!`].
 ///
+/// Labels are qualified with the path of the enclosing function, so `label!("start")` in `fn worker`
+/// of module `my_mod` can be targeted as `"start"` or `"my_mod::worker::start"`.
+///
 /// ## Usage
 /// ```rust,ignore
 /// // code
@@ -15,31 +20,146 @@ use syn::{punctuated::Punctuated, ImplItem, ItemImpl};
 /// // code
 /// ```
 ///
+/// The thread parks at the label when [`run_to!`] targets it, and stays parked until it is resumed.
+///
 /// ## Expansion
 /// ```rust,ignore
 /// label!("label 1");
 /// // Expands to
-/// tokitest_thread_controller.label("label 1").await;
-/// tokitest_thread_controller.label("label 1 block").await;
+/// tokitest_thread_controller.label(tokitest_function, "label 1").await;
 /// ```
 #[proc_macro]
 pub fn label(input: TokenStream) -> TokenStream {
     let label = syn::parse_macro_input!(input as syn::LitStr);
-    let label_str = label.value();
-    let block_label = format!("{} block", label_str);
 
     let expanded = quote! {
-        #[cfg(feature = "tokitest")] // Label expands to nothing when not in test mode
+        #[cfg(tokitest)] // Label expands to nothing when not in test mode
         {
-            tokitest_thread_controller.label(#label).await;
-            tokitest_thread_controller.label(#block_label).await;
+            tokitest_thread_controller.label(tokitest_function, #label).await;
         }
     };
     TokenStream::from(expanded)
 }
 
+/// Finds the string literal of every `label!("...")` invocation, including those nested in other macros such as [`spawn!`].
+fn collect_labels(tokens: TokenStream2, labels: &mut Vec<String>) {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) if ident == "label" => {
+                if let (Some(TokenTree::Punct(bang)), Some(TokenTree::Group(group))) = (tokens.get(i + 1), tokens.get(i + 2)) {
+                    if bang.as_char() == '!' {
+                        if let Ok(label) = syn::parse2::<LitStr>(group.stream()) {
+                            if !labels.contains(&label.value()) {
+                                labels.push(label.value());
+                            }
+                        }
+                    }
+                }
+            },
+            TokenTree::Group(group) => collect_labels(group.stream(), labels),
+            _ => {}
+        }
+    }
+}
+
+/// Statements inserted at the start of a testable function:
+/// `tokitest_function` names the function that [`label!`] attributes its labels to,
+/// and async functions declare their labels, including `auto_labels`, for the label coverage report.
+fn function_prelude(function_path: TokenStream2, block: &Block, is_async: bool, auto_labels: &[TokenStream2]) -> TokenStream2 {
+    let mut labels = Vec::new();
+    collect_labels(block.to_token_stream(), &mut labels);
+
+    let declare = if is_async && !(labels.is_empty() && auto_labels.is_empty()) {
+        quote! { tokitest_thread_controller.declare_labels(tokitest_function, &[#(#labels,)* #(#auto_labels),*]).await; }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #[allow(unused_variables)]
+        let tokitest_function: &'static str = #function_path;
+        #declare
+    }
+}
+
+/// Inserts [`function_prelude`] at the start of the function body, after placing a checkpoint at every `.await` with `auto_labels`
+fn insert_prelude(block: &mut Block, function_path: TokenStream2, is_async: bool, options: &TestableOptions) {
+    let mut auto_labels = AutoLabels::default();
+    if options.auto_labels {
+        auto_labels.visit_block_mut(block);
+    }
+    let prelude = function_prelude(function_path, block, is_async, &auto_labels.labels);
+    let prelude_block: Block = syn::parse_quote! {{ #prelude }};
+    block.stmts.splice(0..0, prelude_block.stmts);
+}
+
+/// Options of [`testable`] and [`testable_struct`]
+#[derive(Default)]
+struct TestableOptions {
+    auto_labels: bool,
+}
+
+impl Parse for TestableOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut options = TestableOptions::default();
+        for option in Punctuated::<Ident, Token![,]>::parse_terminated(input)? {
+            match option.to_string().as_str() {
+                "auto_labels" => options.auto_labels = true,
+                _ => return Err(Error::new_spanned(option, "unknown option, expected `auto_labels`")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Rewrites every `future.await` of a function body into a checkpoint labelled `{file}:{line}`,
+/// the source location of the `.await`, placed after the future is created and before it is first polled.
+///
+/// Closures, async blocks and nested items may run in another task or function, so their `.await`s are left alone,
+/// as are those inside macro invocations.
+#[derive(Default)]
+struct AutoLabels {
+    labels: Vec<TokenStream2>,
+}
+
+impl VisitMut for AutoLabels {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Closure(_) | Expr::Async(_) => {},
+            Expr::Await(awaited) => {
+                self.visit_expr_mut(&mut awaited.base);
+                let line = quote_spanned! { awaited.await_token.span=> line!() };
+                let label = quote! { concat!(file!(), ":", #line) };
+                self.labels.push(label.clone());
+                let future = &awaited.base;
+                // A match keeps the temporaries of the awaited expression alive until the future completes
+                *expr = syn::parse_quote! {
+                    match #future {
+                        tokitest_future => {
+                            tokitest_thread_controller.label(tokitest_function, #label).await;
+                            tokitest_future.await
+                        }
+                    }
+                };
+            },
+            _ => visit_mut::visit_expr_mut(self, expr),
+        }
+    }
+
+    fn visit_item_mut(&mut self, _item: &mut syn::Item) {}
+}
+
 /// Mark a function as `testable` to allow it to contain [`label!`], [`call!`], [`Networkcall!`]
 ///
+/// ## Options
+/// - `auto_labels`: every `.await` in the function body becomes a checkpoint, as if a [`label!`] named after
+///   the source location of the `.await`, e.g. `src/node.rs:42`, was placed once the awaited future is created
+///   and before it is polled, so in `a().await.b().await` the checkpoint of the second `.await` follows the first.
+///   The labels are qualified with the function like any other, so [`run_to!`] can stop the thread at each yield point
+///   of the function, and `RegexLabel::new(Regex::new(r"node\.rs:4[0-9]$").unwrap())` matches a range of lines.
+///   `.await`s in closures, async blocks and macro invocations such as [`spawn!`] are not rewritten.
+///
 /// ## Usage
 /// ```rust,ignore
 /// #[testable]
@@ -61,9 +181,25 @@ pub fn label(input: TokenStream) -> TokenStream {
 /// fn my_function (tokitest_thread_controller &std::sync::Arc<::tokitest::controller::ThreadController>, arg: i32, ...) {
 ///     ...
 /// }
+///
+/// #[testable(auto_labels)]
+/// async fn my_async_function() {
+///     fetch().await;
+/// }
+/// // Expands to
+/// async fn my_async_function(tokitest_thread_controller: std::sync::Arc<::tokitest::controller::ThreadController>) {
+///     ...
+///     match fetch() {
+///         tokitest_future => {
+///             tokitest_thread_controller.label(tokitest_function, concat!(file!(), ":", 3)).await;
+///             tokitest_future.await
+///         }
+///     };
+/// }
 /// ```
 #[proc_macro_attribute]
-pub fn testable(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn testable(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as TestableOptions);
     let mut input_fn = parse_macro_input!(item as ItemFn);
 
     // Create the first argument: controller: &Arc<::tokitest::controller::ThreadController>
@@ -80,18 +216,24 @@ pub fn testable(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
     input_fn.sig.inputs.insert(insert_pos, controller_arg);
 
+    let fn_name = input_fn.sig.ident.to_string();
+    let is_async = input_fn.sig.asyncness.is_some();
+    insert_prelude(&mut input_fn.block, quote! { concat!(module_path!(), "::", #fn_name) }, is_async, &options);
+
     // Return modified function
     TokenStream::from(quote! {
-        #[cfg(feature = "tokitest")]
+        #[cfg(tokitest)]
         #input_fn
         
-        #[cfg(not(feature = "tokitest"))]
+        #[cfg(not(tokitest))]
         #unchanged_input_fn
     })
 }
 
 /// Makes all functions in an impl block `testable`
 ///
+/// `#[testable_struct(auto_labels)]` applies the `auto_labels` option of [`testable`] to each of them.
+///
 /// ## Usage
 /// ```rust,ignore
 /// struct MyStruct {}
@@ -136,8 +278,10 @@ pub fn testable(_attr: TokenStream, item: TokenStream) -> TokenStream {
 // }
 
 #[proc_macro_attribute]
-pub fn testable_struct(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn testable_struct(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as TestableOptions);
     let input_impl = parse_macro_input!(item as ItemImpl);
+    let type_name: String = input_impl.self_ty.to_token_stream().to_string().split_whitespace().collect();
 
     // Split each method into two: test and non-test versions
     let mut new_items = Vec::new();
@@ -159,14 +303,18 @@ pub fn testable_struct(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
             test_method.sig.inputs.insert(insert_pos, controller_arg);
 
+            let function_path = format!("{}::{}", type_name, test_method.sig.ident);
+            let is_async = test_method.sig.asyncness.is_some();
+            insert_prelude(&mut test_method.block, quote! { concat!(module_path!(), "::", #function_path) }, is_async, &options);
+
             // Wrap each in cfg
             new_items.push(ImplItem::Verbatim(quote! {
-                #[cfg(feature = "tokitest")]
+                #[cfg(tokitest)]
                 #test_method
             }));
 
             new_items.push(ImplItem::Verbatim(quote! {
-                #[cfg(not(feature = "tokitest"))]
+                #[cfg(not(tokitest))]
                 #non_test_method
             }));
         } else {
@@ -230,11 +378,11 @@ pub fn call(input: TokenStream) -> TokenStream {
             let args_normal = quote! { #args };
             let expanded = quote! {
                 {
-                    #[cfg(feature = "tokitest")]
+                    #[cfg(tokitest)]
                     {
                         #func(#args_test)
                     }
-                    #[cfg(not(feature = "tokitest"))]
+                    #[cfg(not(tokitest))]
                     {
                         #func(#args_normal)
                     }
@@ -251,11 +399,11 @@ pub fn call(input: TokenStream) -> TokenStream {
 
             let expanded = quote! {
                 {
-                    #[cfg(feature = "tokitest")]
+                    #[cfg(tokitest)]
                     {
                         #receiver.#method(#args_test)
                     }
-                    #[cfg(not(feature = "tokitest"))]
+                    #[cfg(not(tokitest))]
                     {
                         #receiver.#method(#args_normal)
                     }
@@ -270,6 +418,19 @@ pub fn call(input: TokenStream) -> TokenStream {
 }
 
 
+/// The task of a testable thread controlled by `tcNew`: reports INIT, runs the body as the thread, then reports END
+fn testable_task(body: &Expr) -> TokenStream2 {
+    quote! {
+        async move {
+            tcNew.label(tokitest_function, "INIT").await;
+            let tokitest_thread_controller = tcNew.clone();
+            let result = tcNew.clone().scope({ #body }).await;
+            tcNew.finish(tokitest_function).await;
+            result
+        }
+    }
+}
+
 /// Use the `spawn!` macro to spawn a testable thread with the new thread ID, using `tokio::spawn`.
 /// 
 /// The Spawned thread inherits it's parent's thread ID and adds the new ID as a prefix.
@@ -277,7 +438,7 @@ pub fn call(input: TokenStream) -> TokenStream {
 /// 
 /// ## Usage
 /// 
-/// ```
+/// ```rust,ignore
 /// // Spawns a thread with threadID `thread1`
 /// spawn!("thread1", async {
 ///     label!("label 1");
@@ -297,7 +458,7 @@ pub fn call(input: TokenStream) -> TokenStream {
 /// ```
 /// 
 /// ## Expansion
-/// ```rust
+/// ```rust,ignore
 /// // from
 /// spawn!("child thread", async {
 ///     // some async code
@@ -307,12 +468,12 @@ pub fn call(input: TokenStream) -> TokenStream {
 /// 
 /// let tcNew = tokitest_thread_controller.nest("child thread").await;
 /// tokio::spawn(async move {
-///     tcNew.label("INIT").await;
+///     tcNew.label(tokitest_function, "INIT").await;
 ///     let tokitest_thread_controller = tcNew.clone();
 ///     let result = {
 ///      // some async code
 ///     }.await;
-///     tcNew.label("END").await;
+///     tcNew.finish(tokitest_function).await;
 ///     result
 /// })
 /// ```
@@ -335,23 +496,21 @@ pub fn spawn(input: TokenStream) -> TokenStream {
     }
 
     let SpawnInput { label, _comma, body } = parse_macro_input!(input as SpawnInput);
+    let task = testable_task(&body);
 
     let expanded = quote! {
         {
-            #[cfg(feature = "tokitest")]
+            #[cfg(tokitest)]
             {
                 // let tcNew = tokitest_thread_controller.nest(#label).await;
                 let tcNew = tokitest_thread_controller.nest().with_id(#label).build().await;
-                tokio::spawn(async move {
-                    tcNew.label("INIT").await;
-                    let tokitest_thread_controller = tcNew.clone();
-                    let result = { #body }.await;
-                    tcNew.label("END").await;
-                    result
-                })
+                let tokitest_new_thread = tcNew.clone();
+                let tokitest_handle = tokio::spawn(#task);
+                tokitest_new_thread.set_abort_handle(tokitest_handle.abort_handle());
+                tokitest_handle
             }
 
-            #[cfg(not(feature = "tokitest"))]
+            #[cfg(not(tokitest))]
             {
                 tokio::spawn(async move {
                     #body.await
@@ -416,24 +575,22 @@ pub fn spawn_join_set(item: TokenStream) -> TokenStream {
         _comma2,
         body,
     } = parse_macro_input!(item as SpawnJoinSetInput);
+    let task = testable_task(&body);
 
     let expanded = quote! {
 
-        #[cfg(feature = "tokitest")]
+        #[cfg(tokitest)]
         {
             {
             // let tcNew = tokitest_thread_controller.nest(#label_expr).await;
             let tcNew = tokitest_thread_controller.nest().with_id(#label_expr).build().await;
-            #joinset_var.spawn(async move {
-                tcNew.label("INIT").await;
-                let tokitest_thread_controller = tcNew.clone();
-                let result = { #body }.await;
-                tcNew.label("END").await;
-                result
-            })
+            let tokitest_new_thread = tcNew.clone();
+            let tokitest_abort_handle = #joinset_var.spawn(#task);
+            tokitest_new_thread.set_abort_handle(tokitest_abort_handle.clone());
+            tokitest_abort_handle
             }
         }
-        #[cfg(not(feature = "tokitest"))]
+        #[cfg(not(tokitest))]
         {
             {
                 #joinset_var.spawn(async move {
@@ -445,12 +602,122 @@ pub fn spawn_join_set(item: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// Crash a node: abort the tasks of the thread with this ID and every thread nested under it.
+///
+/// Crashed threads are dead, [`run_to!`] on them panics until they are [`restart!`]ed,
+/// including a `run_to!` already waiting for the thread in another task.
+///
+/// ## Usage
+/// ```rust,ignore
+/// run_to!("node1", "before fsync").await;
+/// crash!("node1").await;
+/// ```
+#[proc_macro]
+pub fn crash(input: TokenStream) -> TokenStream {
+    let thread_id = parse_macro_input!(input as LitStr);
+
+    let expanded = quote! {
+        tokitest_main_controller.crash(#thread_id)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Resume a thread parked at a label without waiting for its next label.
+/// A free running thread, such as a proxy, runs freely again until another [`run_to!`] targets it.
+///
+/// ## Usage
+/// ```rust,ignore
+/// run_to!("proxy-node2", "accept").await;
+/// cut!("node1" -> "node2").await;
+/// resume!("proxy-node2").await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// tokitest_main_controller.resume("proxy-node2").await;
+/// ```
+#[proc_macro]
+pub fn resume(input: TokenStream) -> TokenStream {
+    let thread_id = parse_macro_input!(input as LitStr);
+
+    let expanded = quote! {
+        tokitest_main_controller.resume(#thread_id)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Restart a node that [`crash!`]ed, spawning a new incarnation of the thread with the same ID.
+/// [`run_to!`] and other macros target the new incarnation.
+///
+/// Used like [`spawn!`] in the test body, the ID may be nested such as `"node1.worker"`.
+///
+/// ## Usage
+/// ```rust,ignore
+/// crash!("node1").await;
+/// restart!("node1", async {
+///     call!(recover(storage)).await;
+/// });
+/// run_to!("node1", "recovered").await;
+/// ```
+#[proc_macro]
+pub fn restart(input: TokenStream) -> TokenStream {
+    struct RestartInput {
+        thread_id: LitStr,
+        _comma: Token![,],
+        body: Expr,
+    }
+
+    impl Parse for RestartInput {
+        fn parse(input: ParseStream) -> syn::Result<Self> {
+            Ok(RestartInput {
+                thread_id: input.parse()?,
+                _comma: input.parse()?,
+                body: input.parse()?,
+            })
+        }
+    }
+
+    let RestartInput { thread_id, body, .. } = parse_macro_input!(input as RestartInput);
+    let task = testable_task(&body);
+
+    let expanded = quote! {
+        {
+            #[cfg(tokitest)]
+            {
+                let tcNew = tokitest_main_controller.restart(#thread_id).await;
+                let tokitest_new_thread = tcNew.clone();
+                let tokitest_handle = tokio::spawn(#task);
+                tokitest_new_thread.set_abort_handle(tokitest_handle.abort_handle());
+                tokitest_handle
+            }
+
+            #[cfg(not(tokitest))]
+            {
+                tokio::spawn(async move {
+                    #body.await
+                })
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
 
 /// Mark a function call as a Network Call, causing it to return an error IF this thread or its parent is Isolated.
+///
+/// Declare the destination with `to = "node"` to also fail when the link to that node is [`cut!`] or [`partition!`]ed.
+/// The destination can be any thread ID, and is reachable if the thread is not isolated.
+/// Calls that succeed first wait out any [`latency!`] set on the nodes or the link.
+/// A call can also be lost at random with [`loss!`], which runs the error callback, or delivered twice with [`duplication!`].
+/// Delivering twice evaluates and awaits the call expression a second time, the caller gets the second result,
+/// so only calls marked `duplicate = true` are duplicated. Every decision is recorded in the trace.
+/// Calls on a link held with [`hold!`] wait for the test to [`release!`] or [`drop_message!`] them.
 /// 
 /// ## Usage
 /// 
-/// ```rust
+/// ```rust,ignore
 /// 
 /// async fn mock_http_error_handler() -> Result<String, String> {
 ///     Err("Network is dead".to_string())
@@ -462,10 +729,18 @@ pub fn spawn_join_set(item: TokenStream) -> TokenStream {
 /// 
 /// isolate!("thread0").await;
 /// complete!("thread0").await;     // network call will fail, and result will be Err("Network is dead") 
+///
+/// spawn!("node1", async {
+///     let result = network_call!(to = "node2", rpc("node2"), mock_http_error_handler()).await;
+///     // rpc("node2") may run twice if duplication!("node1", ...) is set
+///     let result = network_call!(to = "node2", duplicate = true, rpc("node2"), mock_http_error_handler()).await;
+/// });
 /// ```
 #[proc_macro]
 pub fn network_call(input: TokenStream) -> TokenStream {
     struct NetworkCallInput {
+        to: Option<Expr>,
+        duplicate: bool,
         network_call: Expr,
         _comma: Token![,],
         error_callback: Expr,
@@ -473,7 +748,24 @@ pub fn network_call(input: TokenStream) -> TokenStream {
 
     impl Parse for NetworkCallInput {
         fn parse(input: ParseStream) -> syn::Result<Self> {
+            // Optional `to = "node",` and `duplicate = true,` before the call
+            let mut to = None;
+            let mut duplicate = false;
+            while input.peek(Ident) && input.peek2(Token![=]) {
+                let key: Ident = input.parse()?;
+                input.parse::<Token![=]>()?;
+                if key == "to" {
+                    to = Some(input.parse()?);
+                } else if key == "duplicate" {
+                    duplicate = input.parse::<syn::LitBool>()?.value;
+                } else {
+                    return Err(Error::new_spanned(key, "expected `to = \"node\"` or `duplicate = true`"));
+                }
+                input.parse::<Token![,]>()?;
+            }
             Ok(NetworkCallInput {
+                to,
+                duplicate,
                 network_call: input.parse()?,
                 _comma: input.parse()?,
                 error_callback: input.parse()?,
@@ -481,21 +773,46 @@ pub fn network_call(input: TokenStream) -> TokenStream {
         }
     }
 
-    let NetworkCallInput { network_call, _comma, error_callback } = parse_macro_input!(input as NetworkCallInput);
+    let NetworkCallInput { to, duplicate, network_call, _comma, error_callback } = parse_macro_input!(input as NetworkCallInput);
+
+    let to = match to {
+        Some(to) => quote! { Some(#to) },
+        None => quote! { None },
+    };
+    // The call can only be delivered twice if evaluating it twice compiles, so duplication is opt-in
+    let delivered = if duplicate {
+        quote! {
+            ::tokitest::macros::NetworkFate::Delivered { delay: tokitest_delay } => {
+                ::tokitest::macros::Either::Right(::tokitest::macros::delayed(tokitest_delay, #network_call, None))
+            },
+            ::tokitest::macros::NetworkFate::Duplicated { delay: tokitest_delay } => {
+                ::tokitest::macros::Either::Right(::tokitest::macros::delayed(tokitest_delay, #network_call, Some(#network_call)))
+            },
+        }
+    } else {
+        quote! {
+            ::tokitest::macros::NetworkFate::Delivered { delay: tokitest_delay }
+            | ::tokitest::macros::NetworkFate::Duplicated { delay: tokitest_delay } => {
+                ::tokitest::macros::Either::Right(::tokitest::macros::delayed(tokitest_delay, #network_call, None))
+            },
+        }
+    };
 
     let expanded = quote! {
         {
-            #[cfg(feature = "tokitest")]
+            #[cfg(tokitest)]
             {
-                if tokitest_thread_controller.is_isolated().await {
+                match tokitest_thread_controller.network_fate(#to, #duplicate).await {
+                    #delivered
                     // Box::pin(#error_callback) as std::pin::Pin<Box<dyn std::future::Future<Output = _> + Send>>
-                    ::futures::future::Either::Left(#error_callback)
-                } else {
-                    // Box::pin(#network_call) as std::pin::Pin<Box<dyn std::future::Future<Output = _> + Send>>
-                    ::futures::future::Either::Right(#network_call)
+                    ::tokitest::macros::NetworkFate::Unreachable
+                    | ::tokitest::macros::NetworkFate::Lost
+                    | ::tokitest::macros::NetworkFate::Dropped => {
+                        ::tokitest::macros::Either::Left(#error_callback)
+                    },
                 }
             }
-            #[cfg(not(feature = "tokitest"))]
+            #[cfg(not(tokitest))]
             {
                 #network_call
             }
@@ -508,12 +825,12 @@ pub fn network_call(input: TokenStream) -> TokenStream {
 /// Isolate a thread to cause [`network_call!`] of it and its children to fail.
 /// 
 /// ## Usage
-/// ```rust
+/// ```rust,ignore
 /// isolate!("thread0").await;
 /// // Network calls in thread0 will fail
 /// heal!("thread0").await;
 /// // Network calls in thread0 will succeed
-/// ``
+/// ```
 #[proc_macro]
 pub fn isolate(input: TokenStream) -> TokenStream {
     let thread_id = syn::parse_macro_input!(input as syn::LitStr);
@@ -525,17 +842,48 @@ pub fn isolate(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// A one-way link between two nodes, `"node1" -> "node2"`
+struct Link {
+    from: LitStr,
+    _arrow: Token![->],
+    to: LitStr,
+}
+
+impl Parse for Link {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Link {
+            from: input.parse()?,
+            _arrow: input.parse()?,
+            to: input.parse()?,
+        })
+    }
+}
+
 /// Heal the network of a thread to cause [`network_call!`] of it and its children to succeed.
+///
+/// Healing a thread also heals every link [`cut!`] to or from it, and takes it out of the [`partition!`], so it reaches
+/// every group again. A single cut link can be healed with `heal!("node1" -> "node2")`, and `heal!()` heals the whole network.
 /// 
 /// ## Usage
-/// ```rust
+/// ```rust,ignore
 /// isolate!("thread0").await;
 /// // Network calls in thread0 will fail
 /// heal!("thread0").await;
 /// // Network calls in thread0 will succeed
-/// ``
+/// ```
 #[proc_macro]
 pub fn heal(input: TokenStream) -> TokenStream {
+    if input.is_empty() {
+        return TokenStream::from(quote! {
+            tokitest_main_controller.heal_all()
+        });
+    }
+    if let Ok(Link { from, to, .. }) = syn::parse::<Link>(input.clone()) {
+        return TokenStream::from(quote! {
+            tokitest_main_controller.heal_link(#from, #to)
+        });
+    }
+
     let thread_id = syn::parse_macro_input!(input as syn::LitStr);
 
     let expanded = quote! {
@@ -545,6 +893,709 @@ pub fn heal(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// Cut the link from one node to another in one direction: [`network_call!`]s from the first node to the second fail,
+/// while calls the other way still succeed.
+///
+/// ## Usage
+/// ```rust,ignore
+/// cut!("node1" -> "node2").await;
+/// // network_call!(to = "node2", ...) in node1 will fail
+/// // network_call!(to = "node1", ...) in node2 will succeed
+/// heal!("node1" -> "node2").await;
+/// ```
+#[proc_macro]
+pub fn cut(input: TokenStream) -> TokenStream {
+    let Link { from, to, .. } = syn::parse_macro_input!(input as Link);
+
+    let expanded = quote! {
+        tokitest_main_controller.cut(#from, #to)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Partition the network into groups of nodes.
+/// [`network_call!`]s between nodes in different groups fail in both directions, nodes in the same group can still reach each other.
+/// A new partition replaces the previous one, nodes in no group reach every node.
+///
+/// ## Usage
+/// ```rust,ignore
+/// // Split brain: node1 and node2 reach each other, but neither reaches node3
+/// partition!(["node1", "node2"], ["node3"]).await;
+/// // network_call!(to = "node2", ...) in node1 will succeed
+/// // network_call!(to = "node3", ...) in node1 will fail
+/// heal!().await;
+/// ```
+#[proc_macro]
+pub fn partition(input: TokenStream) -> TokenStream {
+    let groups = parse_macro_input!(input with Punctuated::<syn::ExprArray, Token![,]>::parse_terminated);
+    let groups = groups.iter();
+
+    let expanded = quote! {
+        tokitest_main_controller.partition(&[#(&#groups[..]),*])
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Delay [`network_call!`]s made by or sent to a node, or along one link.
+///
+/// Takes a fixed delay and optionally a jitter: each call waits the fixed delay plus a random delay of up to the jitter,
+/// drawn from the test seed. A link latency replaces the latency of both nodes, otherwise the latencies of caller
+/// and destination are added. Run the test with `start_paused = true` so the delays take no real time.
+/// A zero latency removes it.
+///
+/// ## Usage
+/// ```rust,ignore
+/// latency!("node1", Duration::from_millis(50)).await;
+/// latency!("node1" -> "node2", Duration::from_millis(100), Duration::from_millis(20)).await;
+/// // network_call!(to = "node2", ...) in node1 waits 100 to 120ms
+/// // network_call!(to = "node3", ...) in node1 waits 50ms
+/// ```
+#[proc_macro]
+pub fn latency(input: TokenStream) -> TokenStream {
+    struct LatencyInput {
+        target: NetworkTarget,
+        fixed: Expr,
+        jitter: Option<Expr>,
+    }
+
+    impl Parse for LatencyInput {
+        fn parse(input: ParseStream) -> syn::Result<Self> {
+            let target = input.parse()?;
+            input.parse::<Token![,]>()?;
+            let fixed = input.parse()?;
+            let jitter = if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+                Some(input.parse()?)
+            } else {
+                None
+            };
+            Ok(LatencyInput { target, fixed, jitter })
+        }
+    }
+
+    let LatencyInput { target, fixed, jitter } = parse_macro_input!(input as LatencyInput);
+    let jitter = jitter.map_or_else(|| quote! { ::std::time::Duration::ZERO }, |jitter| quote! { #jitter });
+
+    let expanded = match target {
+        NetworkTarget::Node(id) => quote! {
+            tokitest_main_controller.latency(#id, #fixed, #jitter)
+        },
+        NetworkTarget::Link(Link { from, to, .. }) => quote! {
+            tokitest_main_controller.link_latency(#from, #to, #fixed, #jitter)
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Advance the controller's virtual clock, firing every timer that expires on the way.
+///
+/// The first use pauses tokio's clock, which requires the `current_thread` runtime flavor.
+/// Once paused, the runtime also advances the clock by itself whenever every task is waiting on a timer,
+/// so the `timeout` option of `#[tokitest::test]` counts virtual time. Every advance is recorded in the trace.
+///
+/// ## Usage
+/// ```rust,ignore
+/// run_to!("node1", "lease acquired").await;
+/// advance_time!(Duration::from_secs(10)).await;
+/// run_to!("node1", "lease expired").await;
+/// ```
+#[proc_macro]
+pub fn advance_time(input: TokenStream) -> TokenStream {
+    let duration = parse_macro_input!(input as Expr);
+
+    let expanded = quote! {
+        tokitest_main_controller.advance_time(#duration)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Hold [`network_call!`]s and `SimNetwork` messages sent along a link, until the test releases or drops them.
+///
+/// A held `network_call!` parks its thread at the label `held to {node}`, and waits there for the decision.
+/// A held `SimNetwork` message does not block its sender. List held messages with [`pending_messages!`],
+/// then deliver them with [`release!`] or drop them with [`drop_message!`]: releasing them out of order reorders them.
+///
+/// ## Usage
+/// ```rust,ignore
+/// hold!("node1" -> "node2").await;
+/// run_to!("node1", "held to node2").await;
+/// let pending = pending_messages!("node1" -> "node2").await;
+/// release!(pending[0]).await;
+/// complete!("node1").await;
+/// ```
+#[proc_macro]
+pub fn hold(input: TokenStream) -> TokenStream {
+    let Link { from, to, .. } = parse_macro_input!(input as Link);
+
+    let expanded = quote! {
+        tokitest_main_controller.hold(#from, #to)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// List the messages [`hold!`]ing on a link, in the order they were held. Returns a `Vec<PendingMessage>`.
+///
+/// ## Usage
+/// ```rust,ignore
+/// let pending = pending_messages!("node1" -> "node2").await;
+/// ```
+#[proc_macro]
+pub fn pending_messages(input: TokenStream) -> TokenStream {
+    let Link { from, to, .. } = parse_macro_input!(input as Link);
+
+    let expanded = quote! {
+        tokitest_main_controller.pending_messages(#from, #to)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Deliver a `PendingMessage` returned by [`pending_messages!`]. Returns false if it was already released or dropped.
+///
+/// With `next`, a `SimNetwork` message is delivered ahead of the messages already waiting at its destination,
+/// so it is received next.
+///
+/// ## Usage
+/// ```rust,ignore
+/// release!(pending[1]).await;
+/// release!(pending[2], next).await;
+/// ```
+#[proc_macro]
+pub fn release(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input with Punctuated::<Expr, Token![,]>::parse_terminated);
+    let args: Vec<&Expr> = args.iter().collect();
+
+    let expanded = match args[..] {
+        [message] => quote! {
+            tokitest_main_controller.release_message((#message).id)
+        },
+        [message, Expr::Path(next)] if next.path.is_ident("next") => quote! {
+            tokitest_main_controller.release_message_next((#message).id)
+        },
+        _ => {
+            return Error::new(proc_macro2::Span::call_site(), "expected `release!(message)` or `release!(message, next)`")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Drop a `PendingMessage` returned by [`pending_messages!`]: a [`network_call!`] runs its error callback,
+/// a `SimNetwork` message is never received. Returns false if it was already released or dropped.
+///
+/// ## Usage
+/// ```rust,ignore
+/// drop_message!(pending[0]).await;
+/// ```
+#[proc_macro]
+pub fn drop_message(input: TokenStream) -> TokenStream {
+    let message = parse_macro_input!(input as Expr);
+
+    let expanded = quote! {
+        tokitest_main_controller.drop_message((#message).id)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Record an operation of the current thread in a `tokitest::history::History`, around awaiting a call.
+///
+/// The invocation is recorded when the returned future is first polled, and the return when the call completes.
+/// By default the output of the call is the result of the operation, a closure taking a reference to the output
+/// can map it to the result instead. Returns the output of the call. Recording works with and without the `tokitest` cfg flag.
+///
+/// ## Usage
+/// ```rust,ignore
+/// let value = record_op!(history, KvOp::Get(1), kv.get(1)).await;
+/// record_op!(history, KvOp::Put(1, 2), kv.put(1, 2), |_| None).await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// record_op!(history, KvOp::Put(1, 2), kv.put(1, 2), |_| None)
+/// // Expands to
+/// history.record_with(KvOp::Put(1, 2), kv.put(1, 2), |_| None)
+/// ```
+#[proc_macro]
+pub fn record_op(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input with Punctuated::<Expr, Token![,]>::parse_terminated);
+    let args: Vec<&Expr> = args.iter().collect();
+
+    let expanded = match args[..] {
+        [history, op, call] => quote! {
+            (#history).record(#op, #call)
+        },
+        [history, op, call, result] => quote! {
+            (#history).record_with(#op, #call, #result)
+        },
+        _ => {
+            return Error::new(proc_macro2::Span::call_site(), "expected `record_op!(history, op, call)` or `record_op!(history, op, call, |output| result)`")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Register an invariant, a condition checked every time any thread reaches a label.
+///
+/// The first time it does not hold, the test fails with the thread and label that broke it and the recent trace,
+/// see `tokitest::invariant`. The invariant is named after its condition, unless a name is given first.
+/// The closure must be `'static`, so move clones of the state it reads into it.
+///
+/// ## Usage
+/// ```rust,ignore
+/// let queue = Arc::new(std::sync::Mutex::new(Vec::new()));
+/// let qc = queue.clone();
+/// invariant!(move || qc.lock().unwrap().len() <= 10).await;
+/// invariant!("balance is never negative", move || balance.get() >= 0).await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// invariant!(move || qc.lock().unwrap().len() <= 10)
+/// // Expands to
+/// tokitest_main_controller.invariant("qc.lock().unwrap().len() <= 10", move || qc.lock().unwrap().len() <= 10)
+/// ```
+#[proc_macro]
+pub fn invariant(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input with Punctuated::<Expr, Token![,]>::parse_terminated);
+    let args: Vec<&Expr> = args.iter().collect();
+
+    let (name, holds) = match args[..] {
+        [Expr::Closure(closure)] => {
+            let condition = &closure.body;
+            (quote! { stringify!(#condition) }, closure)
+        },
+        [Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(name), .. }), Expr::Closure(closure)] => (quote! { #name }, closure),
+        _ => {
+            return Error::new(proc_macro2::Span::call_site(), "expected `invariant!(|| condition)` or `invariant!(\"name\", || condition)`")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    let expanded = quote! {
+        tokitest_main_controller.invariant(#name, #holds)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// A proposition of [`assert_trace!`], expanded to a `tokitest::temporal::Prop`:
+/// `"thread" at "label"`, `not(prop)`, `before("first", "then")` or `(prop)`, joined with `while`
+struct TemporalProp(TokenStream2);
+
+impl Parse for TemporalProp {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut prop = parse_temporal_atom(input)?;
+        while input.peek(Token![while]) {
+            input.parse::<Token![while]>()?;
+            let other = parse_temporal_atom(input)?;
+            prop = quote! { #prop.and(#other) };
+        }
+        Ok(TemporalProp(prop))
+    }
+}
+
+fn parse_temporal_atom(input: ParseStream) -> syn::Result<TokenStream2> {
+    if input.peek(LitStr) {
+        let thread: LitStr = input.parse()?;
+        let at: Ident = input.parse()?;
+        if at != "at" {
+            return Err(Error::new_spanned(at, "expected `at`"));
+        }
+        let label: LitStr = input.parse()?;
+        return Ok(quote! { ::tokitest::temporal::at(#thread, #label) });
+    }
+
+    let content;
+    if input.peek(syn::token::Paren) {
+        syn::parenthesized!(content in input);
+        return Ok(content.parse::<TemporalProp>()?.0);
+    }
+    let function: Ident = input.parse()?;
+    syn::parenthesized!(content in input);
+    match function.to_string().as_str() {
+        "not" => {
+            let TemporalProp(prop) = content.parse()?;
+            Ok(quote! { ::tokitest::temporal::not(#prop) })
+        },
+        "before" => {
+            let first: LitStr = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let then: LitStr = content.parse()?;
+            Ok(quote! { ::tokitest::temporal::before(#first, #then) })
+        },
+        _ => Err(Error::new_spanned(function, "expected `\"thread\" at \"label\"`, `not(...)` or `before(\"first\", \"then\")`")),
+    }
+}
+
+/// `always(prop)`, `never(prop)` or `eventually(prop)`, where `"thread", "label"` is short for `"thread" at "label"`
+struct TemporalFormula(TokenStream2);
+
+impl Parse for TemporalFormula {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let function: Ident = input.parse()?;
+        let content;
+        syn::parenthesized!(content in input);
+        let prop = if content.peek(LitStr) && content.peek2(Token![,]) {
+            let thread: LitStr = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let label: LitStr = content.parse()?;
+            quote! { ::tokitest::temporal::at(#thread, #label) }
+        } else {
+            content.parse::<TemporalProp>()?.0
+        };
+        if !content.is_empty() {
+            return Err(content.error("unexpected tokens after the proposition"));
+        }
+
+        match function.to_string().as_str() {
+            "always" | "never" | "eventually" => Ok(TemporalFormula(quote! { ::tokitest::temporal::#function(#prop) })),
+            _ => Err(Error::new_spanned(function, "expected `always`, `never` or `eventually`")),
+        }
+    }
+}
+
+/// Assert a temporal property of the labels the threads reached so far, see `tokitest::temporal`.
+///
+/// `always` and `never` check every state of the trace, where each thread is at the last label it reached,
+/// `eventually` checks that at least one state matches. Fails the test with the events leading up to the violation.
+///
+/// ## Usage
+/// ```rust,ignore
+/// complete!("node1").await;
+/// complete!("node2").await;
+/// assert_trace!(never("node1" at "commit" while "node2" at "commit")).await;
+/// assert_trace!(always(before("lock acquired:log", "write"))).await;
+/// assert_trace!(eventually("node2", "done")).await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// assert_trace!(never("node1" at "commit" while "node2" at "commit"))
+/// // Expands to
+/// tokitest_main_controller.assert_trace(::tokitest::temporal::never(
+///     ::tokitest::temporal::at("node1", "commit").and(::tokitest::temporal::at("node2", "commit"))
+/// ))
+/// ```
+#[proc_macro]
+pub fn assert_trace(input: TokenStream) -> TokenStream {
+    let TemporalFormula(formula) = parse_macro_input!(input as TemporalFormula);
+
+    let expanded = quote! {
+        tokitest_main_controller.assert_trace(#formula)
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Assert that a thread is blocked: it waits without reaching a label, such as on a lock another thread holds.
+///
+/// A thread waiting on a `tokitest::sync` primitive is blocked on it, as `lock:{name}`, `recv:{name}` or `send:{name}`,
+/// which can be given to check what it waits on. A thread waiting on anything else counts as blocked
+/// once it reaches no label for a short quiescence window, 50ms unless the test sets `quiescence = ms` in [`test`],
+/// and `tokitest::controller::ThreadState` reports it as blocked until it reaches a label.
+///
+/// ## Usage
+/// ```rust,ignore
+/// run_to!("thread1", "lock acquired:accounts").await;
+/// run_to!("thread2", "lock requested:accounts").await;
+/// resume!("thread2").await;
+/// assert_blocked!("thread2", "lock:accounts").await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// assert_blocked!("thread2", "lock:accounts")
+/// // Expands to
+/// tokitest_main_controller.assert_blocked("thread2", Some("lock:accounts"))
+/// ```
+#[proc_macro]
+pub fn assert_blocked(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input with Punctuated::<Expr, Token![,]>::parse_terminated);
+    let args: Vec<&Expr> = args.iter().collect();
+
+    let expanded = match args[..] {
+        [thread_id] => quote! {
+            tokitest_main_controller.assert_blocked(#thread_id, None)
+        },
+        [thread_id, on] => quote! {
+            tokitest_main_controller.assert_blocked(#thread_id, Some(#on))
+        },
+        _ => {
+            return Error::new(proc_macro2::Span::call_site(), "expected `assert_blocked!(\"thread\")` or `assert_blocked!(\"thread\", \"lock:name\")`")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Assert that a thread cannot reach a label on its own: the thread runs past its labels while every other thread
+/// stays parked where it is, until it blocks or returns, and the test fails if it reaches the label on the way.
+///
+/// Like [`run_to!`], the label is a string or any `LabelTrait`. The thread must settle where the test can tell
+/// it will not go on: blocked on a `tokitest::sync` primitive, finished or crashed. A thread that is still running
+/// without reaching a label after the quiescence window, see [`assert_blocked!`], may reach the label later,
+/// so the test fails as undetermined. Reaching the label leaves the thread parked there.
+///
+/// ## Usage
+/// ```rust,ignore
+/// run_to!("thread1", "critical section").await;
+/// assert_cannot_reach!("thread2", "critical section").await;
+/// complete!("thread1").await;
+/// run_to!("thread2", "critical section").await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// assert_cannot_reach!("thread2", "critical section")
+/// // Expands to
+/// tokitest_main_controller.assert_cannot_reach("thread2", ::tokitest::StringLabel::new("critical section"))
+/// ```
+#[proc_macro]
+pub fn assert_cannot_reach(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input with Punctuated::<Expr, Token![,]>::parse_terminated);
+    let args: Vec<&Expr> = args.iter().collect();
+
+    let expanded = match args[..] {
+        [thread_id, label @ Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(_), .. })] => quote! {
+            tokitest_main_controller.assert_cannot_reach(#thread_id, ::tokitest::StringLabel::new(#label))
+        },
+        [thread_id, label] => quote! {
+            tokitest_main_controller.assert_cannot_reach(#thread_id, #label)
+        },
+        _ => {
+            return Error::new(proc_macro2::Span::call_site(), "expected `assert_cannot_reach!(\"thread\", \"label\")`")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Assert that a thread is parked at a label, given by name or qualified name.
+/// A running thread is given a short quiescence window to reach the label.
+///
+/// ## Usage
+/// ```rust,ignore
+/// resume!("thread1").await;
+/// assert_parked_at!("thread1", "label 1").await;
+/// ```
+///
+/// ## Expansion
+/// ```rust,ignore
+/// assert_parked_at!("thread1", "label 1")
+/// // Expands to
+/// tokitest_main_controller.assert_parked_at("thread1", "label 1")
+/// ```
+#[proc_macro]
+pub fn assert_parked_at(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input with Punctuated::<Expr, Token![,]>::parse_terminated);
+    let args: Vec<&Expr> = args.iter().collect();
+
+    let expanded = match args[..] {
+        [thread_id, label] => quote! {
+            tokitest_main_controller.assert_parked_at(#thread_id, #label)
+        },
+        _ => {
+            return Error::new(proc_macro2::Span::call_site(), "expected `assert_parked_at!(\"thread\", \"label\")`")
+                .to_compile_error()
+                .into();
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// A node, `"node1"`, or a one-way link, `"node1" -> "node2"`, to set a network condition on
+enum NetworkTarget {
+    Node(LitStr),
+    Link(Link),
+}
+
+impl Parse for NetworkTarget {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitStr) && input.peek2(Token![->]) {
+            Ok(NetworkTarget::Link(input.parse()?))
+        } else {
+            Ok(NetworkTarget::Node(input.parse()?))
+        }
+    }
+}
+
+/// A network condition and its probability, `"node1", 0.1` or `"node1" -> "node2", 0.1`
+struct ProbabilityInput {
+    target: NetworkTarget,
+    _comma: Token![,],
+    probability: Expr,
+}
+
+impl Parse for ProbabilityInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(ProbabilityInput {
+            target: input.parse()?,
+            _comma: input.parse()?,
+            probability: input.parse()?,
+        })
+    }
+}
+
+/// Lose [`network_call!`]s made by or sent to a node, or along one link, with a probability between 0 and 1.
+///
+/// A lost call runs the error callback, like a call to an isolated node. Whether a call is lost is drawn from
+/// the test seed and recorded in the trace. A link probability replaces the probability of both nodes,
+/// otherwise the caller and destination each roll on their own. A zero probability removes it.
+///
+/// ## Usage
+/// ```rust,ignore
+/// loss!("node1", 0.1).await;
+/// loss!("node1" -> "node2", 0.5).await;
+/// ```
+#[proc_macro]
+pub fn loss(input: TokenStream) -> TokenStream {
+    let ProbabilityInput { target, probability, .. } = parse_macro_input!(input as ProbabilityInput);
+
+    let expanded = match target {
+        NetworkTarget::Node(id) => quote! {
+            tokitest_main_controller.loss(#id, #probability)
+        },
+        NetworkTarget::Link(Link { from, to, .. }) => quote! {
+            tokitest_main_controller.link_loss(#from, #to, #probability)
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Deliver [`network_call!`]s made by or sent to a node, or along one link, twice with a probability between 0 and 1.
+///
+/// Models a retry after a lost reply: the call runs twice and the caller gets the second result.
+/// Only calls marked `network_call!(duplicate = true, ...)` are duplicated, as the call expression is evaluated again.
+/// Whether a call is duplicated is drawn from the test seed and recorded in the trace. A zero probability removes it.
+///
+/// ## Usage
+/// ```rust,ignore
+/// duplication!("node1" -> "node2", 0.5).await;
+/// ```
+#[proc_macro]
+pub fn duplication(input: TokenStream) -> TokenStream {
+    let ProbabilityInput { target, probability, .. } = parse_macro_input!(input as ProbabilityInput);
+
+    let expanded = match target {
+        NetworkTarget::Node(id) => quote! {
+            tokitest_main_controller.duplication(#id, #probability)
+        },
+        NetworkTarget::Link(Link { from, to, .. }) => quote! {
+            tokitest_main_controller.link_duplication(#from, #to, #probability)
+        },
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// A network change written like the macro that makes it, `isolate("node1")` or `cut("node1" -> "node2")`,
+/// parsed into an expression building a `NetworkFault`
+struct FaultAction(TokenStream2);
+
+impl Parse for FaultAction {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let action: Ident = input.parse()?;
+        let content;
+        syn::parenthesized!(content in input);
+        let fault = quote! { ::tokitest::controller::NetworkFault };
+
+        let expanded = if action == "isolate" {
+            let id: LitStr = content.parse()?;
+            quote! { #fault::Isolate(#id.to_string()) }
+        } else if action == "heal" && content.is_empty() {
+            quote! { #fault::HealAll }
+        } else if action == "heal" && content.peek2(Token![->]) {
+            let Link { from, to, .. } = content.parse()?;
+            quote! { #fault::HealLink(#from.to_string(), #to.to_string()) }
+        } else if action == "heal" {
+            let id: LitStr = content.parse()?;
+            quote! { #fault::Heal(#id.to_string()) }
+        } else if action == "cut" {
+            let Link { from, to, .. } = content.parse()?;
+            quote! { #fault::Cut(#from.to_string(), #to.to_string()) }
+        } else if action == "partition" {
+            let groups = Punctuated::<syn::ExprArray, Token![,]>::parse_terminated(&content)?;
+            let groups = groups.iter().map(|group| {
+                let ids = group.elems.iter();
+                quote! { vec![#(#ids.to_string()),*] }
+            });
+            quote! { #fault::Partition(vec![#(#groups),*]) }
+        } else {
+            return Err(Error::new_spanned(action, "expected `isolate`, `heal`, `cut` or `partition`"));
+        };
+        if !content.is_empty() {
+            return Err(content.error("unexpected tokens in fault"));
+        }
+        Ok(FaultAction(expanded))
+    }
+}
+
+/// Schedule a network fault for when a thread reaches a label.
+///
+/// The fault is written like the macro that applies it immediately: `isolate("node1")`, `heal("node1")`,
+/// `heal("node1" -> "node2")`, `heal()`, `cut("node1" -> "node2")` or `partition(["node1"], ["node2", "node3"])`.
+/// It is applied atomically as the thread reaches the label, before the thread parks there,
+/// so the test does not have to stop the thread to change the network. Each scheduled fault is applied once.
+///
+/// Like [`run_to!`], the label can be a string or an object with LabelTrait.
+///
+/// ## Usage
+/// ```rust,ignore
+/// on_label!("node1", "before commit", isolate("node1")).await;
+/// on_label!("node2", RepeatedLabel::new(StringLabel::new("append"), 3), partition(["node1", "node2"], ["node3"])).await;
+/// complete!("node1").await;   // node1 is isolated from "before commit" onwards
+/// ```
+#[proc_macro]
+pub fn on_label(input: TokenStream) -> TokenStream {
+    struct OnLabelInput {
+        thread_id: LitStr,
+        label: Expr,
+        fault: FaultAction,
+    }
+
+    impl Parse for OnLabelInput {
+        fn parse(input: ParseStream) -> syn::Result<Self> {
+            let thread_id = input.parse()?;
+            input.parse::<Token![,]>()?;
+            let label = input.parse()?;
+            input.parse::<Token![,]>()?;
+            let fault = input.parse()?;
+            input.parse::<Option<Token![,]>>()?;
+            Ok(OnLabelInput { thread_id, label, fault })
+        }
+    }
+
+    let OnLabelInput { thread_id, label, fault: FaultAction(fault) } = parse_macro_input!(input as OnLabelInput);
+
+    let label = match &label {
+        Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(_), .. }) => quote! { ::tokitest::StringLabel::new(#label) },
+        _ => quote! { #label },
+    };
+
+    let expanded = quote! {
+        tokitest_main_controller.on_label(#thread_id, #label, #fault)
+    };
+
+    TokenStream::from(expanded)
+}
+
 struct RunToArgs {
     args: Punctuated<Expr, Token![,]>,
 }
@@ -561,6 +1612,8 @@ impl Parse for RunToArgs {
 /// Unblock a thread until it hits a specified label.
 /// 
 /// Strings or objects with LabelTrait may be used (see RegexLabel, OrLabel, RepeatedLabel)
+///
+/// Strings match either the label's name or its name qualified with the function path, e.g. `"my_mod::worker::start"`
 /// 
 /// Developer's responsibility to avoid Deadlock in test
 /// - Ensure the thread specified exists or will be spawned
@@ -568,21 +1621,23 @@ impl Parse for RunToArgs {
 /// - Ensure the Label specified is reachable
 /// 
 /// ## Usage
-/// ```
+/// ```rust,ignore
 /// // Unblock thread 0, then block it when it reaches label 1
 /// run_to!("thread0", "label 1").await;
 /// 
 /// // Unblock thread 1, then block it after it reaches label 2 5 times.
 /// run_to!("thread1", RepeatedLabel::new(StringLabel::new("label 2", 5))).await;
+///
+/// // Advance the paused clock as far as needed, up to 30 seconds, until thread 2 reaches the label
+/// run_to!("thread2", "lease expired", advance_time = Duration::from_secs(30)).await;
 /// ```
 #[proc_macro]
 pub fn run_to(input: TokenStream) -> TokenStream {
     let RunToArgs { args } = syn::parse_macro_input!(input as RunToArgs);
 
-    // Expect exactly two arguments
-    // TODO: may want to allow non string literal?
-    if args.len() != 2 {
-        return syn::Error::new_spanned(args, "run_to! requires exactly two arguments: a string literal and an expression")
+    // Expect two arguments, and optionally `advance_time = ...`
+    if args.len() != 2 && args.len() != 3 {
+        return syn::Error::new_spanned(args, "run_to! requires two arguments: a string literal and an expression, and optionally `advance_time = ...`")
             .to_compile_error()
             .into();
     }
@@ -590,6 +1645,15 @@ pub fn run_to(input: TokenStream) -> TokenStream {
     let mut args_iter = args.into_iter();
     let thread_id = args_iter.next().unwrap();
     let label = args_iter.next().unwrap();
+    let advance_time = match args_iter.next() {
+        Some(Expr::Assign(assign)) if matches!(&*assign.left, Expr::Path(path) if path.path.is_ident("advance_time")) => Some(assign.right),
+        Some(other) => {
+            return syn::Error::new_spanned(other, "expected `advance_time = Duration`")
+                .to_compile_error()
+                .into();
+        }
+        None => None,
+    };
 
     // First argument must be a LitStr
     let label_lit = match &thread_id {
@@ -605,22 +1669,22 @@ pub fn run_to(input: TokenStream) -> TokenStream {
     };
 
     // Now check whether second argument is also a LitStr
-    let expanded = match &label {
+    let label = match &label {
         syn::Expr::Lit(syn::ExprLit {
             lit: syn::Lit::Str(_),
             ..
-        }) => {
-            // Both are string literals
-            quote! {
-                tokitest_main_controller.run_to(#label_lit, #label)
-            }
-        }
-        _ => {
-            // Second argument is a general expression, assume is a LabelTrait
-            quote! {
-                tokitest_main_controller.run_to_label(#label_lit, #label)
-            }
-        }
+        }) => quote! { ::tokitest::StringLabel::new(#label) },
+        // Second argument is a general expression, assume is a LabelTrait
+        _ => quote! { #label },
+    };
+
+    let expanded = match advance_time {
+        Some(limit) => quote! {
+            tokitest_main_controller.run_to_label_advancing(#label_lit, #label, #limit)
+        },
+        None => quote! {
+            tokitest_main_controller.run_to_label(#label_lit, #label)
+        },
     };
 
     TokenStream::from(expanded)
@@ -630,7 +1694,7 @@ pub fn run_to(input: TokenStream) -> TokenStream {
 /// 
 /// ## Usage
 /// 
-/// ```
+/// ```rust,ignore
 /// complete!("threadid").await;
 /// ```
 #[proc_macro]
@@ -645,32 +1709,143 @@ pub fn complete(input: TokenStream) -> TokenStream {
 }
 
 
+/// Options of [`test`] that tokitest handles itself, every other option is passed to `#[tokio::test]`
+#[derive(Default)]
+struct TestOptions {
+    timeout: Option<syn::LitInt>,
+    seed: Option<syn::LitInt>,
+    schedules: Option<syn::LitInt>,
+    quiescence: Option<syn::LitInt>,
+    trace: Option<LitStr>,
+    tokio_args: Vec<syn::MetaNameValue>,
+}
+
+impl Parse for TestOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        fn int(arg: &syn::MetaNameValue) -> syn::Result<syn::LitInt> {
+            match &arg.value {
+                Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(lit), .. }) => Ok(lit.clone()),
+                other => Err(Error::new_spanned(other, "expected an integer literal")),
+            }
+        }
+
+        let mut options = TestOptions::default();
+        for arg in Punctuated::<syn::MetaNameValue, Token![,]>::parse_terminated(input)? {
+            let key = arg.path.get_ident().map(|ident| ident.to_string()).unwrap_or_default();
+            match key.as_str() {
+                "timeout" => options.timeout = Some(int(&arg)?),
+                "seed" => options.seed = Some(int(&arg)?),
+                "schedules" => options.schedules = Some(int(&arg)?),
+                "quiescence" => options.quiescence = Some(int(&arg)?),
+                "trace" => match &arg.value {
+                    Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(lit), .. }) => options.trace = Some(lit.clone()),
+                    other => return Err(Error::new_spanned(other, "trace must be a path string literal")),
+                },
+                _ => options.tokio_args.push(arg),
+            }
+        }
+        Ok(options)
+    }
+}
+
 /// Mark tests with #[tokitest::test] to use the testing framework.
 /// 
-/// Run tokitests with `cargo test --features tokitest`
+/// Run tokitests with `RUSTFLAGS="--cfg tokitest" cargo test`.
+/// Without the `tokitest` cfg the test is compiled out, since its threads would never be blocked at labels.
+///
+/// ## Options
+/// `#[tokio::test]` options such as `flavor = "multi_thread"`, `worker_threads = 2` and `start_paused = true` are passed through.
+/// Tokitest adds its own options:
+/// - `timeout = 5000`: fail the test if it has not finished after this many milliseconds
+/// - `seed = 42`: seed of the `MainController`, used for any randomness tokitest introduces (default 0)
+/// - `schedules = 10`: run the test body this many times, each run with a fresh `MainController` seeded with
+///   `seed`, `seed + 1`, ... (the test cannot return a value). Tokitest does not choose interleavings itself,
+///   so a run differs from the others only in what is drawn from its seed, such as network loss, duplication and
+///   jitter: threads still interleave only where the test's `run_to!` calls stop them.
+/// - `quiescence = 200`: how many milliseconds [`assert_blocked!`], [`assert_parked_at!`] and [`assert_cannot_reach!`]
+///   give a running thread to reach a label before it counts as blocked (default 50). Raise it for threads that do
+///   slow work between labels, so they are not mistaken for blocked ones.
+/// - `trace = "target/tokitest/my_test.trace"`: write the test's trace to this file when the test ends
+///
+/// ## Usage
+/// ```rust,ignore
+/// #[tokitest::test(flavor = "multi_thread", worker_threads = 2, timeout = 1000, seed = 7, schedules = 5)]
+/// async fn my_test() {
+///     // ...
+/// }
+/// ```
 #[proc_macro_attribute]
-pub fn test(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as TestOptions);
     let mut input_fn = parse_macro_input!(item as ItemFn);
 
     // Extract the original function body
     let original_body = &input_fn.block;
+    let fn_name = input_fn.sig.ident.to_string();
+    let prelude = function_prelude(quote! { concat!(module_path!(), "::", #fn_name) }, original_body, true, &[]);
+
+    let seed = match (&options.seed, &options.schedules) {
+        (Some(seed), Some(_)) => quote! { (#seed as u64).wrapping_add(tokitest_run) },
+        (None, Some(_)) => quote! { tokitest_run },
+        (Some(seed), None) => quote! { #seed },
+        (None, None) => quote! { 0 },
+    };
+    let trace = match &options.trace {
+        Some(path) => quote! { .with_trace_output(#path) },
+        None => quote! {},
+    };
+    let start_paused = options.tokio_args.iter().any(|arg| {
+        arg.path.is_ident("start_paused")
+            && matches!(&arg.value, Expr::Lit(syn::ExprLit { lit: syn::Lit::Bool(paused), .. }) if paused.value)
+    });
+    let paused_clock = if start_paused { quote! { .with_paused_clock() } } else { quote! {} };
+    let quiescence = match &options.quiescence {
+        Some(millis) => quote! { .with_quiescence_window(::std::time::Duration::from_millis(#millis)) },
+        None => quote! {},
+    };
 
-    // Generate new body with tokitest setup + original code
-    let new_body = quote! {
-        {
-            let tokitest_main_controller = std::sync::Arc::new(::tokitest::controller::MainController::new());
-            let tokitest_thread_controller = tokitest_main_controller.nest().build().await;
+    // tokitest setup + original code
+    let mut new_body = quote! {
+        let tokitest_main_controller = std::sync::Arc::new(
+            ::tokitest::controller::MainController::builder().with_seed(#seed)#trace #paused_clock #quiescence.build()
+        );
+        let tokitest_thread_controller = tokitest_main_controller.nest().build().await;
+        #prelude
 
-            #original_body
-        }
+        #original_body
     };
 
+    if let Some(schedules) = &options.schedules {
+        if let syn::ReturnType::Type(_, ty) = &input_fn.sig.output {
+            return Error::new_spanned(ty, "tests run with several schedules cannot return a value")
+                .to_compile_error()
+                .into();
+        }
+        // Each run gets a fresh controller, `return` in the body ends only that run
+        new_body = quote! {
+            for tokitest_run in 0..(#schedules as u64) {
+                async { #new_body }.await;
+            }
+        };
+    }
+
+    if let Some(timeout) = &options.timeout {
+        new_body = quote! {
+            match ::tokio::time::timeout(::std::time::Duration::from_millis(#timeout), async { #new_body }).await {
+                Ok(result) => result,
+                Err(_) => panic!("tokitest timed out after {}ms", #timeout),
+            }
+        };
+    }
+
     // Replace the function body
-    input_fn.block = syn::parse2(new_body).unwrap();
+    input_fn.block = syn::parse2(quote! {{ #new_body }}).unwrap();
 
     // Add tokio::test attribute and return the modified function
+    let tokio_args = &options.tokio_args;
     quote! {
-        #[tokio::test]
+        #[cfg(tokitest)]
+        #[tokio::test(#(#tokio_args),*)]
         #input_fn
     }.into()  // <-- Add this!
-}
\ No newline at end of file
+}
@@ -0,0 +1,49 @@
+//! Testable code in a crate other than tokitest, checked by `tests/plain_test.rs`.
+//!
+//! Without `--cfg tokitest` the functions below take no controller argument,
+//! so calling them directly from plain code only compiles if the macros emitted plain code.
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, call, network_call};
+
+#[tokitest::testable]
+pub async fn push_twice(value: i32, data: Arc<RwLock<Vec<i32>>>) {
+    data.write().await.push(value);
+    label!("between pushes");
+    data.write().await.push(value);
+}
+
+#[tokitest::testable]
+pub async fn fetch(url: &str) -> Result<String, String> {
+    label!("before fetch");
+    network_call!(get(url), unreachable_error()).await
+}
+
+async fn get(url: &str) -> Result<String, String> {
+    Ok(format!("data from {}", url))
+}
+
+/// Only the real call may run without the tokitest cfg
+#[allow(dead_code)] // network_call! drops the error callback without the tokitest cfg
+async fn unreachable_error() -> Result<String, String> {
+    panic!("network_call! evaluated its error callback without the tokitest cfg")
+}
+
+pub struct Counter {
+    data: Arc<RwLock<Vec<i32>>>,
+}
+
+#[tokitest::testable_struct]
+impl Counter {
+    pub fn new(data: Arc<RwLock<Vec<i32>>>) -> Self {
+        Counter { data }
+    }
+
+    pub async fn push_all(&self, values: &[i32]) {
+        for value in values {
+            call!(push_twice(*value, self.data.clone())).await;
+            label!("pushed");
+        }
+    }
+}
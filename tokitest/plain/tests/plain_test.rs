@@ -0,0 +1,130 @@
+#![cfg(not(tokitest))]
+
+//! Without `--cfg tokitest`, no controller exists in these tests.
+//! Any macro that expanded to instrumented code would fail to find `tokitest_thread_controller` and not compile.
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::{JoinHandle, JoinSet};
+use tokitest::{label, spawn, spawn_join_set, call, network_call};
+use tokitest_plain::{push_twice, fetch, Counter};
+
+#[tokio::test]
+async fn test_spawn_is_tokio_spawn() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    let dc = data.clone();
+    let handle: JoinHandle<usize> = spawn!("thread1", async {
+        label!("label 1");
+        call!(push_twice(1, dc.clone())).await;
+        dc.read().await.len()
+    });
+
+    // Threads run without being driven by run_to!
+    assert_eq!(2, handle.await.unwrap());
+    assert_eq!(vec![1, 1], *data.read().await);
+}
+
+#[tokio::test]
+async fn test_spawn_join_set_is_join_set_spawn() {
+    let mut set: JoinSet<i32> = JoinSet::new();
+    for i in 0..3 {
+        spawn_join_set!(&format!("spawned{}", i), set, async {
+            label!("label 1");
+            i
+        });
+    }
+
+    let mut results = set.join_all().await;
+    results.sort();
+    assert_eq!(vec![0, 1, 2], results);
+}
+
+#[tokio::test]
+async fn test_call_and_label_are_plain() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    label!("not a checkpoint");
+    call!(push_twice(7, data.clone())).await;
+    // Testable functions keep their original signature
+    push_twice(8, data.clone()).await;
+
+    let counter = call!(Counter::new(data.clone()));
+    call!(counter.push_all(&[9])).await;
+    counter.push_all(&[10]).await;
+
+    assert_eq!(vec![7, 7, 8, 8, 9, 9, 10, 10], *data.read().await);
+}
+
+#[tokio::test]
+async fn test_network_call_is_the_real_call() {
+    #[allow(dead_code)] // network_call! drops the error callback without the tokitest cfg
+    async fn error() -> Result<String, String> {
+        Err("isolated".to_string())
+    }
+
+    let response = network_call!(fetch("http://api.example.com"), error()).await;
+    assert_eq!(Ok("data from http://api.example.com".to_string()), response);
+
+    assert_eq!(Ok("data from http://other.example.com".to_string()), call!(fetch("http://other.example.com")).await);
+}
+
+#[tokio::test]
+async fn test_sync_types_are_tokio_types() {
+    use tokitest::sync::Named;
+
+    let mutex: tokio::sync::Mutex<i32> = tokitest::sync::Mutex::named("counter", 1);
+    let rwlock: tokio::sync::RwLock<i32> = tokitest::sync::RwLock::new(2);
+    *mutex.lock().await += *rwlock.read().await;
+    assert_eq!(3, mutex.into_inner());
+}
+
+#[tokio::test]
+async fn test_lock_guards_are_tokio_guards() {
+    use std::sync::Arc;
+    use tokitest::sync::{MutexGuard, Named, OwnedRwLockWriteGuard};
+
+    static COUNTER: tokitest::sync::Mutex<i32> = tokitest::sync::Mutex::const_new(1);
+    let owned: tokio::sync::OwnedMutexGuard<i32> = Arc::new(tokitest::sync::Mutex::named("counter", 2)).lock_owned().await;
+    let mapped: tokio::sync::MappedMutexGuard<'_, i32> = MutexGuard::map(COUNTER.lock().await, |counter| counter);
+    let ledger = Arc::new(tokitest::sync::RwLock::new(vec![3]));
+    let read: tokio::sync::OwnedRwLockReadGuard<Vec<i32>> = OwnedRwLockWriteGuard::downgrade(ledger.write_owned().await);
+    assert_eq!(6, *owned + *mapped + read[0]);
+}
+
+#[tokio::test]
+async fn test_channels_are_tokio_channels() {
+    use tokitest::sync::{mpsc, oneshot};
+
+    let (sender, mut receiver): (tokio::sync::mpsc::Sender<i32>, tokio::sync::mpsc::Receiver<i32>) =
+        mpsc::channel_with_payloads("jobs", 4);
+    sender.send(1).await.unwrap();
+    assert_eq!(Some(1), receiver.recv().await);
+
+    let (reply, response): (tokio::sync::oneshot::Sender<i32>, tokio::sync::oneshot::Receiver<i32>) =
+        oneshot::named_channel("reply");
+    reply.send(2).unwrap();
+    assert_eq!(Ok(2), response.await);
+}
+
+#[tokio::test]
+async fn test_shared_is_a_plain_cell() {
+    use tokitest::sync::{Named, Shared};
+
+    let counter = Shared::named("counter", 1);
+    counter.write(|counter| *counter += 1);
+    assert_eq!(2, counter.get());
+    assert_eq!("counter", counter.name());
+    assert_eq!(2, counter.into_inner());
+}
+
+#[tokio::test]
+async fn test_atomics_run_right_away() {
+    use tokitest::sync::atomic::{AtomicUsize, Ordering};
+    use tokitest::sync::Named;
+
+    let counter = AtomicUsize::named("counter", 1);
+    assert_eq!(1, counter.fetch_add(1, Ordering::SeqCst).await);
+    assert_eq!(Ok(2), counter.compare_exchange(2, 5, Ordering::AcqRel, Ordering::Acquire).await);
+    assert_eq!(5, counter.into_inner());
+}
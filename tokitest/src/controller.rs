@@ -1,12 +1,22 @@
-use std::{collections::HashMap, sync::Arc};
-use tokio::{sync::{mpsc::{Sender, Receiver, channel}, RwLock}};
+use std::{collections::HashMap, fmt, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+use tokio::{sync::{mpsc::{Sender, Receiver, channel}, RwLock}, task::AbortHandle};
 
-use crate::label_spec::{LabelTrait, StringLabel};
+use crate::coverage::{self, LabelCoverage};
+use crate::invariant::Invariants;
+use crate::label_spec::{LabelTrait, QualifiedLabel, StringLabel};
+use crate::lock_order::{LockOrder, PotentialDeadlock};
+use crate::race::{Access, AccessKind, DataRace, HappensBefore};
+use crate::network::{Latency, NetworkState};
+pub use crate::network::NetworkFault;
+use crate::rng::Rng;
+use crate::temporal::Formula;
+use crate::trace::{NetworkFate, Trace, TraceEvent};
 
 pub struct ThreadNestBuilder {
     main_controller_data: Arc<RwLock<MainControllerData>>,
     id: Option<String>,
-    parent_id: String
+    parent_id: String,
+    free_running: bool,
 }
 
 impl ThreadNestBuilder {
@@ -14,10 +24,18 @@ impl ThreadNestBuilder {
         Self {
             main_controller_data: data,
             id: None,
-            parent_id: parent_id.to_string()
+            parent_id: parent_id.to_string(),
+            free_running: false,
         }
     }
 
+    /// The thread only parks at labels while a [`run_to!`] targets it, and records the labels it passes otherwise.
+    /// Used for background tasks such as proxies, which must keep running without the test driving them.
+    pub fn free_running(mut self) -> Self {
+        self.free_running = true;
+        self
+    }
+
     pub fn with_id(mut self, id: &str) -> Self {
         if id.contains('.') {
             panic!("Thread ID cannot contain '.' character, as it is used to nest threads.");
@@ -34,17 +52,166 @@ impl ThreadNestBuilder {
             (_, Some(child_id)) => format!("{}.{}", self.parent_id, child_id),
             (_, None) => format!("{}.", self.parent_id)
         };
-        let tc = Arc::new(ThreadController::new(&id, self.main_controller_data.clone()));
-        self.main_controller_data.write().await.add_thread(&id, tc.clone()).await;
+        let mut data = self.main_controller_data.write().await;
+        let mut tc = ThreadController::new(
+            &id,
+            self.main_controller_data.clone(),
+            data.trace.clone(),
+            data.lock_order.clone(),
+            data.happens_before.clone(),
+            data.invariants.clone(),
+        );
+        tc.free_running = self.free_running;
+        data.happens_before.fork(&self.parent_id, &id);
+        let tc = Arc::new(tc);
+        data.add_thread(&id, tc.clone()).await;
         tc
     }
 }
 
+/// Configures a [`MainController`], `#[tokitest::test(seed = 42, trace = "trace.txt", quiescence = 200)]` sets these options.
+pub struct MainControllerBuilder {
+    seed: u64,
+    trace_output: Option<PathBuf>,
+    paused_clock: bool,
+    quiescence_window: Duration,
+}
+
+impl MainControllerBuilder {
+    fn new() -> Self {
+        Self {
+            seed: 0,
+            trace_output: None,
+            paused_clock: false,
+            quiescence_window: QUIESCENCE_WINDOW,
+        }
+    }
+
+    /// Seed for any randomness the controller introduces, so a failing test can be reproduced
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Write the test's trace to this file when the MainController is dropped
+    pub fn with_trace_output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.trace_output = Some(path.into());
+        self
+    }
+
+    /// Tokio's clock is already paused, as with `#[tokio::test(start_paused = true)]`, so the controller owns a virtual clock from the start
+    pub fn with_paused_clock(mut self) -> Self {
+        self.paused_clock = true;
+        self
+    }
+
+    /// How long a running thread gets to reach a label or return before it counts as blocked, [`QUIESCENCE_WINDOW`] by default.
+    /// Raise it for threads that do slow work between labels.
+    pub fn with_quiescence_window(mut self, window: Duration) -> Self {
+        self.quiescence_window = window;
+        self
+    }
+
+    pub fn build(self) -> MainController {
+        let mut data = MainControllerData::new();
+        data.quiescence_window = self.quiescence_window;
+        if self.paused_clock {
+            data.clock_start = Some(tokio::time::Instant::now());
+        }
+        data.seed = self.seed;
+        data.rng = Rng::new(self.seed);
+        MainController {
+            reports: Reports {
+                seed: self.seed,
+                trace: data.trace.clone(),
+                coverage: data.coverage.clone(),
+                lock_order: data.lock_order.clone(),
+                happens_before: data.happens_before.clone(),
+                invariants: data.invariants.clone(),
+            },
+            data: Arc::new(RwLock::new(data)),
+            trace_output: self.trace_output,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct MainControllerData {
     thread_controllers: HashMap<String, Arc<ThreadController>>,
     waiting_for: HashMap<String, Sender<Arc<ThreadController>>>,
-    isolated_ids: Vec<String>,
+    network: NetworkState,
+    coverage: Arc<std::sync::Mutex<LabelCoverage>>,
+    trace: Arc<Trace>,
+    seed: u64,
+    rng: Rng,
+    scheduled_faults: Vec<ScheduledFault>,
+    next_message_id: u64,
+    pending_messages: Vec<Pending>,
+    /// When the test started on the virtual clock, set once tokio's clock is paused
+    clock_start: Option<tokio::time::Instant>,
+    lock_order: Arc<LockOrder>,
+    happens_before: Arc<HappensBefore>,
+    invariants: Arc<Invariants>,
+    quiescence_window: Duration,
+}
+
+/// A fault applied once when a thread reaches a label, see [`MainController::on_label`]
+struct ScheduledFault {
+    thread: String,
+    label: Box<dyn LabelTrait + Send + Sync>,
+    fault: NetworkFault,
+}
+
+/// A message held on a link with [`hold!`], waiting for the test to [`release!`] or [`drop_message!`] it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMessage {
+    /// Increases in the order messages were held
+    pub id: u64,
+    pub from: String,
+    pub to: String,
+    /// The thread that sent the message
+    pub thread: String,
+    /// The message itself, for messages of a [`SimNetwork::with_payloads`](crate::net::SimNetwork::with_payloads)
+    pub payload: Option<String>,
+}
+
+impl std::fmt::Display for PendingMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} {} -> {} sent by {}", self.id, self.from, self.to, crate::trace::display_thread(&self.thread))?;
+        if let Some(payload) = &self.payload {
+            write!(f, ": {}", payload)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the test decided for a held message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    Deliver,
+    /// Deliver ahead of the messages already waiting at the destination, a held `network_call!` just runs
+    DeliverNext,
+    Drop,
+}
+
+/// A held message and what to do once the test decides on it
+struct Pending {
+    message: PendingMessage,
+    /// Called once the test decides to deliver or drop the message.
+    /// The Mutex makes the callback Sync, it is only taken out once.
+    decide: std::sync::Mutex<Box<dyn FnOnce(Delivery) + Send>>,
+}
+
+impl std::fmt::Debug for Pending {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pending").field("message", &self.message).finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Debug for ScheduledFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScheduledFault").field("thread", &self.thread).field("fault", &self.fault).finish_non_exhaustive()
+    }
 }
 
 #[allow(dead_code)]
@@ -53,8 +220,70 @@ impl MainControllerData {
         MainControllerData {
             thread_controllers: HashMap::new(),
             waiting_for: HashMap::new(),
-            isolated_ids: Vec::new()
+            network: NetworkState::new(),
+            coverage: Arc::new(std::sync::Mutex::new(LabelCoverage::new())),
+            trace: Arc::new(Trace::new()),
+            seed: 0,
+            rng: Rng::new(0),
+            scheduled_faults: Vec::new(),
+            next_message_id: 0,
+            pending_messages: Vec::new(),
+            clock_start: None,
+            lock_order: Arc::new(LockOrder::new()),
+            happens_before: Arc::new(HappensBefore::new()),
+            invariants: Arc::new(Invariants::new()),
+            quiescence_window: QUIESCENCE_WINDOW,
+        }
+    }
+
+    fn hold_message(&mut self, thread: &str, from: &str, to: &str, payload: Option<String>, decide: Box<dyn FnOnce(Delivery) + Send>) {
+        let message = PendingMessage {
+            id: self.next_message_id,
+            from: from.to_string(),
+            to: to.to_string(),
+            thread: thread.to_string(),
+            payload,
+        };
+        self.next_message_id += 1;
+        self.pending_messages.push(Pending { message, decide: std::sync::Mutex::new(decide) });
+    }
+
+    fn roll_fate(&mut self, from: &str, to: Option<&str>, duplicable: bool) -> NetworkFate {
+        let network = &self.network;
+        let reachable = match to {
+            Some(to) => network.can_reach(from, to),
+            None => !network.is_isolated(from),
+        };
+
+        if !reachable {
+            NetworkFate::Unreachable
+        } else if network.is_lost(from, to, &mut self.rng) {
+            NetworkFate::Lost
+        } else {
+            let delay = network.delay(from, to, &mut self.rng);
+            if duplicable && network.is_duplicated(from, to, &mut self.rng) {
+                NetworkFate::Duplicated { delay }
+            } else {
+                NetworkFate::Delivered { delay }
+            }
+        }
+    }
+
+    /// Applies the faults scheduled for the thread `id` at this label, and forgets them
+    fn apply_scheduled_faults(&mut self, id: &str, label: &QualifiedLabel) {
+        let mut remaining = Vec::new();
+        for mut scheduled in std::mem::take(&mut self.scheduled_faults) {
+            if scheduled.thread == id {
+                scheduled.label.register(label);
+            }
+            if scheduled.thread == id && scheduled.label.reached() {
+                self.network.apply(&scheduled.fault);
+                self.trace.record(TraceEvent::Fault { thread: id.to_string(), label: label.clone(), fault: scheduled.fault });
+            } else {
+                remaining.push(scheduled);
+            }
         }
+        self.scheduled_faults = remaining;
     }
 
     pub async fn add_thread(&mut self, id: &str, tc: Arc<ThreadController>) {
@@ -67,18 +296,6 @@ impl MainControllerData {
             None => {}
         }
     }
-
-    pub fn isolate(&mut self, id: &str) {
-        self.isolated_ids.push(id.to_string());
-    }
-
-    pub fn heal(&mut self, id: &str) {
-        self.isolated_ids.retain(|prefix| !id.starts_with(prefix));
-    }
-
-    pub fn is_isolated(&self, id: &str) -> bool {
-        self.isolated_ids.iter().any(|prefix| id.starts_with(prefix))
-    }
 }
 
 /// Mark tests with `#[tokitest::test]` to create a MainController, this object manages nesting other threads and running to labels
@@ -87,14 +304,36 @@ impl MainControllerData {
 /// Manually calling [`MainController::run_to`], [`MainController::isolate`] and [`MainController::nest`] should be avoided, and instead use macros [`run_to!`], [`isolate!`], and [`spawn!`] should be used.
 #[derive(Debug)]
 pub struct MainController {
-    data: Arc<RwLock<MainControllerData>>
+    data: Arc<RwLock<MainControllerData>>,
+    trace_output: Option<PathBuf>,
+    reports: Reports,
+}
+
+/// What the controller reports when it is dropped, shared with its state so they are read without its lock
+#[derive(Debug)]
+struct Reports {
+    seed: u64,
+    trace: Arc<Trace>,
+    coverage: Arc<std::sync::Mutex<LabelCoverage>>,
+    lock_order: Arc<LockOrder>,
+    happens_before: Arc<HappensBefore>,
+    invariants: Arc<Invariants>,
 }
 
 #[allow(dead_code)]
 impl MainController {
     /// It is recommended to create MainController by marking tests with `#[tokitest::test]`
     pub fn new() -> MainController {
-        MainController { data: Arc::new(RwLock::new(MainControllerData::new())) }
+        MainController::builder().build()
+    }
+
+    pub fn builder() -> MainControllerBuilder {
+        MainControllerBuilder::new()
+    }
+
+    /// The seed this test runs with, set with `#[tokitest::test(seed = ...)]`
+    pub async fn seed(&self) -> u64 {
+        self.data.read().await.seed
     }
 
     /// It is recommended to use [`complete!`] instead of this function
@@ -103,14 +342,71 @@ impl MainController {
     }
 
     /// It is recommended to use [`run_to!`] instead of this function
+    ///
+    /// Once the clock is paused, virtual time the runtime advances while waiting for the thread is recorded in the trace.
     pub async fn run_to(&self, id: &str, label: &str) {
         self.run_to_label(id, StringLabel::new(label)).await;
     }
 
     /// It is recommended to use [`run_to!`] instead of this function
     pub async fn run_to_label(&self, id: &str, label: impl LabelTrait) {
+        let start = self.virtual_now().await;
         let thread_controller = self.get_thread_controller(id).await;
         thread_controller.run_to_label(label).await;
+        self.record_time_advanced(start).await;
+    }
+
+    /// It is recommended to use `run_to!(id, label, advance_time = limit)` instead of this function
+    ///
+    /// Pauses the clock, then advances it as far as needed until the thread reaches the label.
+    /// Panics if the thread does not reach the label within `limit` of virtual time.
+    pub async fn run_to_label_advancing(&self, id: &str, label: impl LabelTrait, limit: Duration) {
+        self.pause_time().await;
+        let start = tokio::time::Instant::now();
+        let thread_controller = self.get_thread_controller(id).await;
+        let reached = tokio::time::timeout(limit, thread_controller.run_to_label(label)).await;
+        self.record_time_advanced(Some(start)).await;
+        if reached.is_err() {
+            panic!("Thread {} did not reach the label passed to run_to! within {:?}", id, limit);
+        }
+    }
+
+    /// Pauses tokio's clock, so time only moves when the test advances it or every task waits on a timer.
+    /// Does nothing if the clock is already paused by the controller.
+    pub async fn pause_time(&self) {
+        let mut data = self.data.write().await;
+        if data.clock_start.is_none() {
+            tokio::time::pause();
+            data.clock_start = Some(tokio::time::Instant::now());
+        }
+    }
+
+    /// It is recommended to use [`advance_time!`] instead of this function
+    pub async fn advance_time(&self, duration: Duration) {
+        self.pause_time().await;
+        let start = tokio::time::Instant::now();
+        tokio::time::advance(duration).await;
+        self.record_time_advanced(Some(start)).await;
+    }
+
+    /// Virtual time since the test started, if the clock is paused
+    pub async fn elapsed(&self) -> Option<Duration> {
+        self.data.read().await.clock_start.map(|start| start.elapsed())
+    }
+
+    async fn virtual_now(&self) -> Option<tokio::time::Instant> {
+        self.data.read().await.clock_start.map(|_| tokio::time::Instant::now())
+    }
+
+    /// Records how far the virtual clock moved since `start`, if it moved
+    async fn record_time_advanced(&self, start: Option<tokio::time::Instant>) {
+        let data = self.data.read().await;
+        if let (Some(start), Some(clock_start)) = (start, data.clock_start) {
+            let by = start.elapsed();
+            if !by.is_zero() {
+                data.trace.record(TraceEvent::TimeAdvanced { by, elapsed: clock_start.elapsed() });
+            }
+        }
     }
 
     async fn get_thread_controller(&self, id: &str) -> Arc<ThreadController> {
@@ -134,11 +430,278 @@ impl MainController {
 
     /// It is recommended to use [`isolate!`] instead of this function
     pub async fn isolate(&self, id: &str) {
-        self.data.write().await.isolate(id);
+        self.data.write().await.network.isolate(id);
     }
 
+    /// It is recommended to use [`heal!`] instead of this function
+    ///
+    /// Heals the isolation of the thread, every link cut to or from it, and takes it out of the partition.
     pub async fn heal(&self, id: &str) {
-        self.data.write().await.heal(id);
+        self.data.write().await.network.heal(id);
+    }
+
+    /// It is recommended to use [`heal!`] instead of this function
+    pub async fn heal_link(&self, from: &str, to: &str) {
+        self.data.write().await.network.heal_link(from, to);
+    }
+
+    /// It is recommended to use [`heal!`] instead of this function
+    pub async fn heal_all(&self) {
+        self.data.write().await.network.heal_all();
+    }
+
+    /// It is recommended to use [`cut!`] instead of this function
+    pub async fn cut(&self, from: &str, to: &str) {
+        self.data.write().await.network.cut(from, to);
+    }
+
+    /// It is recommended to use [`partition!`] instead of this function
+    ///
+    /// Replaces the previous partition, if any.
+    pub async fn partition(&self, groups: &[&[&str]]) {
+        self.data.write().await.network.partition(groups);
+    }
+
+    /// It is recommended to use [`latency!`] instead of this function
+    ///
+    /// Delays every network call made by or sent to the thread by `fixed`, plus a seeded random delay of up to `jitter`.
+    /// A zero latency removes it.
+    pub async fn latency(&self, id: &str, fixed: Duration, jitter: Duration) {
+        self.data.write().await.network.set_latency(id, Latency { fixed, jitter });
+    }
+
+    /// It is recommended to use [`latency!`] instead of this function
+    ///
+    /// Delays network calls from one node to another, instead of the latency of either node.
+    pub async fn link_latency(&self, from: &str, to: &str, fixed: Duration, jitter: Duration) {
+        self.data.write().await.network.set_link_latency(from, to, Latency { fixed, jitter });
+    }
+
+    /// It is recommended to use [`invariant!`] instead of this function
+    ///
+    /// Checks `holds` every time a thread reaches a label, see [`crate::invariant`].
+    pub async fn invariant(&self, name: &str, holds: impl Fn() -> bool + Send + Sync + 'static) {
+        self.data.read().await.invariants.add(name, Box::new(holds));
+    }
+
+    /// It is recommended to use [`assert_trace!`] instead of this function
+    ///
+    /// Panics with the events leading up to the violation if the trace so far does not satisfy the formula.
+    pub async fn assert_trace(&self, formula: Formula) {
+        if let Err(violation) = formula.check(&self.data.read().await.trace.events()) {
+            panic!("tokitest: {}", violation);
+        }
+    }
+
+    /// It is recommended to use [`assert_blocked!`] instead of this function
+    ///
+    /// Panics unless the thread is blocked, see [`ThreadState::Blocked`], and waiting on `on` if it is given.
+    /// A running thread gets the quiescence window to reach a label or return before it counts as blocked,
+    /// see [`MainControllerBuilder::with_quiescence_window`].
+    pub async fn assert_blocked(&self, id: &str, on: Option<&str>) {
+        let window = self.data.read().await.quiescence_window;
+        let state = self.get_thread_controller(id).await.settle(window).await;
+        match (&state, on) {
+            (ThreadState::Blocked(_), None) => {},
+            (ThreadState::Blocked(Some(blocked_on)), Some(on)) if blocked_on == on => {},
+            (_, None) => panic!("Thread {} is not blocked, it is {}", id, state),
+            (_, Some(on)) => panic!("Thread {} is not blocked on {}, it is {}", id, on, state),
+        }
+    }
+
+    /// It is recommended to use [`assert_cannot_reach!`] instead of this function
+    ///
+    /// Resumes the thread at every label while the other threads stay where they are, until it blocks on a
+    /// `tokitest::sync` primitive, returns or crashes, and panics if it reaches the label on the way.
+    /// Also panics if the thread is still running without reaching a label after the quiescence window,
+    /// since it may reach the label later. Free running threads keep running meanwhile, as they do between [`run_to!`] steps.
+    pub async fn assert_cannot_reach(&self, id: &str, label: impl LabelTrait) {
+        let thread_controller = self.get_thread_controller(id).await;
+        match thread_controller.run_until_blocked(label).await {
+            Settled::Reached(reached) => panic!("Thread {} reached {} without another thread progressing", id, reached),
+            Settled::Undetermined => panic!(
+                "Thread {} is still running without reaching a label after the quiescence window, \
+                 whether it can reach the label is undetermined",
+                id
+            ),
+            Settled::Stopped => {},
+        }
+    }
+
+    /// It is recommended to use [`assert_parked_at!`] instead of this function
+    ///
+    /// Panics unless the thread is parked at the label, given by name or qualified name.
+    /// A running thread gets the quiescence window to reach it.
+    pub async fn assert_parked_at(&self, id: &str, label: &str) {
+        let window = self.data.read().await.quiescence_window;
+        let state = self.get_thread_controller(id).await.settle(window).await;
+        match &state {
+            ThreadState::Parked(parked) if parked.name() == label || parked.qualified() == label => {},
+            _ => panic!("Thread {} is not parked at {}, it is {}", id, label, state),
+        }
+    }
+
+    /// It is recommended to use [`on_label!`] instead of this function
+    ///
+    /// Applies the fault when the thread reaches the label, before it parks there.
+    /// The network changes atomically with the thread reaching the label: no network call of any thread sees it half applied,
+    /// and the thread's own calls after the label see the fault. Each scheduled fault is applied once.
+    pub async fn on_label(&self, id: &str, label: impl LabelTrait + Send + Sync + 'static, fault: NetworkFault) {
+        self.data.write().await.scheduled_faults.push(ScheduledFault {
+            thread: id.to_string(),
+            label: Box::new(label),
+            fault,
+        });
+    }
+
+    /// It is recommended to use [`crash!`] instead of this function
+    ///
+    /// Aborts the tasks of the thread `id` and every thread nested under it, and waits for them to stop.
+    /// The threads stay registered as [`ThreadState::Crashed`] until they are restarted.
+    pub async fn crash(&self, id: &str) {
+        let crashed: Vec<Arc<ThreadController>> = self.data.read().await.thread_controllers.iter()
+            .filter(|(thread, _)| crate::network::belongs_to(thread, id))
+            .map(|(_, tc)| tc.clone())
+            .collect();
+        if crashed.is_empty() {
+            panic!("Cannot crash {}, no thread has that ID", id);
+        }
+        for tc in crashed {
+            tc.crash().await;
+        }
+    }
+
+    /// It is recommended to use [`restart!`] instead of this function
+    ///
+    /// Registers a new incarnation of a crashed thread, returning its controller.
+    pub async fn restart(&self, id: &str) -> Arc<ThreadController> {
+        let previous = self.data.read().await.thread_controllers.get(id).cloned();
+        match previous {
+            Some(tc) if tc.state().await == ThreadState::Crashed => {},
+            _ => panic!("Cannot restart {}, it has not crashed", id),
+        }
+        let (parent, child) = id.rsplit_once('.').unwrap_or(("", id));
+        ThreadNestBuilder::new(parent, self.data.clone()).with_id(child).build().await
+    }
+
+    /// It is recommended to use [`hold!`] instead of this function
+    ///
+    /// Holds `network_call!(to = ...)`s and [`SimNetwork`](crate::net::SimNetwork) messages from one node to another
+    /// until the test releases or drops them. Messages already held stay held after [`MainController::unhold`].
+    pub async fn hold(&self, from: &str, to: &str) {
+        self.data.write().await.network.hold(from, to);
+    }
+
+    pub async fn unhold(&self, from: &str, to: &str) {
+        self.data.write().await.network.unhold(from, to);
+    }
+
+    /// It is recommended to use [`pending_messages!`] instead of this function
+    ///
+    /// Returns the messages held from the node `from` to the node `to`, in the order they were held
+    pub async fn pending_messages(&self, from: &str, to: &str) -> Vec<PendingMessage> {
+        self.data.read().await.pending_messages.iter()
+            .map(|pending| &pending.message)
+            .filter(|message| crate::network::belongs_to(&message.from, from) && crate::network::belongs_to(&message.to, to))
+            .cloned()
+            .collect()
+    }
+
+    /// It is recommended to use [`release!`] instead of this function
+    ///
+    /// Delivers a held message. Releasing messages in a different order than they were held reorders them.
+    /// Returns false if the message is not held.
+    pub async fn release_message(&self, id: u64) -> bool {
+        self.decide_message(id, Delivery::Deliver).await
+    }
+
+    /// It is recommended to use `release!(message, next)` instead of this function
+    ///
+    /// Delivers a held SimNetwork message ahead of the messages already waiting at its destination, so it is received next.
+    /// A held `network_call!` runs as with [`MainController::release_message`]. Returns false if the message is not held.
+    pub async fn release_message_next(&self, id: u64) -> bool {
+        self.decide_message(id, Delivery::DeliverNext).await
+    }
+
+    /// It is recommended to use [`drop_message!`] instead of this function
+    ///
+    /// Drops a held message: a `network_call!` runs its error callback, a SimNetwork message is never received.
+    /// Returns false if the message is not held.
+    pub async fn drop_message(&self, id: u64) -> bool {
+        self.decide_message(id, Delivery::Drop).await
+    }
+
+    async fn decide_message(&self, id: u64, delivery: Delivery) -> bool {
+        let mut data = self.data.write().await;
+        let Some(index) = data.pending_messages.iter().position(|pending| pending.message.id == id) else {
+            return false;
+        };
+        let pending = data.pending_messages.remove(index);
+        drop(data);
+        let decide = pending.decide.into_inner().unwrap_or_else(|e| e.into_inner());
+        decide(delivery);
+        true
+    }
+
+    /// It is recommended to use [`loss!`] instead of this function
+    ///
+    /// Each network call made by or sent to the thread is lost with this probability, a zero probability removes it.
+    pub async fn loss(&self, id: &str, probability: f64) {
+        self.data.write().await.network.set_loss(id, probability);
+    }
+
+    /// It is recommended to use [`loss!`] instead of this function
+    pub async fn link_loss(&self, from: &str, to: &str, probability: f64) {
+        self.data.write().await.network.set_link_loss(from, to, probability);
+    }
+
+    /// It is recommended to use [`duplication!`] instead of this function
+    ///
+    /// Each network call made by or sent to the thread is delivered twice with this probability, a zero probability removes it.
+    pub async fn duplication(&self, id: &str, probability: f64) {
+        self.data.write().await.network.set_duplication(id, probability);
+    }
+
+    /// It is recommended to use [`duplication!`] instead of this function
+    pub async fn link_duplication(&self, from: &str, to: &str, probability: f64) {
+        self.data.write().await.network.set_link_duplication(from, to, probability);
+    }
+
+    /// Returns the label hit counts recorded so far in this test
+    pub async fn coverage(&self) -> LabelCoverage {
+        self.data.read().await.coverage.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Returns every event recorded so far in this test, such as labels reached by each thread
+    pub async fn trace(&self) -> Vec<TraceEvent> {
+        self.data.read().await.trace.events()
+    }
+
+    /// Takes the potential deadlocks found in the lock-order graph so far, see [`crate::lock_order`].
+    ///
+    /// The test fails when the controller is dropped if any are left, taking them lets a test assert on expected ones.
+    pub async fn take_potential_deadlocks(&self) -> Vec<PotentialDeadlock> {
+        self.data.read().await.lock_order.take_deadlocks()
+    }
+
+    /// Takes the data races found on `Shared` cells so far, see [`crate::race`].
+    ///
+    /// Like potential deadlocks, races left when the controller is dropped fail the test.
+    pub async fn take_data_races(&self) -> Vec<DataRace> {
+        self.data.read().await.happens_before.take_races()
+    }
+
+    /// Resumes a thread parked at a label without waiting for its next label,
+    /// letting a free running thread such as a proxy run freely again after [`run_to!`] stopped it.
+    ///
+    /// It is recommended to use [`resume!`] instead of this function
+    pub async fn resume(&self, id: &str) {
+        self.get_thread_controller(id).await.resume().await;
+    }
+
+    /// Returns whether a thread is running, parked at a label, blocked on a `tokitest::sync` primitive, finished or crashed
+    pub async fn thread_state(&self, id: &str) -> ThreadState {
+        self.get_thread_controller(id).await.state().await
     }
 
     /// It is recommended to use [`spawn!`] or [`spawn_join_set!`] instead of this function
@@ -152,13 +715,143 @@ impl MainController {
     }
 }
 
+impl Drop for MainController {
+    fn drop(&mut self) {
+        let data = &self.reports;
+        if std::thread::panicking() {
+            eprintln!("tokitest: test failed with seed {}", data.seed);
+        }
+        if let Some(path) = &self.trace_output {
+            let written = path.parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(path, data.trace.to_string()));
+            if let Err(e) = written {
+                eprintln!("tokitest: failed to write trace to {}: {}", path.display(), e);
+            }
+        }
+        if coverage::enabled() {
+            if let Err(e) = coverage::write_report(&data.coverage.lock().unwrap_or_else(|e| e.into_inner())) {
+                eprintln!("tokitest: failed to write label coverage report: {}", e);
+            }
+        }
+
+        let mut reports: Vec<String> = data.invariants.violation().iter().map(ToString::to_string).collect();
+        reports.extend(data.lock_order.take_deadlocks().iter().map(ToString::to_string));
+        reports.extend(data.happens_before.take_races().iter().map(ToString::to_string));
+        if !reports.is_empty() && !std::thread::panicking() {
+            panic!("tokitest: {}", reports.join("\ntokitest: "));
+        }
+    }
+}
+
+/// Labels placed while a `network_call!` is held are qualified with the network module, like SimNetwork labels
+const HELD_LABEL_FUNCTION: &str = "tokitest::net";
+
+/// Labels placed by the `tokitest::sync` types, which are not reported as the label a thread took a lock after
+const SYNC_LABEL_FUNCTION: &str = "tokitest::sync";
+
+/// How long [`assert_blocked!`] and [`assert_parked_at!`] wait for a running thread to reach a label by default,
+/// see [`MainControllerBuilder::with_quiescence_window`]. On a paused clock, the window only passes once every task is idle.
+pub const QUIESCENCE_WINDOW: Duration = Duration::from_millis(50);
+
+tokio::task_local! {
+    static CURRENT_THREAD: Arc<ThreadController>;
+}
+
+std::thread_local! {
+    /// Set while invariants run, so they do not act as the thread that reached the label
+    static OUTSIDE_THREADS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Runs `f` with no current [`ThreadController`], see [`ThreadController::current`]
+pub(crate) fn without_current_thread<R>(f: impl FnOnce() -> R) -> R {
+    let outside = OUTSIDE_THREADS.replace(true);
+    let result = f();
+    OUTSIDE_THREADS.set(outside);
+    result
+}
+
+/// Where [`ThreadController::run_until_blocked`] left a thread
+enum Settled {
+    /// The thread reached the label and is parked there
+    Reached(QualifiedLabel),
+    /// The thread blocked on a `tokitest::sync` primitive, returned or crashed without reaching the label
+    Stopped,
+    /// The thread reached no label within the quiescence window, but waits on nothing the controller knows of
+    Undetermined,
+}
+
+/// Where a testable thread is, as seen by the `MainController`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThreadState {
+    /// The thread is running towards its next label
+    Running,
+    /// The thread is parked at a label, waiting for [`run_to!`] to resume it
+    Parked(QualifiedLabel),
+    /// The thread waits without reaching a label, on a [`tokitest::sync`](crate::sync) primitive such as `lock:accounts`
+    /// or `recv:jobs`, or on something else if it reached no label within the quiescence window of [`assert_blocked!`]
+    Blocked(Option<String>),
+    /// The thread has returned
+    Finished,
+    /// The thread was aborted by [`crash!`], and does not run until it is restarted
+    Crashed,
+}
+
+impl fmt::Display for ThreadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThreadState::Running => write!(f, "running"),
+            ThreadState::Parked(label) => write!(f, "parked at {}", label),
+            ThreadState::Blocked(Some(on)) => write!(f, "blocked on {}", on),
+            ThreadState::Blocked(None) => write!(f, "blocked"),
+            ThreadState::Finished => write!(f, "finished"),
+            ThreadState::Crashed => write!(f, "crashed"),
+        }
+    }
+}
+
+/// Clears what the thread waits on once the wait is over or cancelled
+struct Unblock<'a>(&'a ThreadController);
+
+impl Drop for Unblock<'_> {
+    fn drop(&mut self) {
+        self.0.set_blocked_on(None);
+    }
+}
+
+/// Sent by a thread to the `MainController` once per label, the only message of the handshake besides resuming the thread
+#[derive(Debug)]
+enum ThreadEvent {
+    /// The thread reached a label and waits to be resumed
+    Parked(QualifiedLabel),
+    /// The thread returned, it will not wait for a resume
+    Finished(QualifiedLabel),
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct ThreadController {
     id: String,
-    proceed_chan: (Sender<bool>, RwLock<Receiver<bool>>),
-    label_chan: (Sender<String>, RwLock<Receiver<String>>),
-    main_controller_data: Arc<RwLock<MainControllerData>>
+    resume_chan: (Sender<()>, RwLock<Receiver<()>>),
+    event_chan: (Sender<ThreadEvent>, RwLock<Receiver<ThreadEvent>>),
+    state: RwLock<ThreadState>,
+    main_controller_data: Arc<RwLock<MainControllerData>>,
+    trace: Arc<Trace>,
+    abort_handle: std::sync::Mutex<Option<AbortHandle>>,
+    free_running: bool,
+    /// A free running thread parks at labels while this is set by [`ThreadController::run_to_label`]
+    armed: AtomicBool,
+    lock_order: Arc<LockOrder>,
+    happens_before: Arc<HappensBefore>,
+    invariants: Arc<Invariants>,
+    /// The last label the thread passed outside of `tokitest::sync`, for reports about locks
+    last_label: std::sync::Mutex<Option<QualifiedLabel>>,
+    /// What the thread waits on in a `tokitest::sync` primitive, such as `lock:accounts`
+    blocked_on: std::sync::Mutex<Option<String>>,
+    /// An event received while the test waited for the thread to settle, handled by the next [`run_to!`]
+    peeked: std::sync::Mutex<Option<ThreadEvent>>,
+    /// Set once the thread crashed, wakes a [`run_to!`] waiting for its next label
+    crashed: tokio::sync::watch::Sender<bool>,
 }
 
 #[allow(dead_code)]
@@ -166,17 +859,36 @@ impl ThreadController {
 
     /// It is recommended to use [`spawn!`] or [`spawn_join_set!`] instead of this function
     // creates a named controller associated with a thread
-    fn new(id: &str, mc_data: Arc<RwLock<MainControllerData>>) -> ThreadController {
-        //create a channel to send "proceed signal" -- this resumes the thread operation
-        let proceed = channel::<bool>(1);
-        //consume the next label encountered in the thread
-        let label = channel::<String>(1);
+    fn new(
+        id: &str,
+        mc_data: Arc<RwLock<MainControllerData>>,
+        trace: Arc<Trace>,
+        lock_order: Arc<LockOrder>,
+        happens_before: Arc<HappensBefore>,
+        invariants: Arc<Invariants>,
+    ) -> ThreadController {
+        //create a channel to resume a parked thread
+        let resume = channel::<()>(1);
+        //receive the label a thread parked at
+        let event = channel::<ThreadEvent>(1);
 
         ThreadController {
             id: id.to_string(),
-            proceed_chan: (proceed.0, RwLock::new(proceed.1)),
-            label_chan: (label.0, RwLock::new(label.1)),
+            resume_chan: (resume.0, RwLock::new(resume.1)),
+            event_chan: (event.0, RwLock::new(event.1)),
+            state: RwLock::new(ThreadState::Running),
             main_controller_data: mc_data,
+            trace,
+            abort_handle: std::sync::Mutex::new(None),
+            free_running: false,
+            armed: AtomicBool::new(false),
+            lock_order,
+            happens_before,
+            invariants,
+            last_label: std::sync::Mutex::new(None),
+            blocked_on: std::sync::Mutex::new(None),
+            peeked: std::sync::Mutex::new(None),
+            crashed: tokio::sync::watch::Sender::new(false),
         }
     }
 
@@ -185,36 +897,401 @@ impl ThreadController {
     }
 
     async fn run_to_label(&self, mut label: impl LabelTrait) {
-        // println!("runnto {} for thread {}", label.clone(), self.id.clone());
-
+        self.armed.store(true, Ordering::SeqCst);
+        // Only the first resume orders the thread after the test, the labels it passes on the way do not
+        let mut handshake = true;
         loop {
-            let _ = self.proceed_chan.0.send(true).await;
-            match self.label_chan.1.write().await.recv().await {
-                Some(recv_label) => {
-                    if recv_label.ends_with(" block") {
-                        continue;
-                    }
-                    label.register(&recv_label);
-                    if label.reached() {
-                        break
-                    }
+            let state = self.state.read().await.clone();
+            match state {
+                ThreadState::Parked(_) => {
+                    *self.state.write().await = ThreadState::Running;
+                    self.send_resume(std::mem::take(&mut handshake)).await;
+                },
+                ThreadState::Finished => {
+                    panic!("Thread {} finished before reaching the label passed to run_to!", self.id);
+                },
+                ThreadState::Crashed => {
+                    panic!("Thread {} crashed before reaching the label passed to run_to!", self.id);
                 },
-                None => {
-                    println!("none");
+                ThreadState::Running | ThreadState::Blocked(_) => {},
+            }
+
+            let mut crashed = self.crashed.subscribe();
+            let event = tokio::select! {
+                event = self.next_event() => event,
+                _ = crashed.wait_for(|crashed| *crashed) => {
+                    panic!("Thread {} crashed before reaching the label passed to run_to!", self.id);
+                },
+            };
+            let (recv_label, finished) = match event {
+                Some(ThreadEvent::Parked(recv_label)) => {
+                    *self.state.write().await = ThreadState::Parked(recv_label.clone());
+                    (recv_label, false)
+                },
+                Some(ThreadEvent::Finished(recv_label)) => {
+                    *self.state.write().await = ThreadState::Finished;
+                    (recv_label, true)
+                },
+                // The controller holds the sender, so the channel never closes
+                None => unreachable!(),
+            };
+            if let Some(violation) = self.invariants.violation() {
+                panic!("tokitest: {}", violation);
+            }
+
+            label.register(&recv_label);
+            if label.reached() {
+                self.main_controller_data.read().await.coverage.lock().unwrap_or_else(|e| e.into_inner()).target(recv_label.function(), recv_label.name());
+                self.happens_before.stopped(&self.id);
+                self.armed.store(false, Ordering::SeqCst);
+                break
+            }
+            if finished {
+                panic!("Thread {} finished before reaching the label passed to run_to!", self.id);
+            }
+        }
+    }
+
+    /// Resumes the thread at every label until it blocks, returns or crashes, or reaches the label first.
+    /// The thread stays parked at the label it reached.
+    async fn run_until_blocked(&self, mut label: impl LabelTrait) -> Settled {
+        self.armed.store(true, Ordering::SeqCst);
+        let window = self.main_controller_data.read().await.quiescence_window;
+        let mut handshake = true;
+        let settled = loop {
+            self.resume_thread(std::mem::take(&mut handshake)).await;
+            let (at, finished) = match self.settle(window).await {
+                ThreadState::Parked(parked) => (parked, false),
+                // The END label of a thread that returned while settling is still peeked
+                ThreadState::Finished => match &*self.peeked.lock().unwrap_or_else(|e| e.into_inner()) {
+                    Some(ThreadEvent::Finished(end)) => (end.clone(), true),
+                    _ => break Settled::Stopped,
                 },
+                // Only the quiescence window ran out, the thread may still be working towards a label
+                ThreadState::Blocked(None) | ThreadState::Running => break Settled::Undetermined,
+                ThreadState::Blocked(Some(_)) | ThreadState::Crashed => break Settled::Stopped,
+            };
+            if let Some(violation) = self.invariants.violation() {
+                panic!("tokitest: {}", violation);
+            }
+            label.register(&at);
+            if label.reached() {
+                self.happens_before.stopped(&self.id);
+                break Settled::Reached(at);
+            }
+            if finished {
+                break Settled::Stopped;
+            }
+        };
+        self.armed.store(false, Ordering::SeqCst);
+        settled
+    }
+
+    /// The next event of the thread, the one received while it settled first
+    async fn next_event(&self) -> Option<ThreadEvent> {
+        let peeked = self.peeked.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match peeked {
+            Some(event) => Some(event),
+            None => self.event_chan.1.write().await.recv().await,
+        }
+    }
+
+    /// Waits for the thread to reach a label or return, up to `window`, and returns its state.
+    /// A running thread that does neither within the window is blocked, on what it waits for if that is known,
+    /// and its state stays blocked until the controller sees it reach a label.
+    async fn settle(&self, window: Duration) -> ThreadState {
+        if matches!(*self.state.read().await, ThreadState::Running | ThreadState::Blocked(_)) {
+            let mut events = self.event_chan.1.write().await;
+            let mut peeked = self.peeked.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if peeked.is_none() {
+                // Receiving is cancel safe, no event is lost when the window closes first
+                peeked = tokio::time::timeout(window, events.recv()).await.ok().flatten();
+            }
+            *self.peeked.lock().unwrap_or_else(|e| e.into_inner()) = peeked;
+        }
+        match self.state().await {
+            ThreadState::Running => {
+                let mut state = self.state.write().await;
+                if *state == ThreadState::Running {
+                    *state = ThreadState::Blocked(None);
+                }
+                ThreadState::Blocked(None)
+            },
+            state => state,
+        }
+    }
+
+    /// Resumes the thread if it is parked, without waiting for its next label.
+    /// A free running thread keeps running until a [`run_to!`] targets it again.
+    pub async fn resume(&self) {
+        self.resume_thread(true).await;
+    }
+
+    /// Resumes the thread if it is parked, after every stop the test saw if this is a `handshake`, see [`crate::race`]
+    async fn resume_thread(&self, handshake: bool) {
+        let mut state = self.state.write().await;
+        let peeked = {
+            let mut peeked = self.peeked.lock().unwrap_or_else(|e| e.into_inner());
+            match *peeked {
+                Some(ThreadEvent::Parked(_)) => peeked.take(),
+                _ => None,
             }
+        };
+        if let ThreadState::Parked(_) = *state {
+            *state = ThreadState::Running;
+            self.send_resume(handshake).await;
+        } else if peeked.is_some() {
+            *state = ThreadState::Running;
+            self.send_resume(handshake).await;
+        }
+    }
+
+    async fn send_resume(&self, handshake: bool) {
+        if handshake {
+            self.happens_before.resumed(&self.id);
+        }
+        let _ = self.resume_chan.0.send(()).await;
+    }
+
+    /// It is recommended to use [`spawn!`] or [`spawn_join_set!`] instead of this function
+    ///
+    /// Sets the task [`crash!`] aborts
+    pub fn set_abort_handle(&self, handle: AbortHandle) {
+        *self.abort_handle.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+    }
+
+    async fn crash(&self) {
+        let handle = self.abort_handle.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(handle) = handle {
+            handle.abort();
+            // Let the runtime drop the task, so it cannot reach another label after the crash
+            while !handle.is_finished() {
+                tokio::task::yield_now().await;
+            }
+        }
+        *self.state.write().await = ThreadState::Crashed;
+        *self.peeked.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        self.set_blocked_on(None);
+        self.crashed.send_replace(true);
+        self.trace.record(TraceEvent::Crash { thread: self.id.clone() });
+    }
+
+    /// The controller of the testable thread running this task, if it was spawned with [`spawn!`] or [`spawn_join_set!`].
+    ///
+    /// Lets tokitest types such as [`SimNetwork`](crate::net::SimNetwork) endpoints place checkpoints in the thread using them.
+    pub fn current() -> Option<Arc<ThreadController>> {
+        if OUTSIDE_THREADS.get() {
+            return None;
+        }
+        CURRENT_THREAD.try_with(|tc| tc.clone()).ok()
+    }
+
+    /// It is recommended to use [`spawn!`] or [`spawn_join_set!`] instead of this function
+    ///
+    /// Runs the future as the thread of this controller, see [`ThreadController::current`].
+    pub async fn scope<F: std::future::Future>(self: Arc<Self>, future: F) -> F::Output {
+        CURRENT_THREAD.scope(self, future).await
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns whether the thread is running, parked at a label, blocked, finished or crashed.
+    /// A thread is blocked while it waits on a `tokitest::sync` primitive,
+    /// or once it reached no label within the quiescence window of [`assert_blocked!`].
+    pub async fn state(&self) -> ThreadState {
+        let state = self.state.read().await.clone();
+        if !matches!(state, ThreadState::Running | ThreadState::Blocked(None)) {
+            return state;
+        }
+        match &*self.peeked.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(ThreadEvent::Parked(label)) => return ThreadState::Parked(label.clone()),
+            Some(ThreadEvent::Finished(_)) => return ThreadState::Finished,
+            None => {},
+        }
+        match self.blocked_on.lock().unwrap_or_else(|e| e.into_inner()).clone() {
+            Some(on) => ThreadState::Blocked(Some(on)),
+            None => state,
         }
     }
 
+    pub(crate) fn set_blocked_on(&self, on: Option<String>) {
+        *self.blocked_on.lock().unwrap_or_else(|e| e.into_inner()) = on;
+    }
+
+    /// Awaits the future as blocked on `on`, such as `lock:accounts`, see [`ThreadState::Blocked`]
+    pub(crate) async fn wait_on<F: std::future::Future>(&self, on: String, future: F) -> F::Output {
+        self.set_blocked_on(Some(on));
+        let _unblock = Unblock(self);
+        future.await
+    }
+
+    fn reach(&self, function: &str, label: &str) -> QualifiedLabel {
+        // A thread reaching a label waits on nothing anymore, even if a wait was never polled to completion
+        self.set_blocked_on(None);
+        let label = QualifiedLabel::new(function, label);
+        self.trace.record(TraceEvent::Label { thread: self.id.clone(), label: label.clone() });
+        if function != SYNC_LABEL_FUNCTION {
+            *self.last_label.lock().unwrap_or_else(|e| e.into_inner()) = Some(label.clone());
+        }
+        label
+    }
+
+    fn last_label(&self) -> Option<QualifiedLabel> {
+        self.last_label.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Adds the lock to the lock-order graph before the thread waits for it
+    pub(crate) fn lock_requested(&self, lock: u64, name: &str) {
+        self.lock_order.request(&self.id, lock, name, self.last_label());
+    }
+
+    pub(crate) fn lock_acquired(&self, lock: u64, name: &str) {
+        self.lock_order.acquire(&self.id, lock, name, self.last_label());
+    }
+
+    pub(crate) fn lock_released(&self, lock: u64) {
+        self.lock_order.release(&self.id, lock);
+    }
+
+    /// Publishes the thread's clock in a lock or channel, see [`crate::race`]
+    pub(crate) fn sync_release(&self, object: u64) {
+        self.happens_before.release(&self.id, object);
+    }
+
+    /// Joins the clock published in a lock or channel into the thread's clock
+    pub(crate) fn sync_acquire(&self, object: u64) {
+        self.happens_before.acquire(&self.id, object);
+    }
+
+    pub(crate) fn shared_access(&self, cell: u64, name: &str, kind: AccessKind) {
+        let access = Access { thread: self.id.clone(), kind, after: self.last_label() };
+        self.happens_before.access(cell, name, access);
+    }
+
+    /// Records that the thread passed a label, without parking or waiting for the controller.
+    /// Used where the thread cannot await, e.g. when a guard is dropped.
+    pub(crate) fn pass(&self, function: &str, label: &str) {
+        self.reach(function, label);
+    }
+
+    pub(crate) fn record(&self, event: TraceEvent) {
+        self.trace.record(event);
+    }
+
     /// It is recommended to use [`label!`] instead of this function
-    pub async fn label(&self, label: &str) {
-        let _ = self.proceed_chan.1.write().await.recv().await.unwrap();
-        let _ = self.label_chan.0.send(label.to_string()).await;
+    ///
+    /// Parks the thread at the label until [`run_to!`] resumes it.
+    /// `function` is the path of the `#[testable]` function the label is in, the label is qualified with it.
+    pub async fn label(&self, function: &str, label: &str) {
+        let mut data = self.main_controller_data.write().await;
+        data.coverage.lock().unwrap_or_else(|e| e.into_inner()).hit(function, label);
+        let label = self.reach(function, label);
+        data.apply_scheduled_faults(&self.id, &label);
+        drop(data);
+        self.check_invariants(&label);
+        if self.free_running && !self.armed.load(Ordering::SeqCst) {
+            return;
+        }
+        let _ = self.event_chan.0.send(ThreadEvent::Parked(label)).await;
+        let _ = self.resume_chan.1.write().await.recv().await;
+    }
+
+    /// It is recommended to use [`spawn!`] or [`spawn_join_set!`] instead of this function
+    ///
+    /// Reports the `END` label once the thread returns, without parking.
+    pub async fn finish(&self, function: &str) {
+        let mut data = self.main_controller_data.write().await;
+        let label = self.reach(function, "END");
+        data.apply_scheduled_faults(&self.id, &label);
+        drop(data);
+        self.check_invariants(&label);
+        let _ = self.event_chan.0.send(ThreadEvent::Finished(label)).await;
+    }
+
+    /// Runs the invariants at `label` once the controller's state is released, printing the first violation right away
+    /// so it is reported even if the test never waits for a thread again
+    fn check_invariants(&self, label: &QualifiedLabel) {
+        if let Some(violation) = self.invariants.check(&self.id, label, &self.trace) {
+            eprintln!("tokitest: {}", violation);
+        }
+    }
+
+    /// Declares the labels of a `#[testable]` function when it is entered, so unreached labels appear in the coverage report.
+    pub async fn declare_labels(&self, function: &str, labels: &[&str]) {
+        let data = self.main_controller_data.read().await;
+        let mut coverage = data.coverage.lock().unwrap_or_else(|e| e.into_inner());
+        for label in labels {
+            coverage.declare(function, label);
+        }
     }
 
     /// It is recommended to use [`network_call!`] instead of manually testing for isolated threads.
     pub async fn is_isolated(&self) -> bool {
-        return self.main_controller_data.read().await.is_isolated(&self.id);
+        return self.main_controller_data.read().await.network.is_isolated(&self.id);
+    }
+
+    /// Returns true if a message from `from` can reach the node `to`, whichever thread asks
+    pub(crate) async fn can_reach_from(&self, from: &str, to: &str) -> bool {
+        self.main_controller_data.read().await.network.can_reach(from, to)
+    }
+
+    /// It is recommended to use `network_call!(to = ...)` instead of manually testing for partitions.
+    pub async fn can_reach(&self, to: &str) -> bool {
+        self.main_controller_data.read().await.network.can_reach(&self.id, to)
+    }
+
+    /// It is recommended to use [`network_call!`] instead of this function
+    ///
+    /// Decides whether a network call to `to` is unreachable, lost, delivered or duplicated and how long it is delayed,
+    /// drawing any randomness from the test seed, and records the decision in the trace.
+    /// Only calls that can run twice are `duplicable`.
+    ///
+    /// A call on a link held with [`hold!`] parks at the label `held to {to}`, then waits for the test to release or drop it.
+    pub async fn network_fate(&self, to: Option<&str>, duplicable: bool) -> NetworkFate {
+        let mut data = self.main_controller_data.write().await;
+        let mut fate = data.roll_fate(&self.id, to, duplicable);
+
+        let held = match (to, fate) {
+            (Some(to), NetworkFate::Delivered { .. } | NetworkFate::Duplicated { .. }) if data.network.is_held(&self.id, to) => {
+                let (decision_tx, decision_rx) = tokio::sync::oneshot::channel();
+                data.hold_message(&self.id, &self.id, to, None, Box::new(move |delivery| { let _ = decision_tx.send(delivery != Delivery::Drop); }));
+                Some((to, decision_rx))
+            },
+            _ => None,
+        };
+        drop(data);
+
+        if let Some((to, decision_rx)) = held {
+            self.label(HELD_LABEL_FUNCTION, &format!("held to {}", to)).await;
+            // The decision is dropped along with the controller when the test ends
+            if !decision_rx.await.unwrap_or(false) {
+                fate = NetworkFate::Dropped;
+            }
+        }
+        self.trace.record(TraceEvent::NetworkCall { thread: self.id.clone(), to: to.map(str::to_string), fate });
+        fate
+    }
+
+    /// Like [`ThreadController::network_fate`], for a message this thread sends on behalf of the node `from`.
+    /// Held messages do not block the sender, see [`ThreadController::deliver_or_hold`].
+    pub(crate) async fn message_fate(&self, from: &str, to: &str) -> NetworkFate {
+        let fate = self.main_controller_data.write().await.roll_fate(from, Some(to), false);
+        self.trace.record(TraceEvent::NetworkCall { thread: self.id.clone(), to: Some(to.to_string()), fate });
+        fate
+    }
+
+    /// Runs `deliver(Delivery::Deliver)` right away, unless the link from `from` to `to` is held.
+    /// A held message is passed the test's decision once it releases or drops it, `payload` shows it meanwhile.
+    pub(crate) async fn deliver_or_hold(&self, from: &str, to: &str, payload: Option<String>, deliver: Box<dyn FnOnce(Delivery) + Send>) {
+        let mut data = self.main_controller_data.write().await;
+        if data.network.is_held(from, to) {
+            data.hold_message(&self.id, from, to, payload, deliver);
+        } else {
+            drop(data);
+            deliver(Delivery::Deliver);
+        }
     }
 
     /// It is recommended to use [`spawn!`] or [`spawn_join_set!`] instead of this function
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+/// Set this environment variable (to anything other than `0`) to write a label coverage report when each tokitest finishes.
+pub const COVERAGE_ENV: &str = "TOKITEST_COVERAGE";
+
+/// Identifies the test run the binaries of a coverage report belong to, defaults to the id of the process that
+/// started the test binaries, so each `cargo test` is one run. Counts left by other runs are discarded.
+pub const RUN_ID_ENV: &str = "TOKITEST_RUN_ID";
+
+/// Labels emitted by the framework itself, these are not reported.
+const RESERVED_LABELS: [&str; 2] = ["INIT", "END"];
+
+/// Coverage of a single [`label!`](crate::label) checkpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LabelStats {
+    /// Number of times a thread reached the label
+    pub hits: u64,
+    /// Number of times a [`run_to!`](crate::run_to) stopped a thread at the label
+    pub targeted: u64,
+}
+
+/// Hit counts per (function, label), accumulated by the `MainController` while a test runs.
+///
+/// Labels are declared when the `#[testable]` function containing them is entered,
+/// so labels that are declared but never hit show up as unreached.
+/// Functions that are never called are not known to the report.
+///
+/// Run tests with `TOKITEST_COVERAGE=1` to write the report to `target/tokitest/label_coverage.txt`.
+/// Each test binary stores its counts in `target/tokitest/coverage/<binary>.tsv`, stamped with the run it belongs to,
+/// and the report merges the binaries of the current run, see [`RUN_ID_ENV`].
+#[derive(Debug, Clone, Default)]
+pub struct LabelCoverage {
+    labels: BTreeMap<(String, String), LabelStats>,
+}
+
+impl LabelCoverage {
+    pub fn new() -> LabelCoverage {
+        LabelCoverage { labels: BTreeMap::new() }
+    }
+
+    /// Record that `label` exists in `function`, without hitting it
+    pub fn declare(&mut self, function: &str, label: &str) {
+        if is_reserved(label) {
+            return;
+        }
+        self.labels.entry((function.to_string(), label.to_string())).or_default();
+    }
+
+    pub fn hit(&mut self, function: &str, label: &str) {
+        if is_reserved(label) {
+            return;
+        }
+        self.labels.entry((function.to_string(), label.to_string())).or_default().hits += 1;
+    }
+
+    pub fn target(&mut self, function: &str, label: &str) {
+        if is_reserved(label) {
+            return;
+        }
+        self.labels.entry((function.to_string(), label.to_string())).or_default().targeted += 1;
+    }
+
+    /// Returns the stats of a label, or None if it was never declared or hit
+    pub fn get(&self, function: &str, label: &str) -> Option<LabelStats> {
+        self.labels.get(&(function.to_string(), label.to_string())).copied()
+    }
+
+    /// Labels that were declared but never reached by any thread
+    pub fn unreached(&self) -> Vec<(&str, &str)> {
+        self.filter(|stats| stats.hits == 0)
+    }
+
+    /// Labels that no [`run_to!`](crate::run_to) ever stopped at
+    pub fn untargeted(&self) -> Vec<(&str, &str)> {
+        self.filter(|stats| stats.targeted == 0)
+    }
+
+    fn filter(&self, predicate: impl Fn(&LabelStats) -> bool) -> Vec<(&str, &str)> {
+        self.labels.iter()
+            .filter(|(_, stats)| predicate(stats))
+            .map(|((function, label), _)| (function.as_str(), label.as_str()))
+            .collect()
+    }
+
+    pub fn merge(&mut self, other: &LabelCoverage) {
+        for (key, stats) in &other.labels {
+            let entry = self.labels.entry(key.clone()).or_default();
+            entry.hits += stats.hits;
+            entry.targeted += stats.targeted;
+        }
+    }
+
+    /// Human readable report listing unreached and untargeted labels
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "tokitest label coverage: {} labels", self.labels.len());
+
+        let unreached = self.unreached();
+        let _ = writeln!(out, "\nNever reached ({}):", unreached.len());
+        for (function, label) in unreached {
+            let _ = writeln!(out, "    {} \"{}\"", function, label);
+        }
+
+        let untargeted = self.untargeted();
+        let _ = writeln!(out, "\nNever targeted by run_to! ({}):", untargeted.len());
+        for (function, label) in untargeted {
+            let _ = writeln!(out, "    {} \"{}\"", function, label);
+        }
+
+        let _ = writeln!(out, "\nAll labels (hits / targeted):");
+        for ((function, label), stats) in &self.labels {
+            let _ = writeln!(out, "    {} \"{}\": {} / {}", function, label, stats.hits, stats.targeted);
+        }
+        out
+    }
+
+    /// One `function, label, hits, targeted` line per label, tab separated.
+    /// Tabs, newlines and backslashes in labels are escaped, see [`LabelCoverage::from_tsv`].
+    pub fn to_tsv(&self) -> String {
+        let mut out = String::new();
+        for ((function, label), stats) in &self.labels {
+            let _ = writeln!(out, "{}\t{}\t{}\t{}", escape(function), escape(label), stats.hits, stats.targeted);
+        }
+        out
+    }
+
+    /// Parses the counts written by [`LabelCoverage::to_tsv`], skipping malformed lines
+    pub fn from_tsv(tsv: &str) -> LabelCoverage {
+        let mut coverage = LabelCoverage::new();
+        for line in tsv.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [function, label, hits, targeted] = fields[..] {
+                let stats = LabelStats {
+                    hits: hits.parse().unwrap_or(0),
+                    targeted: targeted.parse().unwrap_or(0),
+                };
+                coverage.labels.insert((unescape(function), unescape(label)), stats);
+            }
+        }
+        coverage
+    }
+}
+
+fn escape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn is_reserved(label: &str) -> bool {
+    RESERVED_LABELS.contains(&label)
+}
+
+/// Coverage of every test that has finished in this test binary
+static BINARY_COVERAGE: LazyLock<Mutex<LabelCoverage>> = LazyLock::new(|| Mutex::new(LabelCoverage::new()));
+
+pub(crate) fn enabled() -> bool {
+    matches!(std::env::var(COVERAGE_ENV), Ok(value) if !value.is_empty() && value != "0")
+}
+
+fn output_dir() -> PathBuf {
+    let target = match (std::env::var("CARGO_TARGET_DIR"), std::env::var("CARGO_MANIFEST_DIR")) {
+        (Ok(target), _) => PathBuf::from(target),
+        (Err(_), Ok(manifest)) => PathBuf::from(manifest).join("target"),
+        _ => PathBuf::from("target"),
+    };
+    target.join("tokitest")
+}
+
+/// Name of the running test binary, without the hash cargo appends to it
+fn binary_name() -> String {
+    let stem = std::env::current_exe().ok()
+        .and_then(|exe| exe.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "tokitest".to_string());
+    match stem.rsplit_once('-') {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => name.to_string(),
+        _ => stem,
+    }
+}
+
+fn run_id() -> String {
+    match std::env::var(RUN_ID_ENV) {
+        Ok(run) if !run.is_empty() => run,
+        #[cfg(unix)]
+        _ => std::os::unix::process::parent_id().to_string(),
+        #[cfg(not(unix))]
+        _ => std::process::id().to_string(),
+    }
+}
+
+/// The first line of a binary's counts, naming the run they belong to
+fn run_header(run: &str) -> String {
+    format!("run\t{}\n", escape(run))
+}
+
+/// Merges a finished test's coverage into this binary's counts, then rewrites the report from the counts of every
+/// binary of the current run. Counts of earlier runs are deleted, so renamed or removed labels do not linger.
+pub(crate) fn write_report(coverage: &LabelCoverage) -> std::io::Result<()> {
+    let mut binary_coverage = BINARY_COVERAGE.lock().unwrap_or_else(|e| e.into_inner());
+    binary_coverage.merge(coverage);
+
+    let header = run_header(&run_id());
+    let coverage_dir = output_dir().join("coverage");
+    std::fs::create_dir_all(&coverage_dir)?;
+    std::fs::write(coverage_dir.join(format!("{}.tsv", binary_name())), header.clone() + &binary_coverage.to_tsv())?;
+
+    let mut merged = LabelCoverage::new();
+    for entry in std::fs::read_dir(&coverage_dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "tsv") {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        match contents.strip_prefix(&header) {
+            Some(counts) => merged.merge(&LabelCoverage::from_tsv(counts)),
+            // A file without a complete header may be one another binary of this run is writing
+            None if !contents.contains('\n') => {},
+            None => { let _ = std::fs::remove_file(&path); },
+        }
+    }
+    std::fs::write(output_dir().join("label_coverage.txt"), merged.report())
+}
@@ -0,0 +1,247 @@
+//! Operation histories and a linearizability checker.
+//!
+//! A [`History`] records when each client thread invokes an operation on the system under test, and when it returns.
+//! Record operations with [`record_op!`](crate::record_op), then check the history against a [`SequentialModel`]:
+//! it is linearizable if every operation can be ordered between its invocation and its return,
+//! so that applying them in that order to the model gives the results the clients saw.
+//! Operations that never returned, e.g. because their thread [`crash!`](crate::crash)ed, may or may not have taken effect.
+//!
+//! ```rust,ignore
+//! #[derive(Clone, Default)]
+//! struct Register(i32);
+//!
+//! impl SequentialModel for Register {
+//!     type Op = RegisterOp;
+//!     type Result = Option<i32>;
+//!
+//!     fn apply(&mut self, op: &RegisterOp) -> Option<i32> {
+//!         match op {
+//!             RegisterOp::Write(value) => { self.0 = *value; None },
+//!             RegisterOp::Read => Some(self.0),
+//!         }
+//!     }
+//! }
+//!
+//! let history = History::<Register>::new();
+//! spawn!("client1", async {
+//!     record_op!(history, RegisterOp::Write(1), client.write(1), |_| None).await;
+//! });
+//! // ...
+//! history.assert_linearizable(Register::default());
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::controller::ThreadController;
+use crate::trace::display_thread;
+
+/// The sequential specification a [`History`] is checked against
+pub trait SequentialModel: Clone {
+    type Op: Clone;
+    type Result: Clone + PartialEq;
+
+    /// Applies an operation to the model, and returns the result a client should see
+    fn apply(&mut self, op: &Self::Op) -> Self::Result;
+}
+
+/// An operation recorded in a [`History`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation<Op, Res> {
+    /// The ID of the thread that invoked the operation, empty for the root test thread
+    pub client: String,
+    pub op: Op,
+    /// None if the operation never returned
+    pub result: Option<Res>,
+    /// Position of the invocation among the events of the history
+    pub invoked: usize,
+    /// Position of the return among the events of the history, None if the operation never returned
+    pub returned: Option<usize>,
+}
+
+impl<Op: fmt::Debug, Res: fmt::Debug> fmt::Display for Operation<Op, Res> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?} invoked at {}", display_thread(&self.client), self.op, self.invoked)?;
+        match (&self.result, self.returned) {
+            (Some(result), Some(returned)) => write!(f, ", returned {:?} at {}", result, returned),
+            _ => write!(f, ", never returned"),
+        }
+    }
+}
+
+/// Identifies an operation invoked with [`History::invoke`], to pass to [`History::complete`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpId(usize);
+
+#[derive(Debug)]
+struct Events<Op, Res> {
+    operations: Vec<Operation<Op, Res>>,
+    next_event: usize,
+}
+
+/// Invoke and return events of the operations of concurrent clients, see the [module docs](self).
+///
+/// Clones share the same history, so it can be moved into every client thread.
+pub struct History<M: SequentialModel> {
+    events: Arc<Mutex<Events<M::Op, M::Result>>>,
+}
+
+impl<M: SequentialModel> Clone for History<M> {
+    fn clone(&self) -> Self {
+        History { events: self.events.clone() }
+    }
+}
+
+impl<M: SequentialModel> Default for History<M> {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl<M: SequentialModel> fmt::Debug for History<M> where M::Op: fmt::Debug, M::Result: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.operations()).finish()
+    }
+}
+
+impl<M: SequentialModel> History<M> {
+    pub fn new() -> History<M> {
+        History { events: Arc::new(Mutex::new(Events { operations: Vec::new(), next_event: 0 })) }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Events<M::Op, M::Result>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records that the current thread invoked `op`
+    pub fn invoke(&self, op: M::Op) -> OpId {
+        let client = ThreadController::current().map(|thread| thread.id().to_string()).unwrap_or_default();
+        let mut events = self.lock();
+        let invoked = events.next_event;
+        events.next_event += 1;
+        events.operations.push(Operation { client, op, result: None, invoked, returned: None });
+        OpId(events.operations.len() - 1)
+    }
+
+    /// Records that an invoked operation returned `result`
+    pub fn complete(&self, id: OpId, result: M::Result) {
+        let mut events = self.lock();
+        let returned = events.next_event;
+        events.next_event += 1;
+        let operation = &mut events.operations[id.0];
+        operation.result = Some(result);
+        operation.returned = Some(returned);
+    }
+
+    /// It is recommended to use [`record_op!`](crate::record_op) instead of this function
+    ///
+    /// Records `op` around awaiting `call`, whose output is the result of the operation
+    pub async fn record<F>(&self, op: M::Op, call: F) -> M::Result
+    where
+        F: std::future::Future<Output = M::Result>,
+    {
+        self.record_with(op, call, Clone::clone).await
+    }
+
+    /// It is recommended to use [`record_op!`](crate::record_op) instead of this function
+    ///
+    /// Records `op` around awaiting `call`, `result` maps its output to the result of the operation
+    pub async fn record_with<F, R>(&self, op: M::Op, call: F, result: R) -> F::Output
+    where
+        F: std::future::Future,
+        R: FnOnce(&F::Output) -> M::Result,
+    {
+        let id = self.invoke(op);
+        let output = call.await;
+        self.complete(id, result(&output));
+        output
+    }
+
+    /// The recorded operations, in the order they were invoked
+    pub fn operations(&self) -> Vec<Operation<M::Op, M::Result>> {
+        self.lock().operations.clone()
+    }
+
+    /// Checks that the history is linearizable with respect to `model`, in its initial state.
+    ///
+    /// If it is not, returns a minimal set of operations that is not linearizable on its own:
+    /// removing any one of them makes the rest linearizable.
+    pub fn check(&self, model: M) -> Result<(), NonLinearizable<M::Op, M::Result>> {
+        let operations = self.operations();
+        if is_linearizable(&model, &operations) {
+            return Ok(());
+        }
+
+        // Drop every operation the history stays non-linearizable without
+        let mut minimal = operations;
+        let mut i = 0;
+        while i < minimal.len() {
+            let mut without = minimal.clone();
+            without.remove(i);
+            if is_linearizable(&model, &without) {
+                i += 1;
+            } else {
+                minimal = without;
+            }
+        }
+        Err(NonLinearizable { operations: minimal })
+    }
+
+    /// Panics with the minimal non-linearizable subhistory if the history is not linearizable, see [`History::check`]
+    pub fn assert_linearizable(&self, model: M) where M::Op: fmt::Debug, M::Result: fmt::Debug {
+        if let Err(error) = self.check(model) {
+            panic!("{}", error);
+        }
+    }
+}
+
+/// Searches for an order of the operations that respects their real-time order and the model's results.
+///
+/// An operation can be linearized next if it was invoked before every remaining operation that returned,
+/// as it could then have taken effect first. Operations that never returned can take effect with any result, or not at all.
+fn is_linearizable<M: SequentialModel>(model: &M, operations: &[Operation<M::Op, M::Result>]) -> bool {
+    let mut linearized = vec![false; operations.len()];
+    search(model, operations, &mut linearized)
+}
+
+fn search<M: SequentialModel>(model: &M, operations: &[Operation<M::Op, M::Result>], linearized: &mut [bool]) -> bool {
+    let remaining = || operations.iter().zip(linearized.iter()).filter(|(_, done)| !**done).map(|(operation, _)| operation);
+    if remaining().all(|operation| operation.returned.is_none()) {
+        return true;
+    }
+    let first_return = remaining().filter_map(|operation| operation.returned).min().unwrap_or(usize::MAX);
+
+    for (i, operation) in operations.iter().enumerate() {
+        if linearized[i] || operation.invoked > first_return {
+            continue;
+        }
+        let mut next = model.clone();
+        let result = next.apply(&operation.op);
+        if operation.result.as_ref().is_some_and(|expected| *expected != result) {
+            continue;
+        }
+        linearized[i] = true;
+        if search(&next, operations, linearized) {
+            return true;
+        }
+        linearized[i] = false;
+    }
+    false
+}
+
+/// A set of operations from a [`History`] that cannot be linearized, returned by [`History::check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonLinearizable<Op, Res> {
+    /// The operations, in the order they were invoked
+    pub operations: Vec<Operation<Op, Res>>,
+}
+
+impl<Op: fmt::Debug, Res: fmt::Debug> fmt::Display for NonLinearizable<Op, Res> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "History is not linearizable, minimal non-linearizable subhistory:")?;
+        for operation in &self.operations {
+            write!(f, "\n  {}", operation)?;
+        }
+        Ok(())
+    }
+}
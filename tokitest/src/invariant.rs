@@ -0,0 +1,104 @@
+//! Invariants checked every time a thread reaches a label.
+//!
+//! An invariant registered with [`invariant!`](crate::invariant) runs whenever any testable thread reaches a label,
+//! including the `END` label of a thread that returns, so it sees the state at every point the test could have stopped,
+//! not only between [`run_to!`](crate::run_to) steps. The first violation is printed as soon as it is found, and fails
+//! the test the next time it waits for a thread, or when the controller is dropped, with the thread and label that broke
+//! the invariant and the trace leading up to it.
+//!
+//! Invariants run while the thread is at the label, outside of it and without holding the controller's state,
+//! so they may call into the controller: [`tokitest::sync`](crate::sync) types they read place no checkpoints
+//! and count as no thread's accesses. They must not wait, so read `tokio` locks with `try_read`.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::controller::without_current_thread;
+use crate::label_spec::QualifiedLabel;
+use crate::trace::{display_thread, Trace, TraceEvent};
+
+/// How many trace events before the violation are reported
+const RECENT_EVENTS: usize = 10;
+
+/// An invariant that did not hold when a thread reached a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    /// Name of the invariant, the source of its condition unless it was named
+    pub invariant: String,
+    pub thread: String,
+    pub label: QualifiedLabel,
+    /// The last events of the trace, ending with the label
+    pub recent: Vec<TraceEvent>,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invariant `{}` violated when {} reached {}, recent trace:", self.invariant, display_thread(&self.thread), self.label)?;
+        for event in &self.recent {
+            write!(f, "\n  {}", event)?;
+        }
+        Ok(())
+    }
+}
+
+struct Invariant {
+    name: String,
+    holds: Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+/// The invariants of a test, and the first violation.
+///
+/// Shared by the controllers like the trace, so invariants run and the violation is read without the controller's state.
+#[derive(Default)]
+pub(crate) struct Invariants {
+    invariants: Mutex<Vec<Arc<Invariant>>>,
+    violation: Mutex<Option<InvariantViolation>>,
+}
+
+impl fmt::Debug for Invariants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.snapshot().iter().map(|invariant| invariant.name.clone()).collect();
+        f.debug_struct("Invariants").field("invariants", &names).field("violation", &self.violation()).finish()
+    }
+}
+
+impl Invariants {
+    pub(crate) fn new() -> Invariants {
+        Invariants::default()
+    }
+
+    pub(crate) fn add(&self, name: &str, holds: Box<dyn Fn() -> bool + Send + Sync>) {
+        self.invariants.lock().unwrap_or_else(|e| e.into_inner()).push(Arc::new(Invariant { name: name.to_string(), holds }));
+    }
+
+    /// The invariants registered so far, to run without holding the list
+    fn snapshot(&self) -> Vec<Arc<Invariant>> {
+        self.invariants.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Checks every invariant when `thread` reached `label`, returning the violation if it is the first one.
+    /// Once one is violated, the others are not checked anymore.
+    pub(crate) fn check(&self, thread: &str, label: &QualifiedLabel, trace: &Trace) -> Option<InvariantViolation> {
+        if self.violation().is_some() {
+            return None;
+        }
+        let invariants = self.snapshot();
+        let broken = without_current_thread(|| invariants.iter().find(|invariant| !(invariant.holds)()))?;
+        let mut violation = self.violation.lock().unwrap_or_else(|e| e.into_inner());
+        // Another thread may have found a violation while these invariants ran
+        if violation.is_some() {
+            return None;
+        }
+        *violation = Some(InvariantViolation {
+            invariant: broken.name.clone(),
+            thread: thread.to_string(),
+            label: label.clone(),
+            recent: trace.recent(RECENT_EVENTS),
+        });
+        violation.clone()
+    }
+
+    pub(crate) fn violation(&self) -> Option<InvariantViolation> {
+        self.violation.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
@@ -1,10 +1,51 @@
+use std::fmt;
 use regex::Regex;
 
+/// A label reached by a testable thread, qualified with the path of the `#[testable]` function it is in.
+///
+/// `label!("start")` in `fn worker` of module `my_mod` has the name `start`
+/// and the qualified name `my_mod::worker::start`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QualifiedLabel {
+    function: String,
+    name: String,
+}
+
+impl QualifiedLabel {
+    pub fn new(function: &str, name: &str) -> QualifiedLabel {
+        QualifiedLabel {
+            function: function.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// The label as written in [`label!`](crate::label)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Path of the function the label is in, such as `my_mod::worker`
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+
+    /// The function path followed by the name, such as `my_mod::worker::start`
+    pub fn qualified(&self) -> String {
+        format!("{}::{}", self.function, self.name)
+    }
+}
+
+impl fmt::Display for QualifiedLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.function, self.name)
+    }
+}
+
 /// When specifying a Label to run_to, the user can pass an object with the LabelTrait,
 /// which can be used to specify a condition for when a label should be hit.
 /// Labels can be composed for flexible condition specification
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use tokitest::{test, testable, call, label, spawn, run_to, complete};
 /// #[tokitest::test]
 /// async fn test_labels() {
@@ -43,7 +84,7 @@ use regex::Regex;
 /// ```
 pub trait LabelTrait {
     /// When the test thread reaches a label, this object's register() function will be called with that label
-    fn register(&mut self, label: &str);
+    fn register(&mut self, label: &QualifiedLabel);
     /// Retuns true if the label has been reached. False if otherwise
     fn reached(&self) -> bool;
     /// Resets any internal state (such as reached state)
@@ -51,10 +92,12 @@ pub trait LabelTrait {
 }
 
 /// Most basic Label that requires an exact label match to be triggered.
+///
+/// Either the short name (`start`) or the qualified name (`my_mod::worker::start`) of the label may be given.
 /// 
 /// Labels can be composed for flexible condition specification
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use tokitest::{test, testable, call, label, spawn, run_to, complete};
 /// #[tokitest::test]
 /// async fn test_labels() {
@@ -106,8 +149,8 @@ impl StringLabel {
 }
 #[allow(dead_code)]
 impl LabelTrait for StringLabel {
-    fn register(&mut self, label: &str) {
-        if label == self.label {
+    fn register(&mut self, label: &QualifiedLabel) {
+        if label.name() == self.label || label.qualified() == self.label {
             self.hit = true;
         }
     }
@@ -122,10 +165,13 @@ impl LabelTrait for StringLabel {
 
 /// RegexLabel allows the user to specify a Regex Pattern,
 /// that when matched marks the label as reached
+///
+/// [`RegexLabel::new`] matches against the label's name, [`RegexLabel::qualified`] against its qualified name,
+/// e.g. `RegexLabel::qualified(Regex::new(r"^my_mod::worker::").unwrap())` matches any label in `my_mod::worker`.
 /// 
 /// Labels can be composed for flexible condition specification
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use tokitest::{test, testable, call, label, spawn, run_to, complete};
 /// #[tokitest::test]
 /// async fn test_labels() {
@@ -164,19 +210,35 @@ impl LabelTrait for StringLabel {
 /// ```
 pub struct RegexLabel {
     pattern: Regex,
+    qualified: bool,
     hit: bool
 }
 impl RegexLabel {
     pub fn new(pattern: Regex) -> RegexLabel {
         return RegexLabel {
             pattern: pattern,
+            qualified: false,
+            hit: false
+        }
+    }
+
+    /// Match the pattern against the qualified name of labels, `function::path::label`
+    pub fn qualified(pattern: Regex) -> RegexLabel {
+        RegexLabel {
+            pattern,
+            qualified: true,
             hit: false
         }
     }
 }
 impl LabelTrait for RegexLabel {
-    fn register(&mut self, label: &str) {
-        if self.pattern.is_match(label) {
+    fn register(&mut self, label: &QualifiedLabel) {
+        let matched = if self.qualified {
+            self.pattern.is_match(&label.qualified())
+        } else {
+            self.pattern.is_match(label.name())
+        };
+        if matched {
             self.hit = true;
         }
     }
@@ -193,7 +255,7 @@ impl LabelTrait for RegexLabel {
 /// 
 /// Labels can be composed for flexible condition specification
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use tokitest::{test, testable, call, label, spawn, run_to, complete};
 /// #[tokitest::test]
 /// async fn test_labels() {
@@ -231,13 +293,13 @@ impl LabelTrait for RegexLabel {
 /// }
 /// ```
 pub struct RepeatedLabel {
-    label: Box<dyn LabelTrait>,
+    label: Box<dyn LabelTrait + Send + Sync>,
     count: u64,
     current_count: u64
 }
 #[allow(dead_code)]
 impl RepeatedLabel {
-    pub fn new<L:LabelTrait + 'static>(label: L, count: u64) -> RepeatedLabel {
+    pub fn new<L:LabelTrait + Send + Sync + 'static>(label: L, count: u64) -> RepeatedLabel {
         RepeatedLabel {
             label: Box::new(label),
             count: count,
@@ -247,7 +309,7 @@ impl RepeatedLabel {
 }
 #[allow(dead_code)]
 impl LabelTrait for RepeatedLabel {
-    fn register(&mut self, label: &str) {
+    fn register(&mut self, label: &QualifiedLabel) {
         self.label.register(label);
         if self.label.reached() {
             self.current_count += 1;
@@ -269,7 +331,7 @@ impl LabelTrait for RepeatedLabel {
 /// 
 /// Labels can be composed for flexible condition specification
 /// 
-/// ```rust
+/// ```rust,ignore
 /// use tokitest::{test, testable, call, label, spawn, run_to, complete};
 /// #[tokitest::test]
 /// async fn test_labels() {
@@ -307,19 +369,19 @@ impl LabelTrait for RepeatedLabel {
 /// }
 /// ```
 pub struct OrLabel {
-    labels: Vec<Box<dyn LabelTrait>>,
+    labels: Vec<Box<dyn LabelTrait + Send + Sync>>,
 }
 #[allow(dead_code)]
 impl OrLabel {
-    pub fn new<L:LabelTrait + 'static>(labels: Vec<L>) -> OrLabel {
+    pub fn new<L:LabelTrait + Send + Sync + 'static>(labels: Vec<L>) -> OrLabel {
         OrLabel {
-            labels: labels.into_iter().map(|l| Box::new(l) as Box<dyn LabelTrait>).collect(),
+            labels: labels.into_iter().map(|l| Box::new(l) as Box<dyn LabelTrait + Send + Sync>).collect(),
         }
     }
 }
 #[allow(dead_code)]
 impl LabelTrait for OrLabel {
-    fn register(&mut self, label: &str) {
+    fn register(&mut self, label: &QualifiedLabel) {
         for l in &mut self.labels {
             l.register(label);
         }
@@ -1,15 +1,63 @@
+//! # tokitest
+//!
+//! ## Enabling tokitest
+//!
+//! The macros emit instrumented code only when the `tokitest` cfg flag is set:
+//! ```sh
+//! RUSTFLAGS="--cfg tokitest" cargo test
+//! ```
+//! A cfg flag applies to every crate in the build, so testable code in any of your crates is instrumented together.
+//! Without it, [`spawn!`], [`call!`], [`network_call!`] and [`label!`] compile to the plain tokio code they wrap,
+//! `#[testable]` functions keep their original signature, the types in [`sync`] are the `tokio::sync` types,
+//! and `#[tokitest::test]` tests are compiled out.
+//!
+//! Declare the flag in each crate that uses the macros to silence the `unexpected_cfgs` lint:
+//! ```toml
+//! [lints.rust]
+//! unexpected_cfgs = { level = "warn", check-cfg = ['cfg(tokitest)'] }
+//! ```
+//! Test files that only contain tokitests can be compiled out entirely with `#![cfg(tokitest)]`.
+
 #![allow(dead_code)]
 
 // Hidden module for macro support
 #[doc(hidden)]
 pub mod macros {
     pub use futures::future::Either;
+    pub use crate::trace::NetworkFate;
+
+    /// Waits out the latency of a [`network_call!`](crate::network_call) before running the call,
+    /// and a duplicate of it if the call is delivered twice
+    pub async fn delayed<F: std::future::Future>(delay: std::time::Duration, call: F, duplicate: Option<F>) -> F::Output {
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        let output = call.await;
+        match duplicate {
+            Some(duplicate) => duplicate.await,
+            None => output,
+        }
+    }
 }
 
 pub mod controller;
+pub mod coverage;
+pub mod history;
+pub mod invariant;
 mod label_spec;
+pub mod lock_order;
+pub mod net;
+mod network;
+pub mod proxy;
+pub mod race;
+mod rng;
+pub mod sync;
+pub mod temporal;
+pub mod trace;
 
 pub use crate::label_spec::{
+    LabelTrait,
+    QualifiedLabel,
     OrLabel,
     RegexLabel,
     StringLabel,
@@ -28,5 +76,25 @@ pub use tokitest_macro::{
     network_call,
     isolate,
     complete,
-    heal
+    heal,
+    cut,
+    partition,
+    latency,
+    loss,
+    duplication,
+    on_label,
+    hold,
+    pending_messages,
+    release,
+    drop_message,
+    crash,
+    restart,
+    resume,
+    advance_time,
+    record_op,
+    invariant,
+    assert_trace,
+    assert_blocked,
+    assert_parked_at,
+    assert_cannot_reach,
 };
\ No newline at end of file
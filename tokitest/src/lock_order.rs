@@ -0,0 +1,178 @@
+//! Lock-order graph and potential-deadlock reports.
+//!
+//! Every time a thread waits for a [`tokitest::sync`](crate::sync) lock while holding another,
+//! the controller adds an edge from the held lock to the requested one. The graph spans the whole test,
+//! so if thread A takes `accounts` then `audit` while thread B takes `audit` then `accounts`, the cycle is found
+//! even if the schedule of this run never made them wait for each other. The test fails when the controller
+//! is dropped, with a report naming the threads, locks and the labels they passed before taking each lock,
+//! unless the test took the reports with [`MainController::take_potential_deadlocks`](crate::controller::MainController::take_potential_deadlocks).
+//!
+//! Locks are told apart by instance, not by name, and reads and writes of a `RwLock` are treated alike.
+//! Locks taken with `try_lock` never wait, so they add no edge towards themselves.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::label_spec::QualifiedLabel;
+use crate::trace::display_thread;
+
+/// A lock a thread waited for while holding another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEdge {
+    pub thread: String,
+    /// Name of the lock the thread held
+    pub held: String,
+    /// Name of the lock the thread waited for
+    pub requested: String,
+    /// The last label the thread passed before taking the held lock
+    pub held_after: Option<QualifiedLabel>,
+    /// The last label the thread passed before requesting the other lock
+    pub requested_after: Option<QualifiedLabel>,
+}
+
+impl fmt::Display for LockEdge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} requested {}", display_thread(&self.thread), self.requested)?;
+        if let Some(label) = &self.requested_after {
+            write!(f, " after {}", label)?;
+        }
+        write!(f, " while holding {}", self.held)?;
+        if let Some(label) = &self.held_after {
+            write!(f, " taken after {}", label)?;
+        }
+        Ok(())
+    }
+}
+
+/// A cycle in the lock-order graph: each edge requests the lock the next one holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PotentialDeadlock {
+    pub edges: Vec<LockEdge>,
+}
+
+impl fmt::Display for PotentialDeadlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let locks: Vec<&str> = self.edges.iter().map(|edge| edge.held.as_str()).collect();
+        write!(f, "potential deadlock between locks {}:", locks.join(", "))?;
+        for edge in &self.edges {
+            write!(f, "\n  {}", edge)?;
+        }
+        Ok(())
+    }
+}
+
+/// A lock held by a thread
+#[derive(Debug)]
+struct Held {
+    lock: u64,
+    name: String,
+    after: Option<QualifiedLabel>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    held: HashMap<String, Vec<Held>>,
+    /// The first edge seen between two locks, by lock instance
+    edges: HashMap<u64, HashMap<u64, LockEdge>>,
+    /// Lock instances of the cycles found so far, so each is reported once
+    reported: HashSet<Vec<u64>>,
+    deadlocks: Vec<PotentialDeadlock>,
+}
+
+impl State {
+    /// The edges of a path from `from` to `to` with the lock each edge starts at, if there is one
+    fn path(&self, from: u64, to: u64) -> Option<Vec<(u64, LockEdge)>> {
+        let mut previous: HashMap<u64, u64> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(lock) = queue.pop_front() {
+            if lock == to {
+                let mut path = Vec::new();
+                let mut current = to;
+                while current != from {
+                    let before = previous[&current];
+                    path.push((before, self.edges[&before][&current].clone()));
+                    current = before;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.edges.get(&lock).into_iter().flat_map(HashMap::keys) {
+                if *next != from && !previous.contains_key(next) {
+                    previous.insert(*next, lock);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Locks held by each thread and the order they were taken in, shared by the `MainController` and every `ThreadController`
+#[derive(Debug, Default)]
+pub(crate) struct LockOrder {
+    state: Mutex<State>,
+}
+
+impl LockOrder {
+    pub(crate) fn new() -> LockOrder {
+        LockOrder::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds an edge from every lock `thread` holds to `lock`, and records the cycles they close
+    pub(crate) fn request(&self, thread: &str, lock: u64, name: &str, after: Option<QualifiedLabel>) {
+        let mut state = self.lock();
+        let new_edges: Vec<(u64, LockEdge)> = state.held.get(thread).into_iter().flatten()
+            .filter(|held| held.lock != lock)
+            .map(|held| (held.lock, LockEdge {
+                thread: thread.to_string(),
+                held: held.name.clone(),
+                requested: name.to_string(),
+                held_after: held.after.clone(),
+                requested_after: after.clone(),
+            }))
+            .collect();
+
+        for (held, edge) in new_edges {
+            let edges = state.edges.entry(held).or_default();
+            if edges.contains_key(&lock) {
+                continue;
+            }
+            edges.insert(lock, edge.clone());
+
+            // A path back from the requested lock to the held one closes a cycle
+            let Some(path) = state.path(lock, held) else {
+                continue;
+            };
+            let mut locks: Vec<u64> = path.iter().map(|(from, _)| *from).chain([held]).collect();
+            locks.sort();
+            if state.reported.insert(locks) {
+                let edges = std::iter::once(edge).chain(path.into_iter().map(|(_, edge)| edge)).collect();
+                state.deadlocks.push(PotentialDeadlock { edges });
+            }
+        }
+    }
+
+    /// Records that `thread` holds `lock`
+    pub(crate) fn acquire(&self, thread: &str, lock: u64, name: &str, after: Option<QualifiedLabel>) {
+        self.lock().held.entry(thread.to_string()).or_default().push(Held { lock, name: name.to_string(), after });
+    }
+
+    pub(crate) fn release(&self, thread: &str, lock: u64) {
+        let mut state = self.lock();
+        if let Some(held) = state.held.get_mut(thread) {
+            if let Some(i) = held.iter().rposition(|held| held.lock == lock) {
+                held.remove(i);
+            }
+        }
+    }
+
+    /// Takes the potential deadlocks found so far
+    pub(crate) fn take_deadlocks(&self) -> Vec<PotentialDeadlock> {
+        std::mem::take(&mut self.lock().deadlocks)
+    }
+}
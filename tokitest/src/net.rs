@@ -0,0 +1,185 @@
+//! Simulated message passing between testable nodes.
+//!
+//! A [`SimNetwork`] gives each node an [`Endpoint`] to send typed messages to other nodes and receive them.
+//! Used from a thread spawned with [`spawn!`](crate::spawn), every send and receive is a checkpoint,
+//! and messages follow the network the test sets up with [`isolate!`](crate::isolate), [`cut!`](crate::cut),
+//! [`partition!`](crate::partition), [`latency!`](crate::latency) and [`loss!`](crate::loss).
+//! Node IDs are thread IDs, so endpoint `"node1"` is isolated by `isolate!("node1")`.
+//!
+//! ```rust,ignore
+//! let network = SimNetwork::<String>::new();
+//! let mut node1 = network.endpoint("node1");
+//! let mut node2 = network.endpoint("node2");
+//!
+//! spawn!("node1", async move {
+//!     node1.send("node2", "ping".to_string()).await;   // parks at "send to node2"
+//! });
+//! spawn!("node2", async move {
+//!     let (from, message) = node2.recv().await;       // parks at "recv from node1"
+//! });
+//!
+//! hold!("node1" -> "node2").await;
+//! run_to!("node1", "END").await;
+//! let held = pending_messages!("node1" -> "node2").await;   // the ping is in flight
+//! release!(held[0]).await;
+//! complete!("node2").await;
+//! ```
+//!
+//! Messages are held with the same [`hold!`](crate::hold) as `network_call!`s, and released or dropped with
+//! [`release!`](crate::release) and [`drop_message!`](crate::drop_message). `release!(message, next)` delivers a message
+//! ahead of the messages already waiting at its destination.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+
+use crate::controller::{Delivery, ThreadController};
+use crate::trace::NetworkFate;
+
+/// Labels placed by endpoints are qualified with this path, e.g. `tokitest::net::send to node2`
+const LABEL_FUNCTION: &str = module_path!();
+
+#[derive(Debug)]
+struct Envelope<M> {
+    from: String,
+    to: String,
+    message: M,
+}
+
+#[derive(Debug)]
+struct State<M> {
+    /// Messages delivered to each node, in the order they will be received
+    inboxes: HashMap<String, VecDeque<Envelope<M>>>,
+    receivers: HashMap<String, Arc<Notify>>,
+}
+
+/// An in-process network carrying messages of type `M` between nodes.
+///
+/// Cloning a SimNetwork gives another handle to the same network.
+/// Messages follow the controller's network, including links held with [`hold!`](crate::hold),
+/// whose messages the test delivers, reorders or drops one by one.
+#[derive(Debug)]
+pub struct SimNetwork<M> {
+    state: Arc<Mutex<State<M>>>,
+    /// Shows held messages in [`pending_messages!`](crate::pending_messages), if the network was created with payloads
+    payload: Option<fn(&M) -> String>,
+}
+
+impl<M> Clone for SimNetwork<M> {
+    fn clone(&self) -> Self {
+        SimNetwork { state: self.state.clone(), payload: self.payload }
+    }
+}
+
+impl<M> Default for SimNetwork<M> {
+    fn default() -> Self {
+        SimNetwork::new()
+    }
+}
+
+impl<M> SimNetwork<M> {
+    pub fn new() -> SimNetwork<M> {
+        SimNetwork {
+            state: Arc::new(Mutex::new(State { inboxes: HashMap::new(), receivers: HashMap::new() })),
+            payload: None,
+        }
+    }
+
+    /// Creates a network whose held messages show their payload in [`pending_messages!`](crate::pending_messages)
+    pub fn with_payloads() -> SimNetwork<M> where M: std::fmt::Debug {
+        SimNetwork { payload: Some(|message| format!("{:?}", message)), ..SimNetwork::new() }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<M>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Creates the endpoint of the node `id`, each node has a single endpoint
+    pub fn endpoint(&self, id: &str) -> Endpoint<M> {
+        let notify = Arc::new(Notify::new());
+        if self.lock().receivers.insert(id.to_string(), notify.clone()).is_some() {
+            panic!("SimNetwork endpoint {} already exists", id);
+        }
+        Endpoint { id: id.to_string(), network: self.clone(), notify }
+    }
+
+    fn send(&self, from: &str, to: &str, message: M, delivery: Delivery) {
+        let envelope = Envelope { from: from.to_string(), to: to.to_string(), message };
+        let mut state = self.lock();
+        match delivery {
+            Delivery::Deliver => state.deliver(envelope, VecDeque::push_back),
+            Delivery::DeliverNext => state.deliver(envelope, VecDeque::push_front),
+            Delivery::Drop => {},
+        }
+    }
+
+    fn take(&self, id: &str) -> Option<Envelope<M>> {
+        self.lock().inboxes.get_mut(id).and_then(VecDeque::pop_front)
+    }
+}
+
+impl<M> State<M> {
+    fn deliver(&mut self, envelope: Envelope<M>, push: fn(&mut VecDeque<Envelope<M>>, Envelope<M>)) {
+        if let Some(notify) = self.receivers.get(&envelope.to) {
+            notify.notify_one();
+        }
+        push(self.inboxes.entry(envelope.to.clone()).or_default(), envelope);
+    }
+}
+
+/// A node's connection to a [`SimNetwork`]
+#[derive(Debug)]
+pub struct Endpoint<M> {
+    id: String,
+    network: SimNetwork<M>,
+    notify: Arc<Notify>,
+}
+
+impl<M: Send + 'static> Endpoint<M> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Sends a message to the node `to`, parking at the label `send to {to}` first.
+    ///
+    /// Messages to unreachable nodes and messages lost with [`loss!`](crate::loss) are dropped silently,
+    /// and the sender waits out any [`latency!`](crate::latency) before the message is delivered.
+    /// Messages on a link held with [`hold!`](crate::hold) are delivered when the test releases them, without blocking the sender.
+    pub async fn send(&self, to: &str, message: M) {
+        let Some(tc) = ThreadController::current() else {
+            self.network.send(&self.id, to, message, Delivery::Deliver);
+            return;
+        };
+
+        tc.label(LABEL_FUNCTION, &format!("send to {}", to)).await;
+        match tc.message_fate(&self.id, to).await {
+            NetworkFate::Delivered { delay } | NetworkFate::Duplicated { delay } => {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            },
+            NetworkFate::Unreachable | NetworkFate::Lost | NetworkFate::Dropped => return,
+        }
+        let network = self.network.clone();
+        let (from, to_node) = (self.id.clone(), to.to_string());
+        let payload = self.network.payload.map(|payload| payload(&message));
+        tc.deliver_or_hold(&self.id, to, payload, Box::new(move |delivery| {
+            network.send(&from, &to_node, message, delivery);
+        })).await;
+    }
+
+    /// Waits for the next message delivered to this node, then parks at the label `recv from {from}`.
+    /// Returns the sender and the message.
+    pub async fn recv(&mut self) -> (String, M) {
+        let envelope = loop {
+            if let Some(envelope) = self.network.take(&self.id) {
+                break envelope;
+            }
+            self.notify.notified().await;
+        };
+        if let Some(tc) = ThreadController::current() {
+            tc.label(LABEL_FUNCTION, &format!("recv from {}", envelope.from)).await;
+        }
+        (envelope.from, envelope.message)
+    }
+}
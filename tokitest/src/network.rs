@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+use crate::rng::Rng;
+
+/// Which nodes can reach each other through [`network_call!`](crate::network_call).
+///
+/// A node is a thread ID, and includes every thread nested under it: `node1.worker` belongs to `node1`.
+/// A node can be isolated from everything, individual links between nodes can be cut in one direction,
+/// and the network can be partitioned into groups of nodes that only reach their own group.
+/// Calls that can reach their destination may be delayed by a [`Latency`], lost or duplicated,
+/// as set on a node or a link.
+#[derive(Debug, Default)]
+pub(crate) struct NetworkState {
+    isolated_ids: Vec<String>,
+    /// Messages from the first node to the second are lost
+    cut_links: HashSet<(String, String)>,
+    /// Groups of nodes that cannot reach nodes in other groups, replaced by each partition
+    partition: Vec<Vec<String>>,
+    latency: Conditions<Latency>,
+    /// Probability that a call is lost
+    loss: Conditions<f64>,
+    /// Probability that a call is delivered twice
+    duplication: Conditions<f64>,
+    /// Messages from the first node to the second wait for the test to release them
+    held_links: HashSet<(String, String)>,
+}
+
+/// A fixed delay, plus a random delay of up to `jitter` drawn from the test seed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Latency {
+    pub fixed: Duration,
+    pub jitter: Duration,
+}
+
+impl Latency {
+    pub const ZERO: Latency = Latency { fixed: Duration::ZERO, jitter: Duration::ZERO };
+
+    fn sample(&self, rng: &mut Rng) -> Duration {
+        self.fixed + rng.duration_up_to(self.jitter)
+    }
+}
+
+/// A network condition set on nodes, applying to every call made by or sent to the node,
+/// and on links, replacing the condition of both nodes for calls from the first to the second.
+#[derive(Debug)]
+struct Conditions<T> {
+    nodes: HashMap<String, T>,
+    links: HashMap<(String, String), T>,
+}
+
+impl<T> Default for Conditions<T> {
+    fn default() -> Self {
+        Conditions { nodes: HashMap::new(), links: HashMap::new() }
+    }
+}
+
+impl<T: Copy> Conditions<T> {
+    fn set_node(&mut self, node: &str, condition: Option<T>) {
+        match condition {
+            Some(condition) => { self.nodes.insert(node.to_string(), condition); },
+            None => { self.nodes.remove(node); },
+        }
+    }
+
+    fn set_link(&mut self, from: &str, to: &str, condition: Option<T>) {
+        let link = (from.to_string(), to.to_string());
+        match condition {
+            Some(condition) => { self.links.insert(link, condition); },
+            None => { self.links.remove(&link); },
+        }
+    }
+
+    /// The condition of the most specific link from `from` to `to` if there is one,
+    /// otherwise the conditions of the most specific nodes `from` and `to` belong to.
+    fn lookup(&self, from: &str, to: Option<&str>) -> Vec<T> {
+        if let Some(to) = to {
+            let link = self.links.iter()
+                .filter(|((link_from, link_to), _)| belongs_to(from, link_from) && belongs_to(to, link_to))
+                .max_by_key(|((link_from, link_to), _)| (link_from.len(), link_to.len()));
+            if let Some((_, condition)) = link {
+                return vec![*condition];
+            }
+        }
+
+        std::iter::once(from).chain(to)
+            .filter_map(|id| {
+                self.nodes.iter()
+                    .filter(|(node, _)| belongs_to(id, node))
+                    .max_by_key(|(node, _)| node.len())
+                    .map(|(_, condition)| *condition)
+            })
+            .collect()
+    }
+}
+
+/// A change to which nodes can reach each other, scheduled with [`on_label!`](crate::on_label)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkFault {
+    Isolate(String),
+    /// Heals the isolation of a node and every link cut to or from it
+    Heal(String),
+    HealLink(String, String),
+    HealAll,
+    Cut(String, String),
+    Partition(Vec<Vec<String>>),
+}
+
+impl fmt::Display for NetworkFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkFault::Isolate(id) => write!(f, "isolate {}", id),
+            NetworkFault::Heal(id) => write!(f, "heal {}", id),
+            NetworkFault::HealLink(from, to) => write!(f, "heal {} -> {}", from, to),
+            NetworkFault::HealAll => write!(f, "heal all"),
+            NetworkFault::Cut(from, to) => write!(f, "cut {} -> {}", from, to),
+            NetworkFault::Partition(groups) => {
+                let groups: Vec<String> = groups.iter().map(|group| format!("[{}]", group.join(", "))).collect();
+                write!(f, "partition {}", groups.join(" "))
+            },
+        }
+    }
+}
+
+/// Returns true if the thread `id` is `node` or nested under it
+pub(crate) fn belongs_to(id: &str, node: &str) -> bool {
+    id == node || id.strip_prefix(node).is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn check_probability(probability: f64) -> Option<f64> {
+    if !(0.0..=1.0).contains(&probability) {
+        panic!("Network probability must be between 0 and 1, got {}", probability);
+    }
+    (probability > 0.0).then_some(probability)
+}
+
+impl NetworkState {
+    pub fn new() -> NetworkState {
+        NetworkState::default()
+    }
+
+    pub fn isolate(&mut self, id: &str) {
+        self.isolated_ids.push(id.to_string());
+    }
+
+    /// Heals the isolation of `id`, every link cut to or from it, and takes it out of the partition,
+    /// along with every thread nested under it
+    pub fn heal(&mut self, id: &str) {
+        self.isolated_ids.retain(|node| !belongs_to(node, id));
+        self.cut_links.retain(|(from, to)| !belongs_to(from, id) && !belongs_to(to, id));
+        for group in &mut self.partition {
+            group.retain(|node| !belongs_to(node, id));
+        }
+    }
+
+    /// Heals the links cut from `from` to `to`, and between the threads nested under them.
+    /// Partitions are healed by node or as a whole.
+    pub fn heal_link(&mut self, from: &str, to: &str) {
+        self.cut_links.retain(|(cut_from, cut_to)| !belongs_to(cut_from, from) || !belongs_to(cut_to, to));
+    }
+
+    pub fn heal_all(&mut self) {
+        self.isolated_ids.clear();
+        self.cut_links.clear();
+        self.partition.clear();
+    }
+
+    pub fn is_isolated(&self, id: &str) -> bool {
+        self.isolated_ids.iter().any(|node| belongs_to(id, node))
+    }
+
+    /// Cut the link from `from` to `to`, `to` can still reach `from`
+    pub fn cut(&mut self, from: &str, to: &str) {
+        self.cut_links.insert((from.to_string(), to.to_string()));
+    }
+
+    /// Partitions the network into groups, replacing the previous partition: nodes in different groups cannot reach
+    /// each other in either direction. Nodes in no group, and links cut or nodes isolated otherwise, are not affected.
+    pub fn partition(&mut self, groups: &[&[&str]]) {
+        self.partition = groups.iter()
+            .map(|group| group.iter().map(|node| node.to_string()).collect())
+            .collect();
+    }
+
+    /// The partition group of the most specific node the thread `id` belongs to
+    fn group(&self, id: &str) -> Option<usize> {
+        self.partition.iter().enumerate()
+            .flat_map(|(group, nodes)| nodes.iter().map(move |node| (group, node)))
+            .filter(|(_, node)| belongs_to(id, node))
+            .max_by_key(|(_, node)| node.len())
+            .map(|(group, _)| group)
+    }
+
+    /// Returns true if a network call from the thread `from` can reach the node `to`
+    pub fn can_reach(&self, from: &str, to: &str) -> bool {
+        if self.is_isolated(from) || self.is_isolated(to) {
+            return false;
+        }
+        if let (Some(from_group), Some(to_group)) = (self.group(from), self.group(to)) {
+            if from_group != to_group {
+                return false;
+            }
+        }
+        !self.cut_links.iter().any(|(cut_from, cut_to)| belongs_to(from, cut_from) && belongs_to(to, cut_to))
+    }
+
+    /// Hold messages from `from` to `to` until the test releases or drops them
+    pub fn hold(&mut self, from: &str, to: &str) {
+        self.held_links.insert((from.to_string(), to.to_string()));
+    }
+
+    pub fn unhold(&mut self, from: &str, to: &str) {
+        self.held_links.remove(&(from.to_string(), to.to_string()));
+    }
+
+    /// Returns true if a message from the thread `from` to the node `to` is held
+    pub fn is_held(&self, from: &str, to: &str) -> bool {
+        self.held_links.iter().any(|(held_from, held_to)| belongs_to(from, held_from) && belongs_to(to, held_to))
+    }
+
+    pub fn apply(&mut self, fault: &NetworkFault) {
+        match fault {
+            NetworkFault::Isolate(id) => self.isolate(id),
+            NetworkFault::Heal(id) => self.heal(id),
+            NetworkFault::HealLink(from, to) => self.heal_link(from, to),
+            NetworkFault::HealAll => self.heal_all(),
+            NetworkFault::Cut(from, to) => self.cut(from, to),
+            NetworkFault::Partition(groups) => {
+                let groups: Vec<Vec<&str>> = groups.iter().map(|group| group.iter().map(String::as_str).collect()).collect();
+                let groups: Vec<&[&str]> = groups.iter().map(Vec::as_slice).collect();
+                self.partition(&groups);
+            },
+        }
+    }
+
+    /// Delay calls made by or sent to `node`, a zero latency removes it
+    pub fn set_latency(&mut self, node: &str, latency: Latency) {
+        self.latency.set_node(node, (latency != Latency::ZERO).then_some(latency));
+    }
+
+    /// Delay calls from `from` to `to`, a zero latency removes it
+    pub fn set_link_latency(&mut self, from: &str, to: &str, latency: Latency) {
+        self.latency.set_link(from, to, (latency != Latency::ZERO).then_some(latency));
+    }
+
+    /// Lose calls made by or sent to `node` with the given probability
+    pub fn set_loss(&mut self, node: &str, probability: f64) {
+        self.loss.set_node(node, check_probability(probability));
+    }
+
+    pub fn set_link_loss(&mut self, from: &str, to: &str, probability: f64) {
+        self.loss.set_link(from, to, check_probability(probability));
+    }
+
+    /// Deliver calls made by or sent to `node` twice with the given probability
+    pub fn set_duplication(&mut self, node: &str, probability: f64) {
+        self.duplication.set_node(node, check_probability(probability));
+    }
+
+    pub fn set_link_duplication(&mut self, from: &str, to: &str, probability: f64) {
+        self.duplication.set_link(from, to, check_probability(probability));
+    }
+
+    /// How long a network call from the thread `from` to the node `to` takes before it runs.
+    ///
+    /// The latency of the most specific link between them is used if there is one,
+    /// otherwise the latencies of the most specific nodes `from` and `to` belong to are added.
+    pub fn delay(&self, from: &str, to: Option<&str>, rng: &mut Rng) -> Duration {
+        self.latency.lookup(from, to).iter().map(|latency| latency.sample(rng)).sum()
+    }
+
+    /// Rolls whether a network call from `from` to `to` is lost, each applying loss probability rolls on its own
+    pub fn is_lost(&self, from: &str, to: Option<&str>, rng: &mut Rng) -> bool {
+        roll(&self.loss.lookup(from, to), rng)
+    }
+
+    /// Rolls whether a network call from `from` to `to` is delivered twice
+    pub fn is_duplicated(&self, from: &str, to: Option<&str>, rng: &mut Rng) -> bool {
+        roll(&self.duplication.lookup(from, to), rng)
+    }
+}
+
+/// Rolls every probability, so the generator advances the same way whichever roll succeeds
+fn roll(probabilities: &[f64], rng: &mut Rng) -> bool {
+    probabilities.iter().fold(false, |hit, probability| (rng.next_f64() < *probability) | hit)
+}
@@ -0,0 +1,353 @@
+//! Loopback proxies for code that talks over real sockets.
+//!
+//! A [`TcpProxy`] or [`UdpProxy`] listens on `127.0.0.1` in front of an upstream address, and forwards traffic for a node.
+//! Point the code under test at [`TcpProxy::addr`] instead of the upstream address, then control the connection from the test.
+//!
+//! Proxied traffic follows the controller's network like a [`network_call!`](crate::network_call): requests go from the
+//! client node to the proxied node, replies the other way. A proxy started with [`TcpProxy::start_link`] knows its client
+//! node, so [`cut!`](crate::cut) and [`partition!`](crate::partition) apply to it, while the clients of a proxy started with
+//! [`TcpProxy::start`] belong to no node. While the other side cannot be reached, new TCP connections are closed,
+//! bytes on open TCP connections stall until it can, and UDP datagrams are dropped. Chunks of bytes and datagrams are
+//! delayed with [`latency!`](crate::latency) and lost with [`loss!`](crate::loss), and each one is recorded in the trace
+//! as a network call. The proxy can also drop, delay or stall bytes on its own.
+//!
+//! Every proxy runs as a free running thread with the ID `proxy-{node}`: it records the labels
+//! `accept`, `read from client`, `write to server`, `read from server` and `write to client`
+//! (`recv from ...` and `send to ...` for UDP) without waiting for the test,
+//! and parks at them only while a [`run_to!`](crate::run_to) targets it. [`resume!`](crate::resume) lets it run freely again.
+//!
+//! ```rust,ignore
+//! let proxy = TcpProxy::start_link(&tokitest_main_controller, "node1", "node2", server_addr).await.unwrap();
+//! spawn!("node1", async move {
+//!     let stream = TcpStream::connect(proxy_addr).await.unwrap();
+//!     // ...
+//! });
+//!
+//! run_to!("proxy-node2", "accept").await;
+//! cut!("node1" -> "node2").await;
+//! resume!("proxy-node2").await;
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+
+use crate::controller::{MainController, ThreadController};
+use crate::trace::NetworkFate;
+
+/// Labels placed by proxies are qualified with this path, e.g. `tokitest::proxy::accept`
+const LABEL_FUNCTION: &str = module_path!();
+
+/// How often a stalled connection checks whether the other side can be reached again
+const HEAL_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// The side of the proxy bytes were read from
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Client,
+    Server,
+}
+
+/// Faults a proxy applies to every byte it forwards, on top of the controller's network
+#[derive(Debug)]
+struct Faults {
+    drop_bytes: AtomicBool,
+    delay: Mutex<Duration>,
+    stalled: watch::Sender<bool>,
+}
+
+/// What proxy tasks share: the nodes they forward between, their thread, and the faults to apply
+#[derive(Debug)]
+struct Proxy {
+    /// The node clients belong to, the proxy's thread ID if they belong to no node
+    client: String,
+    node: String,
+    thread: Arc<ThreadController>,
+    /// Tasks of a proxy share its thread controller, one of them passes a label at a time
+    label_lock: tokio::sync::Mutex<()>,
+    faults: Faults,
+    tasks: Mutex<Vec<AbortHandle>>,
+}
+
+impl Proxy {
+    async fn start(main_controller: &MainController, client: Option<&str>, node: &str) -> Arc<Proxy> {
+        let id = format!("proxy-{}", node);
+        let thread = main_controller.nest().with_id(&id).free_running().build().await;
+        Arc::new(Proxy {
+            client: client.map_or(id, str::to_string),
+            node: node.to_string(),
+            thread,
+            label_lock: tokio::sync::Mutex::new(()),
+            faults: Faults {
+                drop_bytes: AtomicBool::new(false),
+                delay: Mutex::new(Duration::ZERO),
+                stalled: watch::channel(false).0,
+            },
+            tasks: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn spawn(&self, task: impl std::future::Future<Output = ()> + Send + 'static) {
+        let handle = tokio::spawn(task).abort_handle();
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner()).push(handle);
+    }
+
+    async fn label(&self, label: &str) {
+        let _guard = self.label_lock.lock().await;
+        self.thread.label(LABEL_FUNCTION, label).await;
+    }
+
+    /// The nodes a chunk read from `side` goes from and to
+    fn link(&self, side: Side) -> (&str, &str) {
+        match side {
+            Side::Client => (&self.client, &self.node),
+            Side::Server => (&self.node, &self.client),
+        }
+    }
+
+    async fn can_reach(&self, side: Side) -> bool {
+        let (from, to) = self.link(side);
+        self.thread.can_reach_from(from, to).await
+    }
+
+    /// Waits while the proxy is stalled, and while the other side cannot be reached if `stall_unreachable`
+    async fn wait_forwarding(&self, side: Side, stall_unreachable: bool) {
+        let mut stalled = self.faults.stalled.subscribe();
+        loop {
+            let _ = stalled.wait_for(|stalled| !stalled).await;
+            if !stall_unreachable || self.can_reach(side).await {
+                return;
+            }
+            tokio::time::sleep(HEAL_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Applies the network and the proxy's faults to a chunk of bytes read from `side`, returns false if it is dropped.
+    /// TCP chunks wait until the other side can be reached, `stall_unreachable`, while datagrams are dropped.
+    async fn forward(&self, side: Side, stall_unreachable: bool) -> bool {
+        self.wait_forwarding(side, stall_unreachable).await;
+        let (from, to) = self.link(side);
+        let network_delay = match self.thread.message_fate(from, to).await {
+            NetworkFate::Delivered { delay } | NetworkFate::Duplicated { delay } => delay,
+            NetworkFate::Unreachable | NetworkFate::Lost | NetworkFate::Dropped => return false,
+        };
+        if self.faults.drop_bytes.load(Ordering::SeqCst) {
+            return false;
+        }
+        let delay = network_delay + *self.faults.delay.lock().unwrap_or_else(|e| e.into_inner());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        true
+    }
+
+    fn stop(&self) {
+        for task in self.tasks.lock().unwrap_or_else(|e| e.into_inner()).drain(..) {
+            task.abort();
+        }
+    }
+}
+
+/// Controls shared by [`TcpProxy`] and [`UdpProxy`]
+macro_rules! proxy_controls {
+    ($proxy:ident) => {
+        impl $proxy {
+            /// The address to connect to instead of the upstream address
+            pub fn addr(&self) -> SocketAddr {
+                self.addr
+            }
+
+            /// The ID of the free running thread the proxy's labels are recorded under
+            pub fn thread_id(&self) -> String {
+                format!("proxy-{}", self.proxy.node)
+            }
+
+            /// Discard every byte read from either side instead of forwarding it
+            pub fn drop_bytes(&self, drop_bytes: bool) {
+                self.proxy.faults.drop_bytes.store(drop_bytes, Ordering::SeqCst);
+            }
+
+            /// Wait before forwarding each chunk of bytes, a zero delay removes it
+            pub fn delay(&self, delay: Duration) {
+                *self.proxy.faults.delay.lock().unwrap_or_else(|e| e.into_inner()) = delay;
+            }
+
+            /// Stop forwarding bytes until [`Self::resume`]
+            pub fn stall(&self) {
+                self.proxy.faults.stalled.send_replace(true);
+            }
+
+            pub fn resume(&self) {
+                self.proxy.faults.stalled.send_replace(false);
+            }
+        }
+
+        impl Drop for $proxy {
+            fn drop(&mut self) {
+                self.proxy.stop();
+            }
+        }
+    };
+}
+
+/// Forwards TCP connections to an upstream address on behalf of a node, see the [module docs](self).
+#[derive(Debug)]
+pub struct TcpProxy {
+    addr: SocketAddr,
+    proxy: Arc<Proxy>,
+}
+
+impl TcpProxy {
+    /// Starts a proxy to `upstream` for the node `node`, listening on a free loopback port.
+    /// Its clients belong to no node, so only conditions set on `node` apply.
+    pub async fn start(main_controller: &MainController, node: &str, upstream: SocketAddr) -> io::Result<TcpProxy> {
+        TcpProxy::start_proxy(main_controller, None, node, upstream).await
+    }
+
+    /// Starts a proxy to `upstream` for the node `node`, whose clients are the node `client`
+    pub async fn start_link(main_controller: &MainController, client: &str, node: &str, upstream: SocketAddr) -> io::Result<TcpProxy> {
+        TcpProxy::start_proxy(main_controller, Some(client), node, upstream).await
+    }
+
+    async fn start_proxy(main_controller: &MainController, client: Option<&str>, node: &str, upstream: SocketAddr) -> io::Result<TcpProxy> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let proxy = Proxy::start(main_controller, client, node).await;
+
+        let accepting = proxy.clone();
+        proxy.spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                accepting.label("accept").await;
+                if !accepting.can_reach(Side::Client).await {
+                    continue;
+                }
+                let Ok(server) = TcpStream::connect(upstream).await else {
+                    continue;
+                };
+                let (client_read, client_write) = client.into_split();
+                let (server_read, server_write) = server.into_split();
+                accepting.spawn(pump(accepting.clone(), client_read, server_write, Side::Client));
+                accepting.spawn(pump(accepting.clone(), server_read, client_write, Side::Server));
+            }
+        });
+
+        Ok(TcpProxy { addr, proxy })
+    }
+}
+
+proxy_controls!(TcpProxy);
+
+/// Forwards bytes read from one side of a TCP connection to the other, until the reading side closes
+async fn pump(
+    proxy: Arc<Proxy>,
+    mut reader: tokio::net::tcp::OwnedReadHalf,
+    mut writer: tokio::net::tcp::OwnedWriteHalf,
+    side: Side,
+) {
+    let (from, to) = match side {
+        Side::Client => ("client", "server"),
+        Side::Server => ("server", "client"),
+    };
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let n = match reader.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        proxy.label(&format!("read from {}", from)).await;
+        if !proxy.forward(side, true).await {
+            continue;
+        }
+        proxy.label(&format!("write to {}", to)).await;
+        if writer.write_all(&buffer[..n]).await.is_err() {
+            break;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+/// Forwards UDP datagrams to an upstream address on behalf of a node, see the [module docs](self).
+///
+/// Each client address gets its own upstream socket, so replies find their way back to the client.
+#[derive(Debug)]
+pub struct UdpProxy {
+    addr: SocketAddr,
+    proxy: Arc<Proxy>,
+}
+
+impl UdpProxy {
+    /// Starts a proxy to `upstream` for the node `node`, bound to a free loopback port.
+    /// Its clients belong to no node, so only conditions set on `node` apply.
+    pub async fn start(main_controller: &MainController, node: &str, upstream: SocketAddr) -> io::Result<UdpProxy> {
+        UdpProxy::start_proxy(main_controller, None, node, upstream).await
+    }
+
+    /// Starts a proxy to `upstream` for the node `node`, whose clients are the node `client`
+    pub async fn start_link(main_controller: &MainController, client: &str, node: &str, upstream: SocketAddr) -> io::Result<UdpProxy> {
+        UdpProxy::start_proxy(main_controller, Some(client), node, upstream).await
+    }
+
+    async fn start_proxy(main_controller: &MainController, client: Option<&str>, node: &str, upstream: SocketAddr) -> io::Result<UdpProxy> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let addr = socket.local_addr()?;
+        let proxy = Proxy::start(main_controller, client, node).await;
+
+        let forwarding = proxy.clone();
+        proxy.spawn(async move {
+            let mut upstreams: HashMap<SocketAddr, Arc<UdpSocket>> = HashMap::new();
+            let mut buffer = vec![0; BUFFER_SIZE];
+            while let Ok((n, client)) = socket.recv_from(&mut buffer).await {
+                forwarding.label("recv from client").await;
+                // Datagrams are lost rather than stalled while the node cannot be reached
+                if !forwarding.forward(Side::Client, false).await {
+                    continue;
+                }
+                let server = match upstreams.get(&client) {
+                    Some(server) => server.clone(),
+                    None => {
+                        let Ok(server) = connect_udp(upstream).await else {
+                            continue;
+                        };
+                        upstreams.insert(client, server.clone());
+                        forwarding.spawn(reply(forwarding.clone(), server.clone(), socket.clone(), client));
+                        server
+                    },
+                };
+                forwarding.label("send to server").await;
+                let _ = server.send(&buffer[..n]).await;
+            }
+        });
+
+        Ok(UdpProxy { addr, proxy })
+    }
+}
+
+proxy_controls!(UdpProxy);
+
+async fn connect_udp(upstream: SocketAddr) -> io::Result<Arc<UdpSocket>> {
+    let server = UdpSocket::bind("127.0.0.1:0").await?;
+    server.connect(upstream).await?;
+    Ok(Arc::new(server))
+}
+
+/// Forwards datagrams from the upstream socket of a client back to the client
+async fn reply(proxy: Arc<Proxy>, server: Arc<UdpSocket>, socket: Arc<UdpSocket>, client: SocketAddr) {
+    let mut buffer = vec![0; BUFFER_SIZE];
+    while let Ok(n) = server.recv(&mut buffer).await {
+        proxy.label("recv from server").await;
+        if !proxy.forward(Side::Server, false).await {
+            continue;
+        }
+        proxy.label("send to client").await;
+        let _ = socket.send_to(&buffer[..n], client).await;
+    }
+}
@@ -0,0 +1,212 @@
+//! Happens-before tracking and data race reports.
+//!
+//! Every thread keeps a vector clock. A thread started with [`spawn!`](crate::spawn) inherits the clock of its parent,
+//! releasing a [`tokitest::sync`](crate::sync) lock, sending on a channel or a release store to an
+//! [`atomic`](crate::sync::atomic) publishes the clock of the thread, and taking the lock, receiving from the channel
+//! or an acquire load joins it into the clock of the next thread. Relaxed atomic operations do not order threads.
+//! Reads and writes of a [`Shared`](crate::sync::Shared) cell are checked against these clocks:
+//! two accesses from different threads conflict if at least one is a write and neither happens before the other.
+//!
+//! The test orders threads through its handshakes with them: once a thread stopped at the label a
+//! [`run_to!`](crate::run_to) ran it to, or returned for [`complete!`](crate::complete), everything it did happens before
+//! what the threads the test resumes afterwards do. Labels a `run_to!` only passes on its way do not order threads,
+//! so threads run together, e.g. with `tokio::join!(complete!("node1"), complete!("node2"))`, race unless the code
+//! under test orders them.
+//! The test fails when the controller is dropped, unless it took the reports with
+//! [`MainController::take_data_races`](crate::controller::MainController::take_data_races).
+//!
+//! Only accesses from spawned threads are tracked. Awaiting a `JoinHandle` is not seen as synchronization,
+//! and a channel orders every receive after every send that came before it, which can hide some races.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::label_spec::QualifiedLabel;
+use crate::trace::display_thread;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessKind::Read => write!(f, "read"),
+            AccessKind::Write => write!(f, "wrote"),
+        }
+    }
+}
+
+/// A read or write of a `Shared` cell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub thread: String,
+    pub kind: AccessKind,
+    /// The last label the thread passed before the access
+    pub after: Option<QualifiedLabel>,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", display_thread(&self.thread), self.kind)?;
+        if let Some(label) = &self.after {
+            write!(f, " after {}", label)?;
+        }
+        Ok(())
+    }
+}
+
+/// Two conflicting accesses of a `Shared` cell that are not ordered by happens-before
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataRace {
+    /// Name of the cell
+    pub shared: String,
+    pub earlier: Access,
+    pub later: Access,
+}
+
+impl fmt::Display for DataRace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "data race on {}:\n  {}\n  {}", self.shared, self.earlier, self.later)
+    }
+}
+
+type VectorClock = HashMap<String, u64>;
+
+fn join(into: &mut VectorClock, other: &VectorClock) {
+    for (thread, time) in other {
+        let entry = into.entry(thread.clone()).or_insert(0);
+        *entry = (*entry).max(*time);
+    }
+}
+
+/// An access, with the time of its thread when it happened
+#[derive(Debug)]
+struct Recorded {
+    time: u64,
+    access: Access,
+}
+
+/// The last write of a cell, and the reads since
+#[derive(Debug, Default)]
+struct Cell {
+    write: Option<Recorded>,
+    reads: HashMap<String, Recorded>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    threads: HashMap<String, VectorClock>,
+    /// Clocks published by locks and channels
+    objects: HashMap<u64, VectorClock>,
+    /// What the test saw of the threads it stopped
+    test: VectorClock,
+    cells: HashMap<u64, Cell>,
+    /// Cell and threads of the races found so far, so each is reported once
+    reported: HashSet<(u64, String, String)>,
+    races: Vec<DataRace>,
+}
+
+impl State {
+    fn clock(&mut self, thread: &str) -> &mut VectorClock {
+        self.threads.entry(thread.to_string()).or_insert_with(|| HashMap::from([(thread.to_string(), 1)]))
+    }
+
+    fn tick(&mut self, thread: &str) {
+        *self.clock(thread).entry(thread.to_string()).or_insert(0) += 1;
+    }
+}
+
+/// Vector clocks of every thread and synchronization object, shared by the `MainController` and every `ThreadController`
+#[derive(Debug, Default)]
+pub(crate) struct HappensBefore {
+    state: Mutex<State>,
+}
+
+impl HappensBefore {
+    pub(crate) fn new() -> HappensBefore {
+        HappensBefore::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Everything `parent` did so far happens before `child` starts
+    pub(crate) fn fork(&self, parent: &str, child: &str) {
+        if parent == child {
+            return;
+        }
+        let mut state = self.lock();
+        let mut clock = state.clock(parent).clone();
+        clock.insert(child.to_string(), 1);
+        state.threads.insert(child.to_string(), clock);
+        state.tick(parent);
+    }
+
+    /// Publishes the clock of `thread` in `object`, for the next thread that acquires it
+    pub(crate) fn release(&self, thread: &str, object: u64) {
+        let mut state = self.lock();
+        let clock = state.clock(thread).clone();
+        join(state.objects.entry(object).or_default(), &clock);
+        state.tick(thread);
+    }
+
+    pub(crate) fn acquire(&self, thread: &str, object: u64) {
+        let mut state = self.lock();
+        if let Some(published) = state.objects.get(&object).cloned() {
+            join(state.clock(thread), &published);
+        }
+    }
+
+    /// The test saw `thread` stop at a label, so everything it did so far happens before the threads the test resumes next
+    pub(crate) fn stopped(&self, thread: &str) {
+        let mut state = self.lock();
+        let clock = state.clock(thread).clone();
+        join(&mut state.test, &clock);
+        state.tick(thread);
+    }
+
+    /// The test resumes `thread`, after every stop it saw
+    pub(crate) fn resumed(&self, thread: &str) {
+        let mut state = self.lock();
+        let test = state.test.clone();
+        join(state.clock(thread), &test);
+    }
+
+    /// Checks an access of the cell `cell` against the conflicting accesses before it
+    pub(crate) fn access(&self, cell: u64, name: &str, access: Access) {
+        let mut state = self.lock();
+        let clock = state.clock(&access.thread).clone();
+        let happened_before = |recorded: &Recorded| clock.get(&recorded.access.thread).copied().unwrap_or(0) >= recorded.time;
+        let concurrent = |recorded: &&Recorded| recorded.access.thread != access.thread && !happened_before(recorded);
+
+        let entry = state.cells.entry(cell).or_default();
+        let mut conflicts: Vec<Access> = entry.write.iter().filter(concurrent).map(|write| write.access.clone()).collect();
+        let recorded = Recorded { time: clock[&access.thread], access: access.clone() };
+        match access.kind {
+            AccessKind::Read => {
+                entry.reads.insert(access.thread.clone(), recorded);
+            },
+            AccessKind::Write => {
+                conflicts.extend(entry.reads.values().filter(concurrent).map(|read| read.access.clone()));
+                entry.write = Some(recorded);
+                entry.reads.clear();
+            },
+        }
+
+        for earlier in conflicts {
+            if state.reported.insert((cell, earlier.thread.clone(), access.thread.clone())) {
+                state.races.push(DataRace { shared: name.to_string(), earlier, later: access.clone() });
+            }
+        }
+    }
+
+    /// Takes the data races found so far
+    pub(crate) fn take_races(&self) -> Vec<DataRace> {
+        std::mem::take(&mut self.lock().races)
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+/// Random numbers derived from the test seed.
+///
+/// SplitMix64 is implemented here rather than taken from a crate, so a seed replays the same run
+/// regardless of platform or dependency versions.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `[0, max]`, does not advance the generator when `max` is zero
+    pub fn duration_up_to(&mut self, max: Duration) -> Duration {
+        if max.is_zero() {
+            return Duration::ZERO;
+        }
+        let max_nanos = max.as_nanos().min(u64::MAX as u128 - 1) as u64;
+        Duration::from_nanos(self.next_u64() % (max_nanos + 1))
+    }
+}
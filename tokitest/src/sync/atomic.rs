@@ -0,0 +1,233 @@
+//! Atomics whose operations are checkpoints, for lock-free code.
+//!
+//! With the `tokitest` cfg flag, every load, store and read-modify-write of an atomic named `head` parks the thread
+//! at a label named after the operation, such as `load:head`, `store:head`, `compare_exchange:head` or `fetch_add:head`,
+//! before the operation runs. [`run_to!`](crate::run_to) can then stop a thread between any two operations
+//! of a lock-free algorithm without a [`label!`](crate::label) next to every atomic.
+//! The operations are `async` for this reason.
+//!
+//! The memory ordering decides what the operation synchronizes, see [`crate::race`]:
+//! a store with `Release`, `AcqRel` or `SeqCst` publishes the clock of the thread in the atomic,
+//! and a load with `Acquire`, `AcqRel` or `SeqCst` joins it. `Relaxed` operations do not order threads,
+//! so [`Shared`](super::Shared) state handed over with a relaxed flag is reported as a data race
+//! even on hardware where the handover happens to work. A load joins the clocks of every release store before it,
+//! not only the store it read from.
+//!
+//! Without the flag the operations run right away, and are ready the first time they are polled.
+//!
+//! ```rust,ignore
+//! use tokitest::sync::{atomic::AtomicUsize, Named};
+//!
+//! let head = Arc::new(AtomicUsize::named("head", 0));
+//! spawn!("node1", async {
+//!     let current = head.load(Ordering::Acquire).await;
+//!     head.compare_exchange(current, current + 1, Ordering::AcqRel, Ordering::Acquire).await
+//! });
+//!
+//! run_to!("node1", "compare_exchange:head").await;
+//! ```
+
+use std::fmt;
+use std::future::Future;
+pub use std::sync::atomic::Ordering;
+
+#[cfg(tokitest)]
+use std::sync::Arc;
+#[cfg(tokitest)]
+use crate::controller::ThreadController;
+#[cfg(tokitest)]
+use super::{next_id, LABEL_FUNCTION};
+use super::{caller_name, Named};
+
+/// The name and identity of an atomic
+#[derive(Debug)]
+struct Point {
+    name: String,
+    #[cfg(tokitest)]
+    id: u64,
+}
+
+/// The thread an operation is checked in, if any
+#[cfg(tokitest)]
+type Thread = Option<Arc<ThreadController>>;
+#[cfg(not(tokitest))]
+type Thread = ();
+
+impl Point {
+    fn new(name: &str) -> Point {
+        Point {
+            name: name.to_string(),
+            #[cfg(tokitest)]
+            id: next_id(),
+        }
+    }
+
+    /// Parks the current thread at `{operation}:{name}`, then publishes its clock in the atomic if `ordering` releases
+    #[cfg(tokitest)]
+    async fn before(&self, operation: &str, ordering: Ordering) -> Thread {
+        let thread = ThreadController::current();
+        if let Some(thread) = &thread {
+            thread.label(LABEL_FUNCTION, &format!("{}:{}", operation, self.name)).await;
+            if matches!(ordering, Ordering::Release | Ordering::AcqRel | Ordering::SeqCst) {
+                thread.sync_release(self.id);
+            }
+        }
+        thread
+    }
+
+    /// Joins the clock published in the atomic if `ordering` acquires
+    #[cfg(tokitest)]
+    fn after(&self, thread: Thread, ordering: Ordering) {
+        if let Some(thread) = thread {
+            if matches!(ordering, Ordering::Acquire | Ordering::AcqRel | Ordering::SeqCst) {
+                thread.sync_acquire(self.id);
+            }
+        }
+    }
+
+    #[cfg(not(tokitest))]
+    async fn before(&self, _operation: &str, _ordering: Ordering) -> Thread {}
+
+    #[cfg(not(tokitest))]
+    fn after(&self, _thread: Thread, _ordering: Ordering) {}
+}
+
+/// The types the atomics in this module hold, implemented by `atomic!` and nothing else
+trait Stored {}
+
+/// Moves a value into the future of an operation, so the future of an `AtomicPtr` is as `Send` as the `AtomicPtr` itself
+struct Value<T: Stored>(T);
+
+// SAFETY: `Stored` is private and only implemented for the booleans, integers and pointers stored in atomics,
+// which are sent as plain values: the pointers are never dereferenced
+unsafe impl<T: Stored> Send for Value<T> {}
+
+impl<T: Stored> Value<T> {
+    fn get(self) -> T {
+        self.0
+    }
+}
+
+/// The operations every atomic has, `$value` is the type it holds
+macro_rules! atomic {
+    ($atomic:ident $(<$t:ident>)?, $value:ty) => {
+        /// An atomic whose operations are checkpoints, see the [module docs](self)
+        pub struct $atomic $(<$t>)? {
+            inner: std::sync::atomic::$atomic $(<$t>)?,
+            point: Point,
+        }
+
+        impl $(<$t>)? $atomic $(<$t>)? {
+            /// Creates an atomic named after the caller's source location
+            #[track_caller]
+            pub fn new(value: $value) -> Self {
+                Self::named(&caller_name(), value)
+            }
+
+            pub fn name(&self) -> &str {
+                &self.point.name
+            }
+
+            pub fn load(&self, ordering: Ordering) -> impl Future<Output = $value> + Send + '_ {
+                async move {
+                    let thread = self.point.before("load", ordering).await;
+                    let value = self.inner.load(ordering);
+                    self.point.after(thread, ordering);
+                    value
+                }
+            }
+
+            pub fn store(&self, value: $value, ordering: Ordering) -> impl Future<Output = ()> + Send + '_ {
+                let value = Value(value);
+                async move {
+                    let thread = self.point.before("store", ordering).await;
+                    self.inner.store(value.get(), ordering);
+                    self.point.after(thread, ordering);
+                }
+            }
+
+            pub fn swap(&self, value: $value, ordering: Ordering) -> impl Future<Output = $value> + Send + '_ {
+                let value = Value(value);
+                async move {
+                    let thread = self.point.before("swap", ordering).await;
+                    let previous = self.inner.swap(value.get(), ordering);
+                    self.point.after(thread, ordering);
+                    previous
+                }
+            }
+
+            /// Passes `compare_exchange:{name}`, the orderings are those of [`std::sync::atomic`]
+            pub fn compare_exchange(
+                &self,
+                current: $value,
+                new: $value,
+                success: Ordering,
+                failure: Ordering,
+            ) -> impl Future<Output = Result<$value, $value>> + Send + '_ {
+                let (current, new) = (Value(current), Value(new));
+                async move {
+                    let thread = self.point.before("compare_exchange", success).await;
+                    let result = self.inner.compare_exchange(current.get(), new.get(), success, failure);
+                    self.point.after(thread, if result.is_ok() { success } else { failure });
+                    result
+                }
+            }
+
+            /// Exclusive access needs no checkpoint
+            pub fn get_mut(&mut self) -> &mut $value {
+                self.inner.get_mut()
+            }
+
+            pub fn into_inner(self) -> $value {
+                self.inner.into_inner()
+            }
+        }
+
+        impl $(<$t>)? Stored for $value {}
+
+        impl $(<$t>)? Named<$value> for $atomic $(<$t>)? {
+            fn named(name: &str, value: $value) -> Self {
+                $atomic { inner: std::sync::atomic::$atomic::new(value), point: Point::new(name) }
+            }
+        }
+
+        impl $(<$t>)? fmt::Debug for $atomic $(<$t>)? {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($atomic)).field("name", &self.point.name).field("value", &self.inner).finish()
+            }
+        }
+    };
+}
+
+/// Read-modify-write operations that return the previous value
+macro_rules! fetch {
+    ($atomic:ident, $value:ty, $($operation:ident),*) => {
+        impl $atomic {
+            $(
+                pub fn $operation(&self, value: $value, ordering: Ordering) -> impl Future<Output = $value> + Send + '_ {
+                    async move {
+                        let thread = self.point.before(stringify!($operation), ordering).await;
+                        let previous = self.inner.$operation(value, ordering);
+                        self.point.after(thread, ordering);
+                        previous
+                    }
+                }
+            )*
+        }
+    };
+}
+
+/// Atomic integers, with the same read-modify-write operations as in [`std::sync::atomic`]
+macro_rules! atomic_int {
+    ($($atomic:ident $int:ty),*) => {
+        $(
+            atomic!($atomic, $int);
+            fetch!($atomic, $int, fetch_add, fetch_sub, fetch_and, fetch_or, fetch_xor, fetch_max, fetch_min);
+        )*
+    };
+}
+
+atomic!(AtomicBool, bool);
+fetch!(AtomicBool, bool, fetch_and, fetch_or, fetch_xor);
+atomic_int!(AtomicUsize usize, AtomicIsize isize, AtomicU64 u64, AtomicI64 i64, AtomicU32 u32, AtomicI32 i32);
+atomic!(AtomicPtr<T>, *mut T);
@@ -0,0 +1,141 @@
+//! A [`tokio::sync::broadcast`] channel that places checkpoints, see the [module docs](super).
+//!
+//! Sending never waits, so `send:{name}` is recorded without parking.
+//! [`Receiver::recv`] passes `recv:{name}` before waiting and `received:{name}` after.
+
+pub use tokio::sync::broadcast::error;
+
+#[cfg(not(tokitest))]
+pub use tokio::sync::broadcast::{channel, Receiver, Sender};
+
+#[cfg(tokitest)]
+use std::sync::Arc;
+#[cfg(tokitest)]
+use error::{RecvError, SendError, TryRecvError};
+
+#[cfg(tokitest)]
+use super::{caller_name, channel::Channel};
+
+/// Creates a channel named `name`
+pub fn named_channel<T: Clone>(name: &str, capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::broadcast::channel(capacity);
+    #[cfg(tokitest)]
+    let (sender, receiver) = instrument(sender, receiver, Channel::new(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a channel named `name` that records its messages in the trace
+pub fn channel_with_payloads<T: Clone + std::fmt::Debug>(name: &str, capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::broadcast::channel(capacity);
+    #[cfg(tokitest)]
+    let (sender, receiver) = instrument(sender, receiver, Channel::with_payloads(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a channel named after the caller's source location
+#[cfg(tokitest)]
+#[track_caller]
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    named_channel(&caller_name(), capacity)
+}
+
+#[cfg(tokitest)]
+fn instrument<T>(
+    sender: tokio::sync::broadcast::Sender<T>,
+    receiver: tokio::sync::broadcast::Receiver<T>,
+    channel: Arc<Channel<T>>,
+) -> (Sender<T>, Receiver<T>) {
+    (Sender { inner: sender, channel: channel.clone() }, Receiver { inner: receiver, channel })
+}
+
+/// Sends to every receiver, recording `send:{name}` without parking
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Sender<T> {
+    inner: tokio::sync::broadcast::Sender<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender { inner: self.inner.clone(), channel: self.channel.clone() }
+    }
+}
+
+#[cfg(tokitest)]
+impl<T> Sender<T> {
+    /// Returns the number of receivers the value was sent to
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        self.channel.pass("send");
+        let payload = self.channel.payload(&value);
+        self.channel.release();
+        let receivers = self.inner.send(value)?;
+        self.channel.sent(payload);
+        Ok(receivers)
+    }
+
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver { inner: self.inner.subscribe(), channel: self.channel.clone() }
+    }
+
+    /// The number of values that not every receiver has received yet
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.inner.receiver_count()
+    }
+}
+
+/// Receives every value sent after it subscribed, see the [module docs](self)
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Receiver<T> {
+    inner: tokio::sync::broadcast::Receiver<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T: Clone> Receiver<T> {
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        self.channel.checkpoint("recv").await;
+        let message = self.channel.wait("recv", self.inner.recv()).await?;
+        self.channel.received(&message);
+        self.channel.checkpoint("received").await;
+        Ok(message)
+    }
+
+    /// Receives a value if one is queued, recording `received:{name}` without parking
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let message = self.inner.try_recv()?;
+        self.channel.received(&message);
+        self.channel.pass("received");
+        Ok(message)
+    }
+
+    pub fn resubscribe(&self) -> Receiver<T> {
+        Receiver { inner: self.inner.resubscribe(), channel: self.channel.clone() }
+    }
+}
+
+#[cfg(tokitest)]
+impl<T> Receiver<T> {
+    /// The number of values queued for this receiver
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
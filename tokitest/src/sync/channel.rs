@@ -0,0 +1,87 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::controller::ThreadController;
+use crate::trace::TraceEvent;
+use super::{next_id, LABEL_FUNCTION};
+
+/// The name of a channel, and how to show its messages if it was created with payloads
+#[derive(Debug)]
+pub(super) struct Channel<T> {
+    id: u64,
+    name: String,
+    payload: Option<fn(&T) -> String>,
+}
+
+impl<T> Channel<T> {
+    pub(super) fn new(name: &str) -> Arc<Channel<T>> {
+        Arc::new(Channel { id: next_id(), name: name.to_string(), payload: None })
+    }
+
+    pub(super) fn with_payloads(name: &str) -> Arc<Channel<T>> where T: fmt::Debug {
+        Arc::new(Channel { id: next_id(), name: name.to_string(), payload: Some(|message| format!("{:?}", message)) })
+    }
+
+    /// Parks the current thread at `{action}:{name}`, if there is one.
+    ///
+    /// The future does not borrow the channel, so it can be stored by futures such as `oneshot::Receiver`.
+    pub(super) fn checkpoint(&self, action: &str) -> impl Future<Output = ()> + Send + 'static {
+        let label = format!("{}:{}", action, self.name);
+        async move {
+            if let Some(thread) = ThreadController::current() {
+                thread.label(LABEL_FUNCTION, &label).await;
+            }
+        }
+    }
+
+    /// Awaits the future with the current thread, if there is one, blocked on `{action}:{name}`
+    pub(super) async fn wait<F: Future>(&self, action: &str, future: F) -> F::Output {
+        match ThreadController::current() {
+            Some(thread) => thread.wait_on(format!("{}:{}", action, self.name), future).await,
+            None => future.await,
+        }
+    }
+
+    /// Marks the current thread as blocked on `{action}:{name}` or not, for futures polled by hand
+    pub(super) fn set_blocked(&self, action: &str, blocked: bool) {
+        if let Some(thread) = ThreadController::current() {
+            thread.set_blocked_on(blocked.then(|| format!("{}:{}", action, self.name)));
+        }
+    }
+
+    /// Records `{action}:{name}` for the current thread without parking
+    pub(super) fn pass(&self, action: &str) {
+        if let Some(thread) = ThreadController::current() {
+            thread.pass(LABEL_FUNCTION, &format!("{}:{}", action, self.name));
+        }
+    }
+
+    /// Shows a message before it is moved into the channel, if the channel was created with payloads
+    pub(super) fn payload(&self, message: &T) -> Option<String> {
+        self.payload.map(|payload| payload(message))
+    }
+
+    pub(super) fn sent(&self, payload: Option<String>) {
+        if let (Some(payload), Some(thread)) = (payload, ThreadController::current()) {
+            thread.record(TraceEvent::Sent { thread: thread.id().to_string(), channel: self.name.clone(), payload });
+        }
+    }
+
+    /// Publishes the clock of the current thread before a message is sent, see [`crate::race`]
+    pub(super) fn release(&self) {
+        if let Some(thread) = ThreadController::current() {
+            thread.sync_release(self.id);
+        }
+    }
+
+    /// Orders the current thread after the senders of the channel, and records the message if it has payloads
+    pub(super) fn received(&self, message: &T) {
+        if let Some(thread) = ThreadController::current() {
+            thread.sync_acquire(self.id);
+        }
+        if let (Some(payload), Some(thread)) = (self.payload(message), ThreadController::current()) {
+            thread.record(TraceEvent::Received { thread: thread.id().to_string(), channel: self.name.clone(), payload });
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::TryLockError;
+
+use crate::controller::ThreadController;
+use super::{caller_name, next_id, Named, LABEL_FUNCTION};
+
+/// The name and identity of a lock, shared with the guards so owned guards can record their release
+#[derive(Debug)]
+struct LockId {
+    id: u64,
+    name: String,
+}
+
+impl LockId {
+    fn new(name: String) -> Arc<LockId> {
+        Arc::new(LockId { id: next_id(), name })
+    }
+}
+
+/// Passes `lock requested` and `lock acquired` around waiting for the lock, in the current thread if there is one,
+/// which is blocked on `lock:{name}` while it waits
+async fn acquire<G>(lock_id: &Arc<LockId>, lock: impl Future<Output = G>) -> (G, Release) {
+    let thread = ThreadController::current();
+    if let Some(thread) = &thread {
+        thread.lock_requested(lock_id.id, &lock_id.name);
+        thread.label(LABEL_FUNCTION, &format!("lock requested:{}", lock_id.name)).await;
+    }
+    let guard = match &thread {
+        Some(thread) => thread.wait_on(format!("lock:{}", lock_id.name), lock).await,
+        None => lock.await,
+    };
+    if let Some(thread) = &thread {
+        thread.sync_acquire(lock_id.id);
+        thread.lock_acquired(lock_id.id, &lock_id.name);
+        thread.label(LABEL_FUNCTION, &format!("lock acquired:{}", lock_id.name)).await;
+    }
+    (guard, Release { lock_id: lock_id.clone(), thread })
+}
+
+/// Records `lock acquired` without parking, for locks taken without waiting
+fn try_acquire<G, E>(lock_id: &Arc<LockId>, guard: Result<G, E>) -> Result<(G, Release), E> {
+    let guard = guard?;
+    Ok((guard, acquired(lock_id, ThreadController::current())))
+}
+
+/// Records `lock requested` and `lock acquired` without parking around blocking for the lock,
+/// since a blocking call cannot wait for the test
+fn blocking_acquire<G>(lock_id: &Arc<LockId>, lock: impl FnOnce() -> G) -> (G, Release) {
+    let thread = ThreadController::current();
+    if let Some(thread) = &thread {
+        thread.lock_requested(lock_id.id, &lock_id.name);
+        thread.pass(LABEL_FUNCTION, &format!("lock requested:{}", lock_id.name));
+    }
+    let guard = lock();
+    (guard, acquired(lock_id, thread))
+}
+
+fn acquired(lock_id: &Arc<LockId>, thread: Option<Arc<ThreadController>>) -> Release {
+    if let Some(thread) = &thread {
+        thread.sync_acquire(lock_id.id);
+        thread.lock_acquired(lock_id.id, &lock_id.name);
+        thread.pass(LABEL_FUNCTION, &format!("lock acquired:{}", lock_id.name));
+    }
+    Release { lock_id: lock_id.clone(), thread }
+}
+
+/// Records `lock released` when a guard is dropped, before the lock itself is released
+/// so the next thread to take it sees the clock of this one
+struct Release {
+    lock_id: Arc<LockId>,
+    thread: Option<Arc<ThreadController>>,
+}
+
+impl Drop for Release {
+    fn drop(&mut self) {
+        if let Some(thread) = &self.thread {
+            thread.sync_release(self.lock_id.id);
+            thread.lock_released(self.lock_id.id);
+            thread.pass(LABEL_FUNCTION, &format!("lock released:{}", self.lock_id.name));
+        }
+    }
+}
+
+/// A [`tokio::sync::Mutex`] that places checkpoints, see the [module docs](super).
+///
+/// Blocking locks record `lock requested` and `lock acquired` without parking.
+/// The tokio mutex is kept in an `Arc` that owned guards share, so unlike tokio's there is no `const_new`,
+/// a `static` can hold one in a [`LazyLock`](std::sync::LazyLock).
+pub struct Mutex<T> {
+    id: Arc<LockId>,
+    inner: Arc<tokio::sync::Mutex<T>>,
+}
+
+impl<T> Mutex<T> {
+    /// Creates a mutex named after the caller's source location
+    #[track_caller]
+    pub fn new(value: T) -> Mutex<T> {
+        Mutex::named(&caller_name(), value)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.id.name
+    }
+
+    /// Locks the mutex, passing `lock requested` and `lock acquired`
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        let (inner, release) = acquire(&self.id, self.inner.lock()).await;
+        MutexGuard { _release: release, inner, lock: self }
+    }
+
+    /// Locks the mutex from outside the runtime, recording `lock requested` and `lock acquired` without parking
+    pub fn blocking_lock(&self) -> MutexGuard<'_, T> {
+        let (inner, release) = blocking_acquire(&self.id, || self.inner.blocking_lock());
+        MutexGuard { _release: release, inner, lock: self }
+    }
+
+    /// Locks the mutex if it is free, recording `lock acquired` without parking
+    pub fn try_lock(&self) -> Result<MutexGuard<'_, T>, TryLockError> {
+        let (inner, release) = try_acquire(&self.id, self.inner.try_lock())?;
+        Ok(MutexGuard { _release: release, inner, lock: self })
+    }
+
+    /// Locks the mutex like [`Mutex::lock`], the guard holds the `Arc`
+    pub async fn lock_owned(self: Arc<Self>) -> OwnedMutexGuard<T> {
+        let (inner, release) = acquire(&self.id, self.inner.clone().lock_owned()).await;
+        OwnedMutexGuard { _release: release, inner, lock: self }
+    }
+
+    /// Locks the mutex like [`Mutex::blocking_lock`], the guard holds the `Arc`
+    pub fn blocking_lock_owned(self: Arc<Self>) -> OwnedMutexGuard<T> {
+        let (inner, release) = blocking_acquire(&self.id, || self.inner.clone().blocking_lock_owned());
+        OwnedMutexGuard { _release: release, inner, lock: self }
+    }
+
+    /// Locks the mutex like [`Mutex::try_lock`], the guard holds the `Arc`
+    pub fn try_lock_owned(self: Arc<Self>) -> Result<OwnedMutexGuard<T>, TryLockError> {
+        let (inner, release) = try_acquire(&self.id, self.inner.clone().try_lock_owned())?;
+        Ok(OwnedMutexGuard { _release: release, inner, lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        // Owned guards hold the `Arc` of this mutex, so none is left when it is borrowed mutably
+        Arc::get_mut(&mut self.inner).expect("owned guards hold the mutex").get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        Arc::into_inner(self.inner).expect("owned guards hold the mutex").into_inner()
+    }
+}
+
+impl<T> Named<T> for Mutex<T> {
+    fn named(name: &str, value: T) -> Self {
+        Mutex { id: LockId::new(name.to_string()), inner: Arc::new(tokio::sync::Mutex::new(value)) }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    #[track_caller]
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex").field("name", &self.id.name).field("inner", &self.inner).finish()
+    }
+}
+
+/// Holds a [`Mutex`], `lock released` is recorded when it is dropped
+pub struct MutexGuard<'a, T> {
+    // Dropped before the inner guard
+    _release: Release,
+    inner: tokio::sync::MutexGuard<'a, T>,
+    lock: &'a Mutex<T>,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    /// Maps the guard to a part of the locked value, `lock released` is recorded when the mapped guard is dropped
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(this: Self, f: F) -> MappedMutexGuard<'a, U> {
+        MappedMutexGuard { _release: this._release, inner: tokio::sync::MutexGuard::map(this.inner, f) }
+    }
+
+    pub fn try_map<U, F: FnOnce(&mut T) -> Option<&mut U>>(this: Self, f: F) -> Result<MappedMutexGuard<'a, U>, Self> {
+        match tokio::sync::MutexGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(MappedMutexGuard { _release: this._release, inner }),
+            Err(inner) => Err(MutexGuard { _release: this._release, inner, lock: this.lock }),
+        }
+    }
+
+    pub fn mutex(this: &Self) -> &'a Mutex<T> {
+        this.lock
+    }
+}
+
+/// Holds part of a [`Mutex`] after [`MutexGuard::map`], `lock released` is recorded when it is dropped
+pub struct MappedMutexGuard<'a, T> {
+    _release: Release,
+    inner: tokio::sync::MappedMutexGuard<'a, T>,
+}
+
+impl<'a, T> MappedMutexGuard<'a, T> {
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(this: Self, f: F) -> MappedMutexGuard<'a, U> {
+        MappedMutexGuard { _release: this._release, inner: tokio::sync::MappedMutexGuard::map(this.inner, f) }
+    }
+
+    pub fn try_map<U, F: FnOnce(&mut T) -> Option<&mut U>>(this: Self, f: F) -> Result<MappedMutexGuard<'a, U>, Self> {
+        match tokio::sync::MappedMutexGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(MappedMutexGuard { _release: this._release, inner }),
+            Err(inner) => Err(MappedMutexGuard { _release: this._release, inner }),
+        }
+    }
+}
+
+/// Holds a [`Mutex`] and the `Arc` it is in, `lock released` is recorded when it is dropped
+pub struct OwnedMutexGuard<T> {
+    _release: Release,
+    inner: tokio::sync::OwnedMutexGuard<T>,
+    lock: Arc<Mutex<T>>,
+}
+
+impl<T> OwnedMutexGuard<T> {
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(this: Self, f: F) -> OwnedMappedMutexGuard<T, U> {
+        OwnedMappedMutexGuard { _release: this._release, inner: tokio::sync::OwnedMutexGuard::map(this.inner, f), _lock: this.lock }
+    }
+
+    pub fn try_map<U, F: FnOnce(&mut T) -> Option<&mut U>>(this: Self, f: F) -> Result<OwnedMappedMutexGuard<T, U>, Self> {
+        match tokio::sync::OwnedMutexGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(OwnedMappedMutexGuard { _release: this._release, inner, _lock: this.lock }),
+            Err(inner) => Err(OwnedMutexGuard { _release: this._release, inner, lock: this.lock }),
+        }
+    }
+
+    pub fn mutex(this: &Self) -> &Arc<Mutex<T>> {
+        &this.lock
+    }
+}
+
+/// Holds part of a [`Mutex`] after [`OwnedMutexGuard::map`], `lock released` is recorded when it is dropped
+pub struct OwnedMappedMutexGuard<T, U = T> {
+    _release: Release,
+    inner: tokio::sync::OwnedMappedMutexGuard<T, U>,
+    _lock: Arc<Mutex<T>>,
+}
+
+impl<T, U> OwnedMappedMutexGuard<T, U> {
+    pub fn map<V, F: FnOnce(&mut U) -> &mut V>(this: Self, f: F) -> OwnedMappedMutexGuard<T, V> {
+        OwnedMappedMutexGuard { _release: this._release, inner: tokio::sync::OwnedMappedMutexGuard::map(this.inner, f), _lock: this._lock }
+    }
+
+    pub fn try_map<V, F: FnOnce(&mut U) -> Option<&mut V>>(this: Self, f: F) -> Result<OwnedMappedMutexGuard<T, V>, Self> {
+        match tokio::sync::OwnedMappedMutexGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(OwnedMappedMutexGuard { _release: this._release, inner, _lock: this._lock }),
+            Err(inner) => Err(OwnedMappedMutexGuard { _release: this._release, inner, _lock: this._lock }),
+        }
+    }
+}
+
+/// A [`tokio::sync::RwLock`] that places checkpoints, see the [module docs](super).
+///
+/// Reads and writes pass the same labels. Blocking locks record `lock requested` and `lock acquired` without parking.
+/// Like [`Mutex`], it keeps the tokio lock in an `Arc` and has no `const_new`.
+pub struct RwLock<T> {
+    id: Arc<LockId>,
+    inner: Arc<tokio::sync::RwLock<T>>,
+}
+
+impl<T> RwLock<T> {
+    /// Creates a lock named after the caller's source location
+    #[track_caller]
+    pub fn new(value: T) -> RwLock<T> {
+        RwLock::named(&caller_name(), value)
+    }
+
+    /// Creates a lock named after the caller's source location that `max_reads` readers can hold at once
+    #[track_caller]
+    pub fn with_max_readers(value: T, max_reads: u32) -> RwLock<T> {
+        RwLock { id: LockId::new(caller_name()), inner: Arc::new(tokio::sync::RwLock::with_max_readers(value, max_reads)) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.id.name
+    }
+
+    /// Locks for reading, passing `lock requested` and `lock acquired`
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        let (inner, release) = acquire(&self.id, self.inner.read()).await;
+        RwLockReadGuard { _release: release, inner }
+    }
+
+    /// Locks for writing, passing `lock requested` and `lock acquired`
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let (inner, release) = acquire(&self.id, self.inner.write()).await;
+        RwLockWriteGuard { _release: release, inner }
+    }
+
+    /// Locks for reading from outside the runtime, recording `lock requested` and `lock acquired` without parking
+    pub fn blocking_read(&self) -> RwLockReadGuard<'_, T> {
+        let (inner, release) = blocking_acquire(&self.id, || self.inner.blocking_read());
+        RwLockReadGuard { _release: release, inner }
+    }
+
+    /// Locks for writing from outside the runtime, recording `lock requested` and `lock acquired` without parking
+    pub fn blocking_write(&self) -> RwLockWriteGuard<'_, T> {
+        let (inner, release) = blocking_acquire(&self.id, || self.inner.blocking_write());
+        RwLockWriteGuard { _release: release, inner }
+    }
+
+    /// Locks for reading if no writer holds the lock, recording `lock acquired` without parking
+    pub fn try_read(&self) -> Result<RwLockReadGuard<'_, T>, TryLockError> {
+        let (inner, release) = try_acquire(&self.id, self.inner.try_read())?;
+        Ok(RwLockReadGuard { _release: release, inner })
+    }
+
+    /// Locks for writing if the lock is free, recording `lock acquired` without parking
+    pub fn try_write(&self) -> Result<RwLockWriteGuard<'_, T>, TryLockError> {
+        let (inner, release) = try_acquire(&self.id, self.inner.try_write())?;
+        Ok(RwLockWriteGuard { _release: release, inner })
+    }
+
+    /// Locks for reading like [`RwLock::read`], the guard holds the `Arc`
+    pub async fn read_owned(self: Arc<Self>) -> OwnedRwLockReadGuard<T> {
+        let (inner, release) = acquire(&self.id, self.inner.clone().read_owned()).await;
+        OwnedRwLockReadGuard { _release: release, inner, lock: self }
+    }
+
+    /// Locks for writing like [`RwLock::write`], the guard holds the `Arc`
+    pub async fn write_owned(self: Arc<Self>) -> OwnedRwLockWriteGuard<T> {
+        let (inner, release) = acquire(&self.id, self.inner.clone().write_owned()).await;
+        OwnedRwLockWriteGuard { _release: release, inner, lock: self }
+    }
+
+    /// Locks for reading like [`RwLock::try_read`], the guard holds the `Arc`
+    pub fn try_read_owned(self: Arc<Self>) -> Result<OwnedRwLockReadGuard<T>, TryLockError> {
+        let (inner, release) = try_acquire(&self.id, self.inner.clone().try_read_owned())?;
+        Ok(OwnedRwLockReadGuard { _release: release, inner, lock: self })
+    }
+
+    /// Locks for writing like [`RwLock::try_write`], the guard holds the `Arc`
+    pub fn try_write_owned(self: Arc<Self>) -> Result<OwnedRwLockWriteGuard<T>, TryLockError> {
+        let (inner, release) = try_acquire(&self.id, self.inner.clone().try_write_owned())?;
+        Ok(OwnedRwLockWriteGuard { _release: release, inner, lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        // Owned guards hold the `Arc` of this lock, so none is left when it is borrowed mutably
+        Arc::get_mut(&mut self.inner).expect("owned guards hold the lock").get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        Arc::into_inner(self.inner).expect("owned guards hold the lock").into_inner()
+    }
+}
+
+impl<T> Named<T> for RwLock<T> {
+    fn named(name: &str, value: T) -> Self {
+        RwLock { id: LockId::new(name.to_string()), inner: Arc::new(tokio::sync::RwLock::new(value)) }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    #[track_caller]
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RwLock").field("name", &self.id.name).field("inner", &self.inner).finish()
+    }
+}
+
+/// Holds a [`RwLock`] for reading, `lock released` is recorded when it is dropped
+pub struct RwLockReadGuard<'a, T> {
+    _release: Release,
+    inner: tokio::sync::RwLockReadGuard<'a, T>,
+}
+
+impl<'a, T> RwLockReadGuard<'a, T> {
+    pub fn map<U, F: FnOnce(&T) -> &U>(this: Self, f: F) -> RwLockReadGuard<'a, U> {
+        RwLockReadGuard { _release: this._release, inner: tokio::sync::RwLockReadGuard::map(this.inner, f) }
+    }
+
+    pub fn try_map<U, F: FnOnce(&T) -> Option<&U>>(this: Self, f: F) -> Result<RwLockReadGuard<'a, U>, Self> {
+        match tokio::sync::RwLockReadGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(RwLockReadGuard { _release: this._release, inner }),
+            Err(inner) => Err(RwLockReadGuard { _release: this._release, inner }),
+        }
+    }
+}
+
+/// Holds a [`RwLock`] for writing, `lock released` is recorded when it is dropped
+pub struct RwLockWriteGuard<'a, T> {
+    _release: Release,
+    inner: tokio::sync::RwLockWriteGuard<'a, T>,
+}
+
+impl<'a, T> RwLockWriteGuard<'a, T> {
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(this: Self, f: F) -> RwLockMappedWriteGuard<'a, U> {
+        RwLockMappedWriteGuard { _release: this._release, inner: tokio::sync::RwLockWriteGuard::map(this.inner, f) }
+    }
+
+    pub fn try_map<U, F: FnOnce(&mut T) -> Option<&mut U>>(this: Self, f: F) -> Result<RwLockMappedWriteGuard<'a, U>, Self> {
+        match tokio::sync::RwLockWriteGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(RwLockMappedWriteGuard { _release: this._release, inner }),
+            Err(inner) => Err(RwLockWriteGuard { _release: this._release, inner }),
+        }
+    }
+
+    pub fn into_mapped(this: Self) -> RwLockMappedWriteGuard<'a, T> {
+        RwLockMappedWriteGuard { _release: this._release, inner: tokio::sync::RwLockWriteGuard::into_mapped(this.inner) }
+    }
+
+    /// Keeps holding the lock for reading, without recording a release
+    pub fn downgrade(self) -> RwLockReadGuard<'a, T> {
+        RwLockReadGuard { _release: self._release, inner: self.inner.downgrade() }
+    }
+
+    pub fn downgrade_map<U, F: FnOnce(&T) -> &U>(this: Self, f: F) -> RwLockReadGuard<'a, U> {
+        RwLockReadGuard { _release: this._release, inner: tokio::sync::RwLockWriteGuard::downgrade_map(this.inner, f) }
+    }
+
+    pub fn try_downgrade_map<U, F: FnOnce(&T) -> Option<&U>>(this: Self, f: F) -> Result<RwLockReadGuard<'a, U>, Self> {
+        match tokio::sync::RwLockWriteGuard::try_downgrade_map(this.inner, f) {
+            Ok(inner) => Ok(RwLockReadGuard { _release: this._release, inner }),
+            Err(inner) => Err(RwLockWriteGuard { _release: this._release, inner }),
+        }
+    }
+}
+
+/// Holds part of a [`RwLock`] for writing after [`RwLockWriteGuard::map`], `lock released` is recorded when it is dropped
+pub struct RwLockMappedWriteGuard<'a, T> {
+    _release: Release,
+    inner: tokio::sync::RwLockMappedWriteGuard<'a, T>,
+}
+
+impl<'a, T> RwLockMappedWriteGuard<'a, T> {
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(this: Self, f: F) -> RwLockMappedWriteGuard<'a, U> {
+        RwLockMappedWriteGuard { _release: this._release, inner: tokio::sync::RwLockMappedWriteGuard::map(this.inner, f) }
+    }
+
+    pub fn try_map<U, F: FnOnce(&mut T) -> Option<&mut U>>(this: Self, f: F) -> Result<RwLockMappedWriteGuard<'a, U>, Self> {
+        match tokio::sync::RwLockMappedWriteGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(RwLockMappedWriteGuard { _release: this._release, inner }),
+            Err(inner) => Err(RwLockMappedWriteGuard { _release: this._release, inner }),
+        }
+    }
+}
+
+/// Holds a [`RwLock`] for reading and the `Arc` it is in, `lock released` is recorded when it is dropped
+pub struct OwnedRwLockReadGuard<T, U = T> {
+    _release: Release,
+    inner: tokio::sync::OwnedRwLockReadGuard<T, U>,
+    lock: Arc<RwLock<T>>,
+}
+
+impl<T, U> OwnedRwLockReadGuard<T, U> {
+    pub fn map<V, F: FnOnce(&U) -> &V>(this: Self, f: F) -> OwnedRwLockReadGuard<T, V> {
+        OwnedRwLockReadGuard { _release: this._release, inner: tokio::sync::OwnedRwLockReadGuard::map(this.inner, f), lock: this.lock }
+    }
+
+    pub fn try_map<V, F: FnOnce(&U) -> Option<&V>>(this: Self, f: F) -> Result<OwnedRwLockReadGuard<T, V>, Self> {
+        match tokio::sync::OwnedRwLockReadGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(OwnedRwLockReadGuard { _release: this._release, inner, lock: this.lock }),
+            Err(inner) => Err(OwnedRwLockReadGuard { _release: this._release, inner, lock: this.lock }),
+        }
+    }
+
+    pub fn rwlock(this: &Self) -> &Arc<RwLock<T>> {
+        &this.lock
+    }
+}
+
+/// Holds a [`RwLock`] for writing and the `Arc` it is in, `lock released` is recorded when it is dropped
+pub struct OwnedRwLockWriteGuard<T> {
+    _release: Release,
+    inner: tokio::sync::OwnedRwLockWriteGuard<T>,
+    lock: Arc<RwLock<T>>,
+}
+
+impl<T> OwnedRwLockWriteGuard<T> {
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(this: Self, f: F) -> OwnedRwLockMappedWriteGuard<T, U> {
+        OwnedRwLockMappedWriteGuard { _release: this._release, inner: tokio::sync::OwnedRwLockWriteGuard::map(this.inner, f), lock: this.lock }
+    }
+
+    pub fn try_map<U, F: FnOnce(&mut T) -> Option<&mut U>>(this: Self, f: F) -> Result<OwnedRwLockMappedWriteGuard<T, U>, Self> {
+        match tokio::sync::OwnedRwLockWriteGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(OwnedRwLockMappedWriteGuard { _release: this._release, inner, lock: this.lock }),
+            Err(inner) => Err(OwnedRwLockWriteGuard { _release: this._release, inner, lock: this.lock }),
+        }
+    }
+
+    pub fn into_mapped(this: Self) -> OwnedRwLockMappedWriteGuard<T> {
+        OwnedRwLockMappedWriteGuard { _release: this._release, inner: tokio::sync::OwnedRwLockWriteGuard::into_mapped(this.inner), lock: this.lock }
+    }
+
+    /// Keeps holding the lock for reading, without recording a release
+    pub fn downgrade(self) -> OwnedRwLockReadGuard<T> {
+        OwnedRwLockReadGuard { _release: self._release, inner: self.inner.downgrade(), lock: self.lock }
+    }
+
+    pub fn downgrade_map<U, F: FnOnce(&T) -> &U>(this: Self, f: F) -> OwnedRwLockReadGuard<T, U> {
+        OwnedRwLockReadGuard { _release: this._release, inner: tokio::sync::OwnedRwLockWriteGuard::downgrade_map(this.inner, f), lock: this.lock }
+    }
+
+    pub fn try_downgrade_map<U, F: FnOnce(&T) -> Option<&U>>(this: Self, f: F) -> Result<OwnedRwLockReadGuard<T, U>, Self> {
+        match tokio::sync::OwnedRwLockWriteGuard::try_downgrade_map(this.inner, f) {
+            Ok(inner) => Ok(OwnedRwLockReadGuard { _release: this._release, inner, lock: this.lock }),
+            Err(inner) => Err(OwnedRwLockWriteGuard { _release: this._release, inner, lock: this.lock }),
+        }
+    }
+
+    pub fn rwlock(this: &Self) -> &Arc<RwLock<T>> {
+        &this.lock
+    }
+}
+
+/// Holds part of a [`RwLock`] for writing after [`OwnedRwLockWriteGuard::map`], `lock released` is recorded when it is dropped
+pub struct OwnedRwLockMappedWriteGuard<T, U = T> {
+    _release: Release,
+    inner: tokio::sync::OwnedRwLockMappedWriteGuard<T, U>,
+    lock: Arc<RwLock<T>>,
+}
+
+impl<T, U> OwnedRwLockMappedWriteGuard<T, U> {
+    pub fn map<V, F: FnOnce(&mut U) -> &mut V>(this: Self, f: F) -> OwnedRwLockMappedWriteGuard<T, V> {
+        OwnedRwLockMappedWriteGuard { _release: this._release, inner: tokio::sync::OwnedRwLockMappedWriteGuard::map(this.inner, f), lock: this.lock }
+    }
+
+    pub fn try_map<V, F: FnOnce(&mut U) -> Option<&mut V>>(this: Self, f: F) -> Result<OwnedRwLockMappedWriteGuard<T, V>, Self> {
+        match tokio::sync::OwnedRwLockMappedWriteGuard::try_map(this.inner, f) {
+            Ok(inner) => Ok(OwnedRwLockMappedWriteGuard { _release: this._release, inner, lock: this.lock }),
+            Err(inner) => Err(OwnedRwLockMappedWriteGuard { _release: this._release, inner, lock: this.lock }),
+        }
+    }
+
+    pub fn rwlock(this: &Self) -> &Arc<RwLock<T>> {
+        &this.lock
+    }
+}
+
+/// Guards deref to the locked value like the tokio guards they wrap
+macro_rules! guard_deref {
+    ([$($generics:tt)*] $guard:ty => $target:ident, DerefMut) => {
+        guard_deref!([$($generics)*] $guard => $target);
+
+        impl<$($generics)*> DerefMut for $guard {
+            fn deref_mut(&mut self) -> &mut $target {
+                &mut self.inner
+            }
+        }
+    };
+    ([$($generics:tt)*] $guard:ty => $target:ident) => {
+        impl<$($generics)*> Deref for $guard {
+            type Target = $target;
+
+            fn deref(&self) -> &$target {
+                &self.inner
+            }
+        }
+
+        impl<$($generics)*> fmt::Debug for $guard where $target: fmt::Debug {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&**self, f)
+            }
+        }
+
+        impl<$($generics)*> fmt::Display for $guard where $target: fmt::Display {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&**self, f)
+            }
+        }
+    };
+}
+
+guard_deref!([T] MutexGuard<'_, T> => T, DerefMut);
+guard_deref!([T] MappedMutexGuard<'_, T> => T, DerefMut);
+guard_deref!([T] OwnedMutexGuard<T> => T, DerefMut);
+guard_deref!([T, U] OwnedMappedMutexGuard<T, U> => U, DerefMut);
+guard_deref!([T] RwLockReadGuard<'_, T> => T);
+guard_deref!([T] RwLockWriteGuard<'_, T> => T, DerefMut);
+guard_deref!([T] RwLockMappedWriteGuard<'_, T> => T, DerefMut);
+guard_deref!([T, U] OwnedRwLockReadGuard<T, U> => U);
+guard_deref!([T] OwnedRwLockWriteGuard<T> => T, DerefMut);
+guard_deref!([T, U] OwnedRwLockMappedWriteGuard<T, U> => U, DerefMut);
@@ -0,0 +1,104 @@
+//! Drop-in replacements for `tokio::sync` types that place checkpoints automatically.
+//!
+//! With the `tokitest` cfg flag, taking a [`Mutex`] or [`RwLock`] passes the labels `lock requested:{name}`
+//! before waiting for the lock and `lock acquired:{name}` once it is held, so [`run_to!`](crate::run_to)
+//! can stop a thread right before it takes a lock, or while it holds it. `lock released:{name}` is recorded
+//! in the trace when the guard is dropped, but the thread cannot park there since guards are dropped synchronously.
+//! Owned, mapped and downgraded guards hold the lock until they are dropped like the guards they came from,
+//! and `blocking_lock`, `blocking_read` and `blocking_write` record their labels without parking.
+//! The labels are qualified with `tokitest::sync`, and only placed in threads started with
+//! [`spawn!`](crate::spawn) or [`spawn_join_set!`](crate::spawn_join_set).
+//!
+//! The channels in [`mpsc`], [`oneshot`], [`broadcast`] and [`watch`] pass `send:{name}` before sending,
+//! `recv:{name}` before waiting for a message and `received:{name}` once it arrived. Sends that do not wait,
+//! such as `UnboundedSender::send`, record `send:{name}` in the trace without parking.
+//! Channels created with `channel_with_payloads` also record the messages themselves in the trace.
+//!
+//! The atomics in [`atomic`] pass a label named after each operation, such as `load:{name}` or `fetch_add:{name}`.
+//!
+//! While a thread waits for a lock or a message, it is blocked on `lock:{name}`, `recv:{name}` or `send:{name}`,
+//! which [`assert_blocked!`](crate::assert_blocked) and [`ThreadState::Blocked`](crate::controller::ThreadState::Blocked) report.
+//!
+//! [`Shared`] holds state the code under test shares between threads without one of these locks.
+//! Its accesses are checked for data races against the happens-before order the locks, channels and the test's
+//! handshakes with the threads create, see [`crate::race`].
+//!
+//! Without the flag these are the `tokio::sync` types themselves, and `Shared` is a plain cell.
+//!
+//! ```rust,ignore
+//! use tokitest::sync::{Mutex, Named};
+//!
+//! let accounts = Arc::new(Mutex::named("accounts", HashMap::new()));
+//! spawn!("node1", async {
+//!     accounts.lock().await.insert(1, 100);
+//! });
+//!
+//! run_to!("node1", "lock requested:accounts").await;
+//! ```
+
+pub mod atomic;
+pub mod broadcast;
+#[cfg(tokitest)]
+mod channel;
+#[cfg(tokitest)]
+mod lock;
+pub mod mpsc;
+pub mod oneshot;
+mod shared;
+pub mod watch;
+
+#[cfg(tokitest)]
+pub use lock::{
+    MappedMutexGuard, Mutex, MutexGuard, OwnedMappedMutexGuard, OwnedMutexGuard, OwnedRwLockMappedWriteGuard,
+    OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard,
+};
+
+#[cfg(not(tokitest))]
+pub use tokio::sync::{
+    MappedMutexGuard, Mutex, MutexGuard, OwnedMappedMutexGuard, OwnedMutexGuard, OwnedRwLockMappedWriteGuard,
+    OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard,
+};
+
+pub use shared::Shared;
+
+/// Labels placed by the instrumented types are qualified with this path, e.g. `tokitest::sync::lock acquired:accounts`
+#[cfg(tokitest)]
+const LABEL_FUNCTION: &str = module_path!();
+
+/// Tells synchronization primitives apart in the lock-order graph and the happens-before clocks, whatever their names
+#[cfg(tokitest)]
+static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+#[cfg(tokitest)]
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Names a primitive created with `new` after the caller's source location
+#[track_caller]
+fn caller_name() -> String {
+    let location = std::panic::Location::caller();
+    format!("{}:{}", location.file(), location.line())
+}
+
+/// Creates a synchronization primitive with a name for its checkpoints.
+///
+/// Without the `tokitest` cfg flag the name is ignored. Primitives created with `new` are named
+/// after the source location they were created at, e.g. `src/bank.rs:12`.
+pub trait Named<T> {
+    fn named(name: &str, value: T) -> Self;
+}
+
+#[cfg(not(tokitest))]
+impl<T> Named<T> for Mutex<T> {
+    fn named(_name: &str, value: T) -> Self {
+        Mutex::new(value)
+    }
+}
+
+#[cfg(not(tokitest))]
+impl<T> Named<T> for RwLock<T> {
+    fn named(_name: &str, value: T) -> Self {
+        RwLock::new(value)
+    }
+}
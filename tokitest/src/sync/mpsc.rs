@@ -0,0 +1,253 @@
+//! A [`tokio::sync::mpsc`] channel that places checkpoints, see the [module docs](super).
+//!
+//! The queue depth is available from the test body through [`Receiver::len`],
+//! or `max_capacity() - capacity()` of a bounded [`Sender`].
+//!
+//! ```rust,ignore
+//! let (jobs, mut queue) = tokitest::sync::mpsc::named_channel("jobs", 16);
+//! spawn!("consumer", async move {
+//!     while let Some(job) = queue.recv().await {
+//!         // ...
+//!     }
+//! });
+//!
+//! run_to!("consumer", "recv:jobs").await;
+//! ```
+
+pub use tokio::sync::mpsc::error;
+
+#[cfg(not(tokitest))]
+pub use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+
+#[cfg(tokitest)]
+use std::sync::Arc;
+#[cfg(tokitest)]
+use error::{SendError, TryRecvError, TrySendError};
+
+#[cfg(tokitest)]
+use super::{caller_name, channel::Channel};
+
+/// Creates a bounded channel named `name`
+pub fn named_channel<T>(name: &str, buffer: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::mpsc::channel(buffer);
+    #[cfg(tokitest)]
+    let (sender, receiver) = bounded(sender, receiver, Channel::new(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a bounded channel named `name` that records its messages in the trace
+pub fn channel_with_payloads<T: std::fmt::Debug>(name: &str, buffer: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::mpsc::channel(buffer);
+    #[cfg(tokitest)]
+    let (sender, receiver) = bounded(sender, receiver, Channel::with_payloads(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates an unbounded channel named `name`
+pub fn named_unbounded_channel<T>(name: &str) -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    #[cfg(tokitest)]
+    let (sender, receiver) = unbounded(sender, receiver, Channel::new(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates an unbounded channel named `name` that records its messages in the trace
+pub fn unbounded_channel_with_payloads<T: std::fmt::Debug>(name: &str) -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    #[cfg(tokitest)]
+    let (sender, receiver) = unbounded(sender, receiver, Channel::with_payloads(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a bounded channel named after the caller's source location
+#[cfg(tokitest)]
+#[track_caller]
+pub fn channel<T>(buffer: usize) -> (Sender<T>, Receiver<T>) {
+    named_channel(&caller_name(), buffer)
+}
+
+/// Creates an unbounded channel named after the caller's source location
+#[cfg(tokitest)]
+#[track_caller]
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    named_unbounded_channel(&caller_name())
+}
+
+#[cfg(tokitest)]
+fn bounded<T>(
+    sender: tokio::sync::mpsc::Sender<T>,
+    receiver: tokio::sync::mpsc::Receiver<T>,
+    channel: Arc<Channel<T>>,
+) -> (Sender<T>, Receiver<T>) {
+    (Sender { inner: sender, channel: channel.clone() }, Receiver { inner: receiver, channel })
+}
+
+#[cfg(tokitest)]
+fn unbounded<T>(
+    sender: tokio::sync::mpsc::UnboundedSender<T>,
+    receiver: tokio::sync::mpsc::UnboundedReceiver<T>,
+    channel: Arc<Channel<T>>,
+) -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    (UnboundedSender { inner: sender, channel: channel.clone() }, UnboundedReceiver { inner: receiver, channel })
+}
+
+/// Sends to a bounded channel, passing `send:{name}` before waiting for capacity
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Sender<T> {
+    inner: tokio::sync::mpsc::Sender<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender { inner: self.inner.clone(), channel: self.channel.clone() }
+    }
+}
+
+#[cfg(tokitest)]
+impl<T> Sender<T> {
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.checkpoint("send").await;
+        let Ok(permit) = self.channel.wait("send", self.inner.reserve()).await else {
+            return Err(SendError(value));
+        };
+        self.channel.release();
+        self.channel.sent(self.channel.payload(&value));
+        permit.send(value);
+        Ok(())
+    }
+
+    /// Sends if the channel has capacity, recording `send:{name}` without parking
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.pass("send");
+        let payload = self.channel.payload(&value);
+        self.channel.release();
+        self.inner.try_send(value)?;
+        self.channel.sent(payload);
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    pub fn max_capacity(&self) -> usize {
+        self.inner.max_capacity()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    pub async fn closed(&self) {
+        self.inner.closed().await
+    }
+}
+
+/// Receives from a bounded channel, passing `recv:{name}` before waiting and `received:{name}` after
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Receiver<T> {
+    inner: tokio::sync::mpsc::Receiver<T>,
+    channel: Arc<Channel<T>>,
+}
+
+/// Sends to an unbounded channel, recording `send:{name}` without parking
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct UnboundedSender<T> {
+    inner: tokio::sync::mpsc::UnboundedSender<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        UnboundedSender { inner: self.inner.clone(), channel: self.channel.clone() }
+    }
+}
+
+#[cfg(tokitest)]
+impl<T> UnboundedSender<T> {
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.pass("send");
+        let payload = self.channel.payload(&value);
+        self.channel.release();
+        self.inner.send(value)?;
+        self.channel.sent(payload);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    pub async fn closed(&self) {
+        self.inner.closed().await
+    }
+}
+
+/// Receives from an unbounded channel, passing `recv:{name}` before waiting and `received:{name}` after
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct UnboundedReceiver<T> {
+    inner: tokio::sync::mpsc::UnboundedReceiver<T>,
+    channel: Arc<Channel<T>>,
+}
+
+/// Both receivers have the same methods
+#[cfg(tokitest)]
+macro_rules! receiver {
+    ($receiver:ident) => {
+        impl<T> $receiver<T> {
+            /// Waits for a message, None once every sender is dropped
+            pub async fn recv(&mut self) -> Option<T> {
+                self.channel.checkpoint("recv").await;
+                let message = self.channel.wait("recv", self.inner.recv()).await?;
+                self.channel.received(&message);
+                self.channel.checkpoint("received").await;
+                Some(message)
+            }
+
+            /// Receives a message if one is queued, recording `received:{name}` without parking
+            pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+                let message = self.inner.try_recv()?;
+                self.channel.received(&message);
+                self.channel.pass("received");
+                Ok(message)
+            }
+
+            /// The number of queued messages
+            pub fn len(&self) -> usize {
+                self.inner.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.inner.is_empty()
+            }
+
+            pub fn is_closed(&self) -> bool {
+                self.inner.is_closed()
+            }
+
+            pub fn close(&mut self) {
+                self.inner.close()
+            }
+        }
+    };
+}
+
+#[cfg(tokitest)]
+receiver!(Receiver);
+#[cfg(tokitest)]
+receiver!(UnboundedReceiver);
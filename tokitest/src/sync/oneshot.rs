@@ -0,0 +1,177 @@
+//! A [`tokio::sync::oneshot`] channel that places checkpoints, see the [module docs](super).
+//!
+//! Sending never waits, so `send:{name}` is recorded without parking.
+//! Awaiting the [`Receiver`] passes `recv:{name}` before waiting and `received:{name}` after.
+
+pub use tokio::sync::oneshot::error;
+
+#[cfg(not(tokitest))]
+pub use tokio::sync::oneshot::{channel, Receiver, Sender};
+
+#[cfg(tokitest)]
+use std::future::Future;
+#[cfg(tokitest)]
+use std::pin::Pin;
+#[cfg(tokitest)]
+use std::sync::Arc;
+#[cfg(tokitest)]
+use std::task::{Context, Poll};
+#[cfg(tokitest)]
+use error::{RecvError, TryRecvError};
+
+#[cfg(tokitest)]
+use super::{caller_name, channel::Channel};
+
+/// Creates a channel named `name`
+pub fn named_channel<T>(name: &str) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    #[cfg(tokitest)]
+    let (sender, receiver) = instrument(sender, receiver, Channel::new(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a channel named `name` that records its message in the trace
+pub fn channel_with_payloads<T: std::fmt::Debug>(name: &str) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    #[cfg(tokitest)]
+    let (sender, receiver) = instrument(sender, receiver, Channel::with_payloads(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a channel named after the caller's source location
+#[cfg(tokitest)]
+#[track_caller]
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    named_channel(&caller_name())
+}
+
+#[cfg(tokitest)]
+fn instrument<T>(
+    sender: tokio::sync::oneshot::Sender<T>,
+    receiver: tokio::sync::oneshot::Receiver<T>,
+    channel: Arc<Channel<T>>,
+) -> (Sender<T>, Receiver<T>) {
+    (
+        Sender { inner: sender, channel: channel.clone() },
+        Receiver { inner: receiver, channel, state: State::Start },
+    )
+}
+
+/// Sends the value of the channel, recording `send:{name}` without parking
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Sender<T> {
+    inner: tokio::sync::oneshot::Sender<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T> Sender<T> {
+    /// Returns the value back if the receiver was dropped
+    pub fn send(self, value: T) -> Result<(), T> {
+        self.channel.pass("send");
+        let payload = self.channel.payload(&value);
+        self.channel.release();
+        self.inner.send(value)?;
+        self.channel.sent(payload);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    pub async fn closed(&mut self) {
+        self.inner.closed().await
+    }
+}
+
+#[cfg(tokitest)]
+type Checkpoint = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Where an awaited [`Receiver`] is, between its two checkpoints
+#[cfg(tokitest)]
+enum State<T> {
+    Start,
+    Requested(Checkpoint),
+    Waiting,
+    Received(Checkpoint, Option<T>),
+}
+
+/// Receives the value of the channel when awaited, see the [module docs](self)
+#[cfg(tokitest)]
+pub struct Receiver<T> {
+    inner: tokio::sync::oneshot::Receiver<T>,
+    channel: Arc<Channel<T>>,
+    state: State<T>,
+}
+
+#[cfg(tokitest)]
+impl<T> Receiver<T> {
+    /// Receives the value if it was sent, recording `received:{name}` without parking
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let message = self.inner.try_recv()?;
+        self.channel.received(&message);
+        self.channel.pass("received");
+        Ok(message)
+    }
+
+    pub fn close(&mut self) {
+        self.inner.close()
+    }
+}
+
+#[cfg(tokitest)]
+impl<T: std::fmt::Debug> std::fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Receiver").field("inner", &self.inner).field("channel", &self.channel).finish()
+    }
+}
+
+// A receiver dropped while it waits leaves its thread blocked on nothing
+#[cfg(tokitest)]
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if let State::Waiting = self.state {
+            self.channel.set_blocked("recv", false);
+        }
+    }
+}
+
+// None of the fields are structurally pinned: the tokio receiver and the boxed checkpoints are Unpin
+#[cfg(tokitest)]
+impl<T> Unpin for Receiver<T> {}
+
+#[cfg(tokitest)]
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                State::Start => this.state = State::Requested(Box::pin(this.channel.checkpoint("recv"))),
+                State::Requested(checkpoint) => {
+                    std::task::ready!(checkpoint.as_mut().poll(cx));
+                    this.state = State::Waiting;
+                },
+                State::Waiting => {
+                    let polled = Pin::new(&mut this.inner).poll(cx);
+                    this.channel.set_blocked("recv", polled.is_pending());
+                    let message = std::task::ready!(polled)?;
+                    this.channel.received(&message);
+                    this.state = State::Received(Box::pin(this.channel.checkpoint("received")), Some(message));
+                },
+                State::Received(checkpoint, message) => {
+                    std::task::ready!(checkpoint.as_mut().poll(cx));
+                    let message = message.take().expect("Receiver polled after completion");
+                    return Poll::Ready(Ok(message));
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! A value shared between threads without a lock of the code under test, see [`crate::race`].
+
+use std::fmt;
+
+#[cfg(tokitest)]
+use crate::controller::ThreadController;
+#[cfg(tokitest)]
+use crate::race::AccessKind;
+#[cfg(tokitest)]
+use super::next_id;
+use super::{caller_name, Named};
+
+/// A value whose reads and writes are checked for data races.
+///
+/// Wrap state that the code under test shares between tasks and expects to be protected by its own
+/// synchronization, e.g. a field written by one task after sending on a channel and read by another after receiving.
+/// Every access is atomic, but with the `tokitest` cfg flag two conflicting accesses from different threads
+/// that are not ordered by a [`tokitest::sync`](super) lock or channel are reported as a data race.
+///
+/// ```rust,ignore
+/// let balance = Arc::new(Shared::named("balance", 100));
+/// spawn!("node1", async {
+///     balance.write(|balance| *balance -= 10);
+/// });
+/// ```
+pub struct Shared<T> {
+    value: std::sync::Mutex<T>,
+    name: String,
+    #[cfg(tokitest)]
+    id: u64,
+}
+
+impl<T> Shared<T> {
+    /// Creates a cell named after the caller's source location
+    #[track_caller]
+    pub fn new(value: T) -> Shared<T> {
+        Shared::named(&caller_name(), value)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reads the value through `f`
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        #[cfg(tokitest)]
+        self.access(AccessKind::Read);
+        f(&self.value.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Writes the value through `f`
+    pub fn write<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        #[cfg(tokitest)]
+        self.access(AccessKind::Write);
+        f(&mut self.value.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn set(&self, value: T) {
+        self.write(|current| *current = value);
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[cfg(tokitest)]
+    fn access(&self, kind: AccessKind) {
+        if let Some(thread) = ThreadController::current() {
+            thread.shared_access(self.id, &self.name, kind);
+        }
+    }
+}
+
+impl<T: Clone> Shared<T> {
+    pub fn get(&self) -> T {
+        self.read(T::clone)
+    }
+}
+
+impl<T> Named<T> for Shared<T> {
+    fn named(name: &str, value: T) -> Self {
+        Shared {
+            value: std::sync::Mutex::new(value),
+            name: name.to_string(),
+            #[cfg(tokitest)]
+            id: next_id(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared").field("name", &self.name).field("value", &*self.value.lock().unwrap_or_else(|e| e.into_inner())).finish()
+    }
+}
@@ -0,0 +1,137 @@
+//! A [`tokio::sync::watch`] channel that places checkpoints, see the [module docs](super).
+//!
+//! Sending never waits, so `send:{name}` is recorded without parking.
+//! [`Receiver::changed`] passes `recv:{name}` before waiting and `received:{name}` after.
+
+pub use tokio::sync::watch::{error, Ref};
+
+#[cfg(not(tokitest))]
+pub use tokio::sync::watch::{channel, Receiver, Sender};
+
+#[cfg(tokitest)]
+use std::sync::Arc;
+#[cfg(tokitest)]
+use error::{RecvError, SendError};
+
+#[cfg(tokitest)]
+use super::{caller_name, channel::Channel};
+
+/// Creates a channel named `name`
+pub fn named_channel<T>(name: &str, init: T) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::watch::channel(init);
+    #[cfg(tokitest)]
+    let (sender, receiver) = instrument(sender, receiver, Channel::new(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a channel named `name` that records its values in the trace
+pub fn channel_with_payloads<T: std::fmt::Debug>(name: &str, init: T) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = tokio::sync::watch::channel(init);
+    #[cfg(tokitest)]
+    let (sender, receiver) = instrument(sender, receiver, Channel::with_payloads(name));
+    #[cfg(not(tokitest))]
+    let _ = name;
+    (sender, receiver)
+}
+
+/// Creates a channel named after the caller's source location
+#[cfg(tokitest)]
+#[track_caller]
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    named_channel(&caller_name(), init)
+}
+
+#[cfg(tokitest)]
+fn instrument<T>(
+    sender: tokio::sync::watch::Sender<T>,
+    receiver: tokio::sync::watch::Receiver<T>,
+    channel: Arc<Channel<T>>,
+) -> (Sender<T>, Receiver<T>) {
+    (Sender { inner: sender, channel: channel.clone() }, Receiver { inner: receiver, channel })
+}
+
+/// Replaces the value of the channel, recording `send:{name}` without parking
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Sender<T> {
+    inner: tokio::sync::watch::Sender<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T> Sender<T> {
+    /// Fails if every receiver was dropped
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.pass("send");
+        let payload = self.channel.payload(&value);
+        self.channel.release();
+        self.inner.send(value)?;
+        self.channel.sent(payload);
+        Ok(())
+    }
+
+    /// Replaces the value even if there are no receivers, and returns the previous one
+    pub fn send_replace(&self, value: T) -> T {
+        self.channel.pass("send");
+        self.channel.release();
+        self.channel.sent(self.channel.payload(&value));
+        self.inner.send_replace(value)
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver { inner: self.inner.subscribe(), channel: self.channel.clone() }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.inner.receiver_count()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+}
+
+/// Watches the value of the channel, see the [module docs](self)
+#[cfg(tokitest)]
+#[derive(Debug)]
+pub struct Receiver<T> {
+    inner: tokio::sync::watch::Receiver<T>,
+    channel: Arc<Channel<T>>,
+}
+
+#[cfg(tokitest)]
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver { inner: self.inner.clone(), channel: self.channel.clone() }
+    }
+}
+
+#[cfg(tokitest)]
+impl<T> Receiver<T> {
+    /// Waits until the value changes, the new value is recorded as received
+    pub async fn changed(&mut self) -> Result<(), RecvError> {
+        self.channel.checkpoint("recv").await;
+        self.channel.wait("recv", self.inner.changed()).await?;
+        self.channel.received(&self.inner.borrow());
+        self.channel.checkpoint("received").await;
+        Ok(())
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        self.inner.borrow_and_update()
+    }
+
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        self.inner.has_changed()
+    }
+}
@@ -0,0 +1,203 @@
+//! Temporal assertions over the labels of a trace.
+//!
+//! A [`Formula`] is checked against the states of a trace: after each label a thread reaches, every thread is
+//! *at* the last label it reached, until it reaches another or crashes. [`always`] and [`never`] hold if a proposition
+//! holds in every state or in none, [`eventually`] if it holds in at least one. Propositions are built from
+//! [`at`], [`before`], [`not`] and [`Prop::and`]. Labels are given by name or qualified name, as in [`run_to!`](crate::run_to).
+//!
+//! [`assert_trace!`](crate::assert_trace) checks a formula against the trace so far, with a shorter syntax:
+//!
+//! ```rust,ignore
+//! assert_trace!(never("node1" at "commit" while "node2" at "commit")).await;
+//! assert_trace!(always(before("lock acquired:log", "write"))).await;
+//! assert_trace!(eventually("node2", "done")).await;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::label_spec::QualifiedLabel;
+use crate::trace::TraceEvent;
+
+/// How many trace events before a violation are reported
+const RECENT_EVENTS: usize = 10;
+
+/// A proposition about one state of a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prop {
+    /// `thread` is at `label`: it reached the label and no other label since
+    At { thread: String, label: String },
+    /// Both hold in the same state, `a while b` in [`assert_trace!`](crate::assert_trace)
+    And(Box<Prop>, Box<Prop>),
+    Not(Box<Prop>),
+    /// Holds unless the thread that just reached `then` never reached `first` before
+    Before { first: String, then: String },
+}
+
+pub fn at(thread: &str, label: &str) -> Prop {
+    Prop::At { thread: thread.to_string(), label: label.to_string() }
+}
+
+pub fn not(prop: Prop) -> Prop {
+    Prop::Not(Box::new(prop))
+}
+
+pub fn before(first: &str, then: &str) -> Prop {
+    Prop::Before { first: first.to_string(), then: then.to_string() }
+}
+
+impl Prop {
+    pub fn and(self, other: Prop) -> Prop {
+        Prop::And(Box::new(self), Box::new(other))
+    }
+
+    fn holds(&self, state: &State) -> bool {
+        match self {
+            Prop::At { thread, label } => state.at.get(thread).is_some_and(|at| matches(at, label)),
+            Prop::And(a, b) => a.holds(state) && b.holds(state),
+            Prop::Not(prop) => !prop.holds(state),
+            Prop::Before { first, then } => match &state.reached {
+                // The label just reached is the last one in the thread's history
+                Some((thread, label)) if matches(label, then) => {
+                    let history = &state.history[thread];
+                    history[..history.len() - 1].iter().any(|earlier| matches(earlier, first))
+                },
+                _ => true,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Prop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Prop::At { thread, label } => write!(f, "{:?} at {:?}", thread, label),
+            Prop::And(a, b) => write!(f, "{} while {}", a, b),
+            Prop::Not(prop) => write!(f, "not({})", prop),
+            Prop::Before { first, then } => write!(f, "before({:?}, {:?})", first, then),
+        }
+    }
+}
+
+/// A property of a whole trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Formula {
+    Always(Prop),
+    Never(Prop),
+    Eventually(Prop),
+}
+
+pub fn always(prop: Prop) -> Formula {
+    Formula::Always(prop)
+}
+
+pub fn never(prop: Prop) -> Formula {
+    Formula::Never(prop)
+}
+
+pub fn eventually(prop: Prop) -> Formula {
+    Formula::Eventually(prop)
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Formula::Always(prop) => write!(f, "always({})", prop),
+            Formula::Never(prop) => write!(f, "never({})", prop),
+            Formula::Eventually(prop) => write!(f, "eventually({})", prop),
+        }
+    }
+}
+
+/// Where every thread is after an event of the trace
+#[derive(Default)]
+struct State {
+    at: HashMap<String, QualifiedLabel>,
+    /// Every label each thread reached so far
+    history: HashMap<String, Vec<QualifiedLabel>>,
+    /// The thread that reached a label in the last event, and the label
+    reached: Option<(String, QualifiedLabel)>,
+}
+
+impl State {
+    /// Moves to the state after `event`, returns false for events that move no thread
+    fn apply(&mut self, event: &TraceEvent) -> bool {
+        match event {
+            TraceEvent::Label { thread, label } => {
+                self.at.insert(thread.clone(), label.clone());
+                self.history.entry(thread.clone()).or_default().push(label.clone());
+                self.reached = Some((thread.clone(), label.clone()));
+                true
+            },
+            TraceEvent::Crash { thread } => {
+                self.at.remove(thread);
+                self.reached = None;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+fn matches(label: &QualifiedLabel, name: &str) -> bool {
+    label.name() == name || label.qualified() == name
+}
+
+impl Formula {
+    /// Checks the formula against the events of a trace, such as [`MainController::trace`](crate::controller::MainController::trace)
+    pub fn check(&self, events: &[TraceEvent]) -> Result<(), TraceViolation> {
+        let mut state = State::default();
+        for (index, event) in events.iter().enumerate() {
+            if !state.apply(event) {
+                continue;
+            }
+            let violated = match self {
+                Formula::Always(prop) => !prop.holds(&state),
+                Formula::Never(prop) => prop.holds(&state),
+                Formula::Eventually(prop) => {
+                    if prop.holds(&state) {
+                        return Ok(());
+                    }
+                    false
+                },
+            };
+            if violated {
+                return Err(TraceViolation::new(self, Some(index), &events[..=index]));
+            }
+        }
+        match self {
+            Formula::Eventually(_) => Err(TraceViolation::new(self, None, events)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A trace that does not satisfy a [`Formula`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceViolation {
+    pub formula: Formula,
+    /// Index of the event that broke the formula, None if an `eventually` never held
+    pub index: Option<usize>,
+    /// The last events of the trace, up to the one that broke the formula
+    pub recent: Vec<TraceEvent>,
+}
+
+impl TraceViolation {
+    fn new(formula: &Formula, index: Option<usize>, events: &[TraceEvent]) -> TraceViolation {
+        let recent = events[events.len().saturating_sub(RECENT_EVENTS)..].to_vec();
+        TraceViolation { formula: formula.clone(), index, recent }
+    }
+}
+
+impl fmt::Display for TraceViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "trace violates {} at event {}, recent trace:", self.formula, index)?,
+            None => write!(f, "trace violates {}, it never held, recent trace:", self.formula)?,
+        }
+        for event in &self.recent {
+            write!(f, "\n  {}", event)?;
+        }
+        Ok(())
+    }
+}
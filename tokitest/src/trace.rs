@@ -0,0 +1,118 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::label_spec::QualifiedLabel;
+use crate::network::NetworkFault;
+
+/// Something that happened during a tokitest, in the order the controller observed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TraceEvent {
+    /// A thread reached a label
+    Label { thread: String, label: QualifiedLabel },
+    /// A thread made a [`network_call!`](crate::network_call), `to` is the declared destination
+    NetworkCall { thread: String, to: Option<String>, fate: NetworkFate },
+    /// A fault scheduled with [`on_label!`](crate::on_label) was applied when `thread` reached `label`
+    Fault { thread: String, label: QualifiedLabel, fault: NetworkFault },
+    /// A thread was aborted by [`crash!`](crate::crash)
+    Crash { thread: String },
+    /// The controller's virtual clock moved forward `by`, to `elapsed` since the test started
+    TimeAdvanced { by: Duration, elapsed: Duration },
+    /// A thread sent a message on a `tokitest::sync` channel created with payloads
+    Sent { thread: String, channel: String, payload: String },
+    /// A thread received a message from a `tokitest::sync` channel created with payloads
+    Received { thread: String, channel: String, payload: String },
+}
+
+/// What the controller decided to do with a [`network_call!`](crate::network_call)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkFate {
+    /// The caller or destination is isolated, or the link is cut: the error callback runs
+    Unreachable,
+    /// The call was lost at random: the error callback runs
+    Lost,
+    /// The real call runs after the delay
+    Delivered { delay: Duration },
+    /// The real call runs twice after the delay, the caller gets the result of the second
+    Duplicated { delay: Duration },
+    /// The call was held with [`hold!`](crate::hold) and the test dropped it: the error callback runs
+    Dropped,
+}
+
+impl fmt::Display for NetworkFate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkFate::Unreachable => write!(f, "unreachable"),
+            NetworkFate::Lost => write!(f, "lost"),
+            NetworkFate::Delivered { delay } => write!(f, "delivered after {:?}", delay),
+            NetworkFate::Duplicated { delay } => write!(f, "duplicated after {:?}", delay),
+            NetworkFate::Dropped => write!(f, "dropped"),
+        }
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Label { thread, label } => write!(f, "{}: {}", display_thread(thread), label),
+            TraceEvent::NetworkCall { thread, to: Some(to), fate } => {
+                write!(f, "{}: network call to {} {}", display_thread(thread), to, fate)
+            },
+            TraceEvent::NetworkCall { thread, to: None, fate } => {
+                write!(f, "{}: network call {}", display_thread(thread), fate)
+            },
+            TraceEvent::Fault { thread, label, fault } => {
+                write!(f, "{}: {} at {}", display_thread(thread), fault, label)
+            },
+            TraceEvent::Crash { thread } => write!(f, "{}: crashed", display_thread(thread)),
+            TraceEvent::TimeAdvanced { by, elapsed } => write!(f, "time: advanced {:?} to {:?}", by, elapsed),
+            TraceEvent::Sent { thread, channel, payload } => {
+                write!(f, "{}: sent {} on {}", display_thread(thread), payload, channel)
+            },
+            TraceEvent::Received { thread, channel, payload } => {
+                write!(f, "{}: received {} on {}", display_thread(thread), payload, channel)
+            },
+        }
+    }
+}
+
+/// The root test thread has an empty ID
+pub(crate) fn display_thread(id: &str) -> &str {
+    if id.is_empty() { "<main>" } else { id }
+}
+
+/// Append-only log of [`TraceEvent`]s, shared by the `MainController` and every `ThreadController`
+#[derive(Debug, Default)]
+pub struct Trace {
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace { events: Mutex::new(Vec::new()) }
+    }
+
+    pub fn record(&self, event: TraceEvent) {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The last `count` events
+    pub fn recent(&self, count: usize) -> Vec<TraceEvent> {
+        let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events[events.len().saturating_sub(count)..].to_vec()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in self.events() {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
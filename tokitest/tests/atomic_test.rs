@@ -0,0 +1,139 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokitest::{spawn, run_to, complete};
+use tokitest::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use tokitest::sync::{Named, Shared};
+
+async fn racy_increment(counter: Arc<AtomicUsize>) {
+    let value = counter.load(Ordering::SeqCst).await;
+    counter.store(value + 1, Ordering::SeqCst).await;
+}
+
+async fn cas_increment(counter: Arc<AtomicUsize>) {
+    let mut value = counter.load(Ordering::Acquire).await;
+    while let Err(current) = counter.compare_exchange(value, value + 1, Ordering::AcqRel, Ordering::Acquire).await {
+        value = current;
+    }
+}
+
+#[tokitest::test]
+async fn test_lost_update_between_load_and_store() {
+    let counter = Arc::new(AtomicUsize::named("counter", 0));
+
+    for node in ["node1", "node2"] {
+        let counter = counter.clone();
+        spawn!(node, async {
+            racy_increment(counter).await;
+        });
+    }
+
+    // Both threads load 0 before either stores
+    run_to!("node1", "store:counter").await;
+    run_to!("node2", "store:counter").await;
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(1, counter.load(Ordering::SeqCst).await);
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| event.contains("tokitest::sync"))
+        .collect();
+    assert_eq!(vec![
+        "node1: tokitest::sync::load:counter",
+        "node1: tokitest::sync::store:counter",
+        "node2: tokitest::sync::load:counter",
+        "node2: tokitest::sync::store:counter",
+    ], trace);
+}
+
+#[tokitest::test]
+async fn test_compare_exchange_retries() {
+    let counter = Arc::new(AtomicUsize::named("counter", 0));
+
+    for node in ["node1", "node2"] {
+        let counter = counter.clone();
+        spawn!(node, async {
+            cas_increment(counter).await;
+        });
+    }
+
+    // node2 compares against the value it loaded before node1 incremented it, fails and retries
+    run_to!("node1", "compare_exchange:counter").await;
+    run_to!("node2", "compare_exchange:counter").await;
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(2, counter.load(Ordering::SeqCst).await);
+
+    let node2 = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| event.starts_with("node2: tokitest::sync"))
+        .count();
+    assert_eq!(3, node2);
+}
+
+#[tokitest::test]
+async fn test_release_acquire_handover() {
+    let data = Arc::new(Shared::named("data", 0));
+    let ready = Arc::new(AtomicBool::named("ready", false));
+
+    let (dc, rc) = (data.clone(), ready.clone());
+    spawn!("producer", async move {
+        dc.set(42);
+        rc.store(true, Ordering::Release).await;
+    });
+    let (dc, rc) = (data.clone(), ready.clone());
+    spawn!("consumer", async move {
+        assert!(rc.load(Ordering::Acquire).await);
+        assert_eq!(42, dc.get());
+    });
+
+    // The consumer loads once the producer stored, without waiting for the test to see the producer return
+    tokio::join!(complete!("producer"), complete!("consumer"));
+    assert!(tokitest_main_controller.take_data_races().await.is_empty());
+}
+
+#[tokitest::test]
+async fn test_relaxed_handover_races() {
+    let data = Arc::new(Shared::named("data", 0));
+    let ready = Arc::new(AtomicBool::named("ready", false));
+
+    let (dc, rc) = (data.clone(), ready.clone());
+    spawn!("producer", async move {
+        dc.set(42);
+        rc.store(true, Ordering::Relaxed).await;
+    });
+    let (dc, rc) = (data.clone(), ready.clone());
+    spawn!("consumer", async move {
+        assert!(rc.load(Ordering::Relaxed).await);
+        assert_eq!(42, dc.get());
+    });
+
+    tokio::join!(complete!("producer"), complete!("consumer"));
+    let races = tokitest_main_controller.take_data_races().await;
+    assert_eq!(1, races.len());
+    assert_eq!(
+        "data race on data:\n  \
+        producer wrote after atomic_test::test_relaxed_handover_races::INIT\n  \
+        consumer read after atomic_test::test_relaxed_handover_races::INIT",
+        races[0].to_string()
+    );
+}
+
+#[tokitest::test]
+async fn test_atomic_ptr() {
+    let mut first = 1;
+    let mut second = 2;
+    let head = Arc::new(AtomicPtr::named("head", &mut first as *mut i32));
+
+    let (hc, new) = (head.clone(), &mut second as *mut i32 as usize);
+    spawn!("node1", async move {
+        let current = hc.load(Ordering::Acquire).await;
+        assert!(hc.compare_exchange(current, new as *mut i32, Ordering::AcqRel, Ordering::Acquire).await.is_ok());
+    });
+
+    run_to!("node1", "compare_exchange:head").await;
+    assert_eq!(&mut first as *mut i32, head.load(Ordering::Acquire).await);
+    complete!("node1").await;
+    assert_eq!(&mut second as *mut i32, head.load(Ordering::Acquire).await);
+}
@@ -0,0 +1,104 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::RwLock;
+use tokio::time::{sleep, Duration, Instant};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokitest::{label, spawn, call, run_to, complete};
+use tokitest::controller::MainController;
+
+#[tokitest::testable]
+async fn push_after_sleep(value: i32, data: Arc<RwLock<Vec<i32>>>) {
+    label!("before sleep");
+    sleep(Duration::from_secs(60)).await;
+    data.write().await.push(value);
+}
+
+#[tokitest::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_multi_thread_flavor() {
+    assert_eq!(RuntimeFlavor::MultiThread, Handle::current().runtime_flavor());
+
+    let data = Arc::new(RwLock::new(Vec::new()));
+    let dc = data.clone();
+    spawn!("thread1", async {
+        label!("label 1");
+        dc.write().await.push(1);
+    });
+
+    run_to!("thread1", "label 1").await;
+    assert!(data.read().await.is_empty());
+    complete!("thread1").await;
+    assert_eq!(vec![1], *data.read().await);
+}
+
+#[tokitest::test(start_paused = true)]
+async fn test_start_paused() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+    let dc = data.clone();
+    spawn!("thread1", async {
+        call!(push_after_sleep(1, dc)).await;
+    });
+
+    let start = Instant::now();
+    run_to!("thread1", "before sleep").await;
+    complete!("thread1").await;
+    // The paused clock auto-advances through the sleep
+    assert!(start.elapsed() >= Duration::from_secs(60));
+    assert_eq!(vec![1], *data.read().await);
+}
+
+#[tokitest::test(seed = 42)]
+async fn test_seed() {
+    assert_eq!(42, tokitest_main_controller.seed().await);
+}
+
+static SCHEDULES_RUN: AtomicU64 = AtomicU64::new(0);
+
+#[tokitest::test(seed = 7, schedules = 3)]
+async fn test_schedules() {
+    let run = SCHEDULES_RUN.fetch_add(1, Ordering::SeqCst);
+    assert!(run < 3);
+    // Every run has its own controller and seed, the threads of earlier runs are gone
+    assert_eq!(7 + run, tokitest_main_controller.seed().await);
+    assert!(tokitest_main_controller.trace().await.is_empty());
+
+    spawn!("thread1", async {
+        label!("label 1");
+    });
+    complete!("thread1").await;
+}
+
+#[tokitest::test(timeout = 100)]
+#[should_panic(expected = "tokitest timed out after 100ms")]
+async fn test_timeout() {
+    spawn!("thread1", async {
+        std::future::pending::<()>().await;
+        label!("never reached");
+    });
+
+    run_to!("thread1", "never reached").await;
+}
+
+#[tokio::test]
+async fn test_trace_output() {
+    let path = std::env::temp_dir().join(format!("tokitest-attribute-test-{}.trace", std::process::id()));
+    let main_controller = MainController::builder().with_trace_output(&path).build();
+    let thread_controller = main_controller.nest().with_id("thread1").build().await;
+
+    let tc = thread_controller.clone();
+    let handle = tokio::spawn(async move {
+        tc.label("attribute_test::test_trace_output", "label 1").await;
+        tc.finish("attribute_test::test_trace_output").await;
+    });
+    main_controller.run_to_end("thread1").await;
+    handle.await.unwrap();
+    drop(main_controller);
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(
+        "thread1: attribute_test::test_trace_output::label 1\nthread1: attribute_test::test_trace_output::END\n",
+        trace
+    );
+}
@@ -0,0 +1,140 @@
+#![cfg(tokitest)]
+
+use std::sync::{Arc, Mutex};
+use regex::Regex;
+use tokio::sync::RwLock;
+use tokitest::{spawn, call, run_to, complete, RegexLabel};
+
+#[tokitest::testable(auto_labels)]
+async fn copy_twice(from: Arc<RwLock<i32>>, to: Arc<RwLock<Vec<i32>>>) {
+    let value = *from.read().await;
+    to.write().await.push(value);
+    let value = *from.read().await;
+    let push = async {
+        // Async blocks are not rewritten
+        to.write().await.push(value);
+    };
+    push.await;
+}
+
+pub struct Counter {
+    count: RwLock<i32>,
+}
+
+#[tokitest::testable_struct(auto_labels)]
+impl Counter {
+    pub async fn increment(&self) {
+        *self.count.write().await += 1;
+    }
+}
+
+/// Logs each step when it runs
+pub struct Steps {
+    log: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Steps {
+    async fn first(self) -> Steps {
+        self.log.lock().unwrap().push("first");
+        self
+    }
+
+    async fn second(self) {
+        self.log.lock().unwrap().push("second");
+    }
+}
+
+#[tokitest::testable(auto_labels)]
+async fn chain(steps: Steps) {
+    steps.first()
+        .await
+        .second()
+        .await;
+}
+
+#[tokitest::test]
+async fn test_every_await_is_a_checkpoint() {
+    let from = Arc::new(RwLock::new(1));
+    let to = Arc::new(RwLock::new(Vec::new()));
+
+    let (fc, tc) = (from.clone(), to.clone());
+    spawn!("node1", async {
+        call!(copy_twice(fc, tc)).await;
+    });
+
+    // Stop before the second read, the value read then is the one the test wrote
+    run_to!("node1", "tests/auto_label_test.rs:12").await;
+    assert_eq!(vec![1], *to.read().await);
+    *from.write().await = 2;
+    complete!("node1").await;
+    assert_eq!(vec![1, 2], *to.read().await);
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| event.starts_with("node1: auto_label_test::copy_twice"))
+        .collect();
+    assert_eq!(vec![
+        "node1: auto_label_test::copy_twice::tests/auto_label_test.rs:10",
+        "node1: auto_label_test::copy_twice::tests/auto_label_test.rs:11",
+        "node1: auto_label_test::copy_twice::tests/auto_label_test.rs:12",
+        "node1: auto_label_test::copy_twice::tests/auto_label_test.rs:17",
+    ], trace);
+}
+
+#[tokitest::test]
+async fn test_regex_label() {
+    let from = Arc::new(RwLock::new(1));
+    let to = Arc::new(RwLock::new(Vec::new()));
+
+    let (fc, tc) = (from.clone(), to.clone());
+    spawn!("node1", async {
+        call!(copy_twice(fc, tc)).await;
+    });
+
+    run_to!("node1", RegexLabel::new(Regex::new(r"auto_label_test\.rs:1[12]$").unwrap())).await;
+    assert!(to.read().await.is_empty());
+    complete!("node1").await;
+
+    let coverage = tokitest_main_controller.coverage().await;
+    assert_eq!(
+        vec![
+            ("auto_label_test::copy_twice", "tests/auto_label_test.rs:10"),
+            ("auto_label_test::copy_twice", "tests/auto_label_test.rs:12"),
+            ("auto_label_test::copy_twice", "tests/auto_label_test.rs:17"),
+        ],
+        coverage.untargeted()
+    );
+}
+
+#[tokitest::test]
+async fn test_testable_struct() {
+    let counter = Arc::new(Counter { count: RwLock::new(0) });
+
+    let cc = counter.clone();
+    spawn!("node1", async {
+        call!(cc.increment()).await;
+    });
+
+    run_to!("node1", "tests/auto_label_test.rs:27").await;
+    assert_eq!(0, *counter.count.read().await);
+    complete!("node1").await;
+    assert_eq!(1, *counter.count.read().await);
+}
+
+#[tokitest::test]
+async fn test_chained_awaits() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let steps = Steps { log: log.clone() };
+    spawn!("node1", async {
+        call!(chain(steps)).await;
+    });
+
+    // The checkpoint of each `.await` comes after the expression it awaits was evaluated
+    run_to!("node1", "tests/auto_label_test.rs:50").await;
+    assert!(log.lock().unwrap().is_empty());
+    run_to!("node1", "tests/auto_label_test.rs:52").await;
+    assert_eq!(vec!["first"], *log.lock().unwrap());
+    complete!("node1").await;
+    assert_eq!(vec!["first", "second"], *log.lock().unwrap());
+}
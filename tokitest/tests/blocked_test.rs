@@ -0,0 +1,142 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokitest::{label, spawn, call, run_to, resume, complete, assert_blocked, assert_parked_at};
+use tokitest::controller::ThreadState;
+use tokitest::sync::{mpsc, Mutex, Named};
+
+#[tokitest::testable]
+async fn deposit(accounts: Arc<Mutex<i32>>, amount: i32) {
+    let mut balance = accounts.lock().await;
+    label!("holding");
+    *balance += amount;
+}
+
+#[tokitest::testable]
+async fn deposit_unnamed(accounts: Arc<tokio::sync::Mutex<i32>>, amount: i32) {
+    *accounts.lock().await += amount;
+    label!("deposited");
+}
+
+#[tokitest::test]
+async fn test_blocked_on_lock() {
+    let accounts = Arc::new(Mutex::named("accounts", 0));
+
+    for (node, amount) in [("node1", 10), ("node2", 20)] {
+        let accounts = accounts.clone();
+        spawn!(node, async move {
+            call!(deposit(accounts, amount)).await;
+        });
+    }
+
+    run_to!("node1", "holding").await;
+    run_to!("node2", "lock requested:accounts").await;
+    resume!("node2").await;
+
+    assert_blocked!("node2").await;
+    assert_blocked!("node2", "lock:accounts").await;
+    assert_parked_at!("node1", "holding").await;
+    assert_parked_at!("node1", "blocked_test::deposit::holding").await;
+    assert_eq!(ThreadState::Blocked(Some("lock:accounts".to_string())), tokitest_main_controller.thread_state("node2").await);
+
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(30, *accounts.lock().await);
+}
+
+#[tokitest::test]
+async fn test_blocked_after_quiescence() {
+    let accounts = Arc::new(tokio::sync::Mutex::new(0));
+    let held = accounts.lock().await;
+
+    let ac = accounts.clone();
+    spawn!("node1", async move {
+        call!(deposit_unnamed(ac, 10)).await;
+    });
+    run_to!("node1", "INIT").await;
+    resume!("node1").await;
+
+    // The tokio mutex is not instrumented, node1 is blocked because it reaches no label
+    assert_blocked!("node1").await;
+    assert_eq!(ThreadState::Blocked(None), tokitest_main_controller.thread_state("node1").await);
+
+    drop(held);
+    assert_parked_at!("node1", "deposited").await;
+    complete!("node1").await;
+    assert_eq!(10, *accounts.lock().await);
+}
+
+#[tokitest::test]
+async fn test_blocked_on_recv() {
+    let (jobs, mut queue) = mpsc::named_channel("jobs", 1);
+
+    spawn!("node1", async move {
+        let job = queue.recv().await;
+        label!("working");
+        assert_eq!(Some(1), job);
+    });
+
+    run_to!("node1", "recv:jobs").await;
+    resume!("node1").await;
+    assert_blocked!("node1", "recv:jobs").await;
+
+    jobs.send(1).await.unwrap();
+    assert_parked_at!("node1", "received:jobs").await;
+    complete!("node1").await;
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node1 is not blocked, it is parked at blocked_test::deposit::holding")]
+async fn test_parked_thread_is_not_blocked() {
+    let accounts = Arc::new(Mutex::named("accounts", 0));
+
+    let ac = accounts.clone();
+    spawn!("node1", async move {
+        call!(deposit(ac, 10)).await;
+    });
+    run_to!("node1", "holding").await;
+
+    assert_blocked!("node1").await;
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node2 is not blocked on lock:ledger, it is blocked on lock:accounts")]
+async fn test_blocked_on_another_lock() {
+    let accounts = Arc::new(Mutex::named("accounts", 0));
+
+    for (node, amount) in [("node1", 10), ("node2", 20)] {
+        let accounts = accounts.clone();
+        spawn!(node, async move {
+            call!(deposit(accounts, amount)).await;
+        });
+    }
+    run_to!("node1", "holding").await;
+    run_to!("node2", "lock requested:accounts").await;
+    resume!("node2").await;
+
+    assert_blocked!("node2", "lock:ledger").await;
+}
+
+#[tokitest::testable]
+async fn slow_deposit(accounts: Arc<Mutex<i32>>, amount: i32) {
+    // Work between labels that takes longer than the default quiescence window
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    *accounts.lock().await += amount;
+}
+
+#[tokitest::test(quiescence = 500)]
+async fn test_quiescence_window() {
+    let accounts = Arc::new(Mutex::named("accounts", 0));
+
+    let ac = accounts.clone();
+    spawn!("node1", async move {
+        call!(slow_deposit(ac, 10)).await;
+    });
+    run_to!("node1", "INIT").await;
+    resume!("node1").await;
+
+    // With the default window node1 would count as blocked while it sleeps
+    assert_parked_at!("node1", "lock requested:accounts").await;
+    complete!("node1").await;
+    assert_eq!(10, *accounts.lock().await);
+}
@@ -0,0 +1,130 @@
+#![cfg(tokitest)]
+
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use futures::FutureExt;
+use tokitest::{label, spawn, call, run_to, complete, assert_cannot_reach, assert_parked_at};
+use tokitest::sync::{Mutex, Named};
+
+#[tokitest::testable]
+async fn guarded(log: Arc<Mutex<Vec<i32>>>, entry: i32) {
+    let mut log = log.lock().await;
+    label!("critical section");
+    log.push(entry);
+}
+
+#[tokitest::testable]
+async fn unguarded(log: Arc<std::sync::Mutex<Vec<i32>>>, entry: i32) {
+    label!("before");
+    label!("critical section");
+    log.lock().unwrap().push(entry);
+}
+
+#[tokitest::testable]
+async fn append_if_empty(log: Arc<Mutex<Vec<i32>>>, entry: i32) {
+    let mut log = log.lock().await;
+    if !log.is_empty() {
+        return;
+    }
+    label!("append");
+    log.push(entry);
+}
+
+#[tokitest::test]
+async fn test_mutual_exclusion() {
+    let log = Arc::new(Mutex::named("log", Vec::new()));
+
+    for (node, entry) in [("node1", 1), ("node2", 2)] {
+        let log = log.clone();
+        spawn!(node, async move {
+            call!(guarded(log, entry)).await;
+        });
+    }
+
+    run_to!("node1", "critical section").await;
+    assert_cannot_reach!("node2", "critical section").await;
+
+    // node2 waits for the lock, it enters once node1 leaves
+    complete!("node1").await;
+    run_to!("node2", "critical section").await;
+    complete!("node2").await;
+    assert_eq!(vec![1, 2], *log.lock().await);
+}
+
+#[tokitest::test]
+async fn test_thread_returns_before_label() {
+    let log = Arc::new(Mutex::named("log", vec![1]));
+
+    let lc = log.clone();
+    spawn!("node1", async move {
+        call!(append_if_empty(lc, 2)).await;
+    });
+
+    assert_cannot_reach!("node1", "append").await;
+    assert_eq!(vec![1], *log.lock().await);
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node2 reached cannot_reach_test::test_end_is_reachable::END without another thread progressing")]
+async fn test_end_is_reachable() {
+    let log = Arc::new(Mutex::named("log", vec![1]));
+
+    let lc = log.clone();
+    spawn!("node2", async move {
+        call!(append_if_empty(lc, 2)).await;
+    });
+
+    assert_cannot_reach!("node2", "END").await;
+}
+
+#[tokitest::test]
+async fn test_reached_label_stays_parked() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let lc = log.clone();
+    spawn!("node1", async move {
+        call!(unguarded(lc, 1)).await;
+    });
+
+    let reached = AssertUnwindSafe(assert_cannot_reach!("node1", "critical section")).catch_unwind().await;
+    assert!(reached.is_err());
+    assert_parked_at!("node1", "critical section").await;
+    complete!("node1").await;
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node2 reached cannot_reach_test::unguarded::critical section without another thread progressing")]
+async fn test_no_mutual_exclusion() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    for (node, entry) in [("node1", 1), ("node2", 2)] {
+        let log = log.clone();
+        spawn!(node, async move {
+            call!(unguarded(log, entry)).await;
+        });
+    }
+
+    run_to!("node1", "critical section").await;
+    assert_cannot_reach!("node2", "critical section").await;
+}
+
+#[tokitest::testable]
+async fn append_unnamed(log: Arc<tokio::sync::Mutex<Vec<i32>>>, entry: i32) {
+    log.lock().await.push(entry);
+    label!("appended");
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node1 is still running without reaching a label after the quiescence window, whether it can reach the label is undetermined")]
+async fn test_uninstrumented_wait_is_undetermined() {
+    let log = Arc::new(tokio::sync::Mutex::new(Vec::new()));
+    let _held = log.lock().await;
+
+    let lc = log.clone();
+    spawn!("node1", async move {
+        call!(append_unnamed(lc, 1)).await;
+    });
+
+    // The tokio mutex is not instrumented, node1 might only be slow to get it
+    assert_cannot_reach!("node1", "appended").await;
+}
@@ -0,0 +1,122 @@
+#![cfg(tokitest)]
+
+use tokitest::{spawn, run_to, complete};
+use tokitest::sync::{broadcast, mpsc, oneshot, watch};
+
+/// Events of the trace from channels, without the INIT and END labels
+async fn channel_events(main_controller: &tokitest::controller::MainController) -> Vec<String> {
+    main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| !event.ends_with("INIT") && !event.ends_with("END"))
+        .collect()
+}
+
+#[tokitest::test]
+async fn test_mpsc_queue_depth() {
+    let (jobs, mut queue) = mpsc::channel_with_payloads("jobs", 8);
+
+    let sender = jobs.clone();
+    spawn!("producer", async move {
+        for job in 1..=3 {
+            sender.send(job).await.unwrap();
+        }
+    });
+    spawn!("consumer", async move {
+        let mut done = Vec::new();
+        while let Some(job) = queue.recv().await {
+            done.push(job);
+        }
+        done
+    });
+
+    run_to!("consumer", "recv:jobs").await;
+    complete!("producer").await;
+    assert_eq!(3, jobs.max_capacity() - jobs.capacity());
+
+    run_to!("consumer", "received:jobs").await;
+    assert_eq!(2, jobs.max_capacity() - jobs.capacity());
+    drop(jobs);
+    complete!("consumer").await;
+
+    let events = channel_events(&tokitest_main_controller).await;
+    assert_eq!(vec![
+        "consumer: tokitest::sync::recv:jobs",
+        "producer: tokitest::sync::send:jobs",
+        "producer: sent 1 on jobs",
+        "producer: tokitest::sync::send:jobs",
+        "producer: sent 2 on jobs",
+        "producer: tokitest::sync::send:jobs",
+        "producer: sent 3 on jobs",
+        "consumer: received 1 on jobs",
+        "consumer: tokitest::sync::received:jobs",
+    ], events[..9]);
+    assert_eq!(Some(&"consumer: tokitest::sync::recv:jobs".to_string()), events.last());
+}
+
+#[tokitest::test]
+async fn test_unbounded_mpsc() {
+    let (sender, mut receiver) = mpsc::named_unbounded_channel("events");
+
+    spawn!("node1", async move {
+        sender.send("started").unwrap();
+        sender.send("stopped").unwrap();
+    });
+
+    // Unbounded sends do not park
+    run_to!("node1", "END").await;
+    assert_eq!(2, receiver.len());
+    assert_eq!(Ok("started"), receiver.try_recv());
+
+    let events = channel_events(&tokitest_main_controller).await;
+    assert_eq!(vec!["node1: tokitest::sync::send:events", "node1: tokitest::sync::send:events"], events);
+}
+
+#[tokitest::test]
+async fn test_oneshot() {
+    let (reply, response) = oneshot::channel_with_payloads("reply");
+
+    spawn!("client", async move {
+        response.await.unwrap()
+    });
+
+    run_to!("client", "recv:reply").await;
+    reply.send(42).unwrap();
+    run_to!("client", "received:reply").await;
+    complete!("client").await;
+
+    let events = channel_events(&tokitest_main_controller).await;
+    assert_eq!(vec![
+        "client: tokitest::sync::recv:reply",
+        "client: received 42 on reply",
+        "client: tokitest::sync::received:reply",
+    ], events);
+}
+
+#[tokitest::test]
+async fn test_broadcast_and_watch() {
+    let (events, _) = broadcast::named_channel("events", 4);
+    let (config, _) = watch::channel_with_payloads("config", 1);
+
+    let mut subscriber = events.subscribe();
+    let mut watcher = config.subscribe();
+    spawn!("subscriber", async move {
+        let event: &str = subscriber.recv().await.unwrap();
+        watcher.changed().await.unwrap();
+        (event, *watcher.borrow())
+    });
+
+    run_to!("subscriber", "recv:events").await;
+    events.send("reload").unwrap();
+    run_to!("subscriber", "recv:config").await;
+    config.send(2).unwrap();
+    complete!("subscriber").await;
+
+    let events = channel_events(&tokitest_main_controller).await;
+    assert_eq!(vec![
+        "subscriber: tokitest::sync::recv:events",
+        "subscriber: tokitest::sync::received:events",
+        "subscriber: tokitest::sync::recv:config",
+        "subscriber: received 2 on config",
+        "subscriber: tokitest::sync::received:config",
+    ], events);
+}
@@ -0,0 +1,75 @@
+#![cfg(tokitest)]
+
+use tokio::time::{sleep, sleep_until, Duration, Instant};
+use tokitest::{label, spawn, call, run_to, advance_time};
+
+#[tokitest::testable]
+async fn heartbeat(beats: usize) {
+    for _ in 0..beats {
+        sleep(Duration::from_secs(1)).await;
+        label!("heartbeat");
+    }
+}
+
+#[tokitest::testable]
+async fn lease(duration: Duration) {
+    let expiry = Instant::now() + duration;
+    label!("lease acquired");
+    sleep_until(expiry).await;
+    label!("lease expired");
+}
+
+#[tokitest::test(start_paused = true)]
+async fn test_run_to_advancing_time() {
+    spawn!("node1", async {
+        call!(heartbeat(3)).await;
+    });
+
+    run_to!("node1", "heartbeat", advance_time = Duration::from_secs(5)).await;
+    run_to!("node1", "heartbeat").await;
+    assert_eq!(Some(Duration::from_secs(2)), tokitest_main_controller.elapsed().await);
+
+    let time: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| event.starts_with("time:"))
+        .collect();
+    assert_eq!(vec!["time: advanced 1s to 1s", "time: advanced 1s to 2s"], time);
+}
+
+#[tokitest::test]
+async fn test_advance_time() {
+    spawn!("node1", async {
+        call!(lease(Duration::from_secs(10))).await;
+    });
+
+    // Pauses the clock on first use
+    run_to!("node1", "lease acquired").await;
+    assert_eq!(None, tokitest_main_controller.elapsed().await);
+    advance_time!(Duration::from_secs(4)).await;
+    advance_time!(Duration::from_secs(6)).await;
+
+    // The lease already expired. The clock was paused mid-millisecond, so running to the label
+    // may still round the timer up by the 1ms timer resolution
+    run_to!("node1", "lease expired").await;
+    let elapsed = tokitest_main_controller.elapsed().await.unwrap();
+    assert!(elapsed >= Duration::from_secs(10) && elapsed <= Duration::from_millis(10_001));
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter().map(ToString::to_string).collect();
+    assert_eq!(&[
+        "node1: clock_test::test_advance_time::INIT",
+        "node1: clock_test::lease::lease acquired",
+        "time: advanced 4s to 4s",
+        "time: advanced 6s to 10s",
+        "node1: clock_test::lease::lease expired",
+    ], &trace[..5]);
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node1 did not reach the label passed to run_to! within 5s")]
+async fn test_advance_time_limit() {
+    spawn!("node1", async {
+        call!(lease(Duration::from_secs(10))).await;
+    });
+
+    run_to!("node1", "lease expired", advance_time = Duration::from_secs(5)).await;
+}
@@ -1,3 +1,5 @@
+#![cfg(tokitest)]
+
 // use tokitest::{RepeatedLabel, StringLabel};
 use std::sync::Arc;
 use tokio::{sync::RwLock};
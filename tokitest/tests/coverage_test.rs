@@ -0,0 +1,68 @@
+#![cfg(tokitest)]
+
+use tokitest::{label, spawn, call, run_to, complete};
+use tokitest::coverage::{LabelCoverage, LabelStats};
+
+#[tokitest::testable]
+async fn branching(take_branch: bool) {
+    label!("start");
+    if take_branch {
+        label!("branch");
+    }
+    for _ in 0..3 {
+        label!("loop");
+    }
+}
+
+#[tokitest::test]
+async fn test_label_hits() {
+    spawn!("thread1", async {
+        call!(branching(false)).await;
+    });
+
+    run_to!("thread1", "start").await;
+    complete!("thread1").await;
+
+    let coverage = tokitest_main_controller.coverage().await;
+    assert_eq!(Some(LabelStats { hits: 1, targeted: 1 }), coverage.get("coverage_test::branching", "start"));
+    assert_eq!(Some(LabelStats { hits: 0, targeted: 0 }), coverage.get("coverage_test::branching", "branch"));
+    assert_eq!(Some(LabelStats { hits: 3, targeted: 0 }), coverage.get("coverage_test::branching", "loop"));
+
+    assert_eq!(vec![("coverage_test::branching", "branch")], coverage.unreached());
+    assert_eq!(
+        vec![("coverage_test::branching", "branch"), ("coverage_test::branching", "loop")],
+        coverage.untargeted()
+    );
+}
+
+#[tokitest::test]
+async fn test_labels_in_test_body() {
+    spawn!("thread1", async {
+        label!("in test");
+    });
+
+    let coverage = tokitest_main_controller.coverage().await;
+    assert_eq!(vec![("coverage_test::test_labels_in_test_body", "in test")], coverage.unreached());
+
+    run_to!("thread1", "in test").await;
+    complete!("thread1").await;
+
+    let coverage = tokitest_main_controller.coverage().await;
+    assert!(coverage.unreached().is_empty());
+    assert!(coverage.untargeted().is_empty());
+}
+
+#[tokitest::test]
+async fn test_tsv_escapes_labels() {
+    spawn!("thread1", async {
+        label!("tab\there");
+        label!("new\nline \\ back");
+    });
+    complete!("thread1").await;
+
+    let coverage = tokitest_main_controller.coverage().await;
+    let parsed = LabelCoverage::from_tsv(&coverage.to_tsv());
+    assert_eq!(2, coverage.to_tsv().lines().count());
+    assert_eq!(Some(LabelStats { hits: 1, targeted: 0 }), parsed.get("coverage_test::test_tsv_escapes_labels", "tab\there"));
+    assert_eq!(Some(LabelStats { hits: 1, targeted: 0 }), parsed.get("coverage_test::test_tsv_escapes_labels", "new\nline \\ back"));
+}
@@ -0,0 +1,129 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, crash, restart};
+use tokitest::controller::ThreadState;
+
+#[tokitest::testable]
+async fn write_log(entries: Vec<i32>, log: Arc<RwLock<Vec<i32>>>) {
+    for entry in entries {
+        label!("before append");
+        log.write().await.push(entry);
+    }
+}
+
+#[tokitest::testable]
+async fn recover(log: Arc<RwLock<Vec<i32>>>, recovered: Arc<RwLock<Vec<i32>>>) {
+    *recovered.write().await = log.read().await.clone();
+    label!("recovered");
+}
+
+#[tokitest::test]
+async fn test_crash_and_restart() {
+    let log = Arc::new(RwLock::new(Vec::new()));
+    let recovered = Arc::new(RwLock::new(Vec::new()));
+
+    let lc = log.clone();
+    spawn!("node1", async {
+        call!(write_log(vec![1, 2, 3], lc)).await;
+    });
+
+    run_to!("node1", "before append").await;
+    run_to!("node1", "before append").await;
+    crash!("node1").await;
+    assert_eq!(ThreadState::Crashed, tokitest_main_controller.thread_state("node1").await);
+    assert_eq!(vec![1], *log.read().await);
+
+    let (lc, rc) = (log.clone(), recovered.clone());
+    restart!("node1", async {
+        call!(recover(lc, rc)).await;
+    });
+
+    run_to!("node1", "recovered").await;
+    assert_eq!(vec![1], *recovered.read().await);
+    complete!("node1").await;
+    assert_eq!(ThreadState::Finished, tokitest_main_controller.thread_state("node1").await);
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter().map(ToString::to_string).collect();
+    assert!(trace.contains(&"node1: crashed".to_string()));
+}
+
+#[tokitest::test]
+async fn test_crash_nested_threads() {
+    let log = Arc::new(RwLock::new(Vec::new()));
+
+    let lc = log.clone();
+    spawn!("node1", async {
+        spawn!("worker", async {
+            call!(write_log(vec![1, 2], lc)).await;
+        });
+        label!("spawned worker");
+    });
+    let lc = log.clone();
+    spawn!("node2", async {
+        call!(write_log(vec![10], lc)).await;
+    });
+
+    run_to!("node1", "spawned worker").await;
+    run_to!("node1.worker", "before append").await;
+    crash!("node1").await;
+    assert_eq!(ThreadState::Crashed, tokitest_main_controller.thread_state("node1").await);
+    assert_eq!(ThreadState::Crashed, tokitest_main_controller.thread_state("node1.worker").await);
+
+    complete!("node2").await;
+    assert_eq!(vec![10], *log.read().await);
+
+    // A nested thread can be restarted on its own
+    let lc = log.clone();
+    restart!("node1.worker", async {
+        call!(write_log(vec![2], lc)).await;
+    });
+    complete!("node1.worker").await;
+    assert_eq!(vec![10, 2], *log.read().await);
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Thread node1 crashed before reaching the label passed to run_to!")]
+async fn test_run_to_crashed_thread() {
+    spawn!("node1", async {
+        label!("label 1");
+    });
+
+    crash!("node1").await;
+    run_to!("node1", "label 1").await;
+}
+
+#[tokitest::test]
+#[should_panic(expected = "Cannot restart node1, it has not crashed")]
+async fn test_restart_running_thread() {
+    spawn!("node1", async {
+        label!("label 1");
+    });
+
+    restart!("node1", async {
+        label!("label 1");
+    });
+}
+
+#[tokitest::test]
+async fn test_crash_wakes_waiting_run_to() {
+    spawn!("node1", async {
+        label!("started");
+        std::future::pending::<()>().await;
+        label!("never reached");
+    });
+    run_to!("node1", "started").await;
+
+    let mc = tokitest_main_controller.clone();
+    let waiting = tokio::spawn(async move {
+        mc.run_to("node1", "never reached").await;
+    });
+    // Let the run_to resume node1 and wait for its next label
+    tokio::task::yield_now().await;
+    crash!("node1").await;
+
+    let error = waiting.await.unwrap_err();
+    let message = error.into_panic().downcast::<String>().unwrap();
+    assert_eq!("Thread node1 crashed before reaching the label passed to run_to!", *message);
+}
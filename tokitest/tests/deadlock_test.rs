@@ -0,0 +1,96 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokitest::{label, spawn, call, complete};
+use tokitest::sync::{Mutex, Named};
+
+#[tokitest::testable]
+async fn transfer(from: Arc<Mutex<i32>>, to: Arc<Mutex<i32>>, amount: i32) {
+    label!("start transfer");
+    let mut from = from.lock().await;
+    let mut to = to.lock().await;
+    *from -= amount;
+    *to += amount;
+}
+
+#[tokitest::test]
+async fn test_lock_order_inversion() {
+    let accounts = Arc::new(Mutex::named("accounts", 100));
+    let audit = Arc::new(Mutex::named("audit", 0));
+
+    let (from, to) = (accounts.clone(), audit.clone());
+    spawn!("node1", async {
+        call!(transfer(from, to, 10)).await;
+    });
+    let (from, to) = (audit.clone(), accounts.clone());
+    spawn!("node2", async {
+        call!(transfer(from, to, 5)).await;
+    });
+
+    // The threads run one after the other, so this schedule does not deadlock
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(95, *accounts.lock().await);
+
+    let deadlocks = tokitest_main_controller.take_potential_deadlocks().await;
+    assert_eq!(1, deadlocks.len());
+    assert_eq!(
+        "potential deadlock between locks audit, accounts:\n  \
+        node2 requested accounts after deadlock_test::transfer::start transfer \
+        while holding audit taken after deadlock_test::transfer::start transfer\n  \
+        node1 requested audit after deadlock_test::transfer::start transfer \
+        while holding accounts taken after deadlock_test::transfer::start transfer",
+        deadlocks[0].to_string()
+    );
+}
+
+#[tokitest::test]
+async fn test_consistent_lock_order() {
+    let accounts = Arc::new(Mutex::named("accounts", 100));
+    let audit = Arc::new(Mutex::named("audit", 0));
+
+    for node in ["node1", "node2"] {
+        let (from, to) = (accounts.clone(), audit.clone());
+        spawn!(node, async {
+            call!(transfer(from, to, 10)).await;
+        });
+    }
+
+    complete!("node1").await;
+    complete!("node2").await;
+    assert!(tokitest_main_controller.take_potential_deadlocks().await.is_empty());
+}
+
+#[tokitest::test]
+async fn test_locks_with_the_same_name() {
+    // Locks are told apart by instance, so a consistent order between two "account" locks is fine
+    let first = Arc::new(Mutex::named("account", 100));
+    let second = Arc::new(Mutex::named("account", 100));
+
+    let (from, to) = (first.clone(), second.clone());
+    spawn!("node1", async {
+        call!(transfer(from, to, 10)).await;
+    });
+
+    complete!("node1").await;
+    assert!(tokitest_main_controller.take_potential_deadlocks().await.is_empty());
+}
+
+#[tokitest::test]
+#[should_panic(expected = "tokitest: potential deadlock between locks b, a:")]
+async fn test_potential_deadlock_fails_test() {
+    let a = Arc::new(Mutex::named("a", 0));
+    let b = Arc::new(Mutex::named("b", 0));
+
+    let (from, to) = (a.clone(), b.clone());
+    spawn!("node1", async {
+        call!(transfer(from, to, 1)).await;
+    });
+    complete!("node1").await;
+
+    let (from, to) = (b.clone(), a.clone());
+    spawn!("node2", async {
+        call!(transfer(from, to, 1)).await;
+    });
+    complete!("node2").await;
+}
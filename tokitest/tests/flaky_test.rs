@@ -0,0 +1,131 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, complete, network_call, loss, duplication};
+use tokitest::controller::MainController;
+use tokitest::trace::{NetworkFate, TraceEvent};
+
+async fn mock_rpc(to: &str, deliveries: Arc<AtomicU64>) -> String {
+    let n = deliveries.fetch_add(1, Ordering::SeqCst) + 1;
+    format!("{}: delivery {}", to, n)
+}
+
+async fn mock_rpc_error(to: &str) -> String {
+    format!("{}: lost", to)
+}
+
+#[tokitest::testable]
+async fn send(to: &'static str, deliveries: Arc<AtomicU64>, results: Arc<RwLock<Vec<String>>>) {
+    let result = network_call!(to = to, mock_rpc(to, deliveries.clone()), mock_rpc_error(to)).await;
+    results.write().await.push(result);
+    label!("sent");
+}
+
+#[tokitest::testable]
+async fn send_with_retries(to: &'static str, deliveries: Arc<AtomicU64>, results: Arc<RwLock<Vec<String>>>) {
+    let result = network_call!(to = to, duplicate = true, mock_rpc(to, deliveries.clone()), mock_rpc_error(to)).await;
+    results.write().await.push(result);
+    label!("sent");
+}
+
+#[tokitest::test]
+async fn test_loss() {
+    let deliveries = Arc::new(AtomicU64::new(0));
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let (dc, rc) = (deliveries.clone(), results.clone());
+    spawn!("node1", async {
+        call!(send("node2", dc.clone(), rc.clone())).await;
+        call!(send("node3", dc, rc)).await;
+    });
+
+    loss!("node1" -> "node2", 1.0).await;
+
+    complete!("node1").await;
+    assert_eq!(vec!["node2: lost", "node3: delivery 1"], *results.read().await);
+    assert_eq!(1, deliveries.load(Ordering::SeqCst));
+}
+
+#[tokitest::test]
+async fn test_duplication() {
+    let deliveries = Arc::new(AtomicU64::new(0));
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let (dc, rc) = (deliveries.clone(), results.clone());
+    spawn!("node1", async {
+        call!(send_with_retries("node2", dc.clone(), rc.clone())).await;
+        // Only call sites marked `duplicate = true` are delivered twice
+        call!(send("node2", dc, rc)).await;
+    });
+
+    duplication!("node2", 1.0).await;
+
+    complete!("node1").await;
+    assert_eq!(vec!["node2: delivery 2", "node2: delivery 3"], *results.read().await);
+    assert_eq!(3, deliveries.load(Ordering::SeqCst));
+
+    let fates: Vec<NetworkFate> = tokitest_main_controller.trace().await.into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::NetworkCall { fate, .. } => Some(fate),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        vec![NetworkFate::Duplicated { delay: Default::default() }, NetworkFate::Delivered { delay: Default::default() }],
+        fates
+    );
+}
+
+async fn flaky_fates(seed: u64) -> Vec<NetworkFate> {
+    let main_controller = MainController::builder().with_seed(seed).build();
+    main_controller.loss("node1", 0.3).await;
+    main_controller.duplication("node2", 0.3).await;
+    let tokitest_thread_controller = main_controller.nest().with_id("node1").build().await;
+
+    let deliveries = Arc::new(AtomicU64::new(0));
+    for _ in 0..32 {
+        network_call!(to = "node2", duplicate = true, mock_rpc("node2", deliveries.clone()), mock_rpc_error("node2")).await;
+    }
+
+    main_controller.trace().await.into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::NetworkCall { fate, .. } => Some(fate),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_flaky_network_follows_seed() {
+    let fates = flaky_fates(1).await;
+    assert_eq!(32, fates.len());
+    assert!(fates.contains(&NetworkFate::Lost));
+    assert!(fates.iter().any(|fate| matches!(fate, NetworkFate::Duplicated { .. })));
+    assert!(fates.iter().any(|fate| matches!(fate, NetworkFate::Delivered { .. })));
+
+    assert_eq!(fates, flaky_fates(1).await);
+    assert_ne!(fates, flaky_fates(2).await);
+}
+
+#[tokio::test]
+async fn test_trace_display() {
+    let main_controller = MainController::new();
+    main_controller.isolate("node1").await;
+    let tokitest_thread_controller = main_controller.nest().with_id("node1").build().await;
+
+    let deliveries = Arc::new(AtomicU64::new(0));
+    network_call!(mock_rpc("node2", deliveries.clone()), mock_rpc_error("node2")).await;
+    main_controller.heal("node1").await;
+    network_call!(to = "node2", mock_rpc("node2", deliveries), mock_rpc_error("node2")).await;
+
+    let trace: Vec<String> = main_controller.trace().await.iter().map(ToString::to_string).collect();
+    assert_eq!(vec!["node1: network call unreachable", "node1: network call to node2 delivered after 0ns"], trace);
+}
+
+#[tokio::test]
+#[should_panic(expected = "Network probability must be between 0 and 1, got 1.5")]
+async fn test_invalid_probability() {
+    MainController::new().loss("node1", 1.5).await;
+}
@@ -0,0 +1,61 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, QualifiedLabel};
+use tokitest::controller::ThreadState;
+
+#[tokitest::testable]
+async fn write_blocks(data: Arc<RwLock<Vec<i32>>>) {
+    data.write().await.push(1);
+    label!("write block");
+    data.write().await.push(2);
+    label!("label 2");
+    data.write().await.push(3);
+}
+
+#[tokitest::test]
+async fn test_labels_ending_in_block() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    let dc = data.clone();
+    spawn!("thread1", async {
+        call!(write_blocks(dc)).await;
+    });
+
+    run_to!("thread1", "write block").await;
+    assert_eq!(vec![1], *data.read().await);
+
+    complete!("thread1").await;
+    assert_eq!(vec![1, 2, 3], *data.read().await);
+}
+
+#[tokitest::test]
+async fn test_thread_state() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    let dc = data.clone();
+    spawn!("thread1", async {
+        call!(write_blocks(dc)).await;
+    });
+
+    run_to!("thread1", "label 2").await;
+    assert_eq!(
+        ThreadState::Parked(QualifiedLabel::new("handshake_test::write_blocks", "label 2")),
+        tokitest_main_controller.thread_state("thread1").await
+    );
+    assert_eq!(vec![1, 2], *data.read().await);
+
+    complete!("thread1").await;
+    assert_eq!(ThreadState::Finished, tokitest_main_controller.thread_state("thread1").await);
+}
+
+#[tokitest::test]
+#[should_panic(expected = "finished before reaching the label")]
+async fn test_run_to_missing_label() {
+    spawn!("thread1", async {
+        label!("label 1");
+    });
+
+    run_to!("thread1", "label 2").await;
+}
@@ -0,0 +1,121 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, crash, record_op};
+use tokitest::history::{History, SequentialModel};
+
+#[derive(Debug, Clone, PartialEq)]
+enum CounterOp {
+    Increment,
+    Read,
+}
+
+/// Increment returns the new value, Read the current one
+#[derive(Debug, Clone, Default)]
+struct Counter(i32);
+
+impl SequentialModel for Counter {
+    type Op = CounterOp;
+    type Result = i32;
+
+    fn apply(&mut self, op: &CounterOp) -> i32 {
+        if *op == CounterOp::Increment {
+            self.0 += 1;
+        }
+        self.0
+    }
+}
+
+/// Not atomic: concurrent increments can read the same value
+#[tokitest::testable]
+async fn increment(counter: Arc<RwLock<i32>>) -> i32 {
+    let value = *counter.read().await + 1;
+    label!("read");
+    *counter.write().await = value;
+    value
+}
+
+#[tokitest::test]
+async fn test_linearizable() {
+    let counter = Arc::new(RwLock::new(0));
+    let history = History::<Counter>::new();
+
+    for client in ["client1", "client2"] {
+        let (c, h) = (counter.clone(), history.clone());
+        spawn!(client, async {
+            record_op!(h, CounterOp::Increment, call!(increment(c))).await;
+        });
+    }
+
+    complete!("client1").await;
+    // client2 read 1 but has not written yet, so its increment takes effect after the read
+    run_to!("client2", "read").await;
+    assert_eq!(1, record_op!(history, CounterOp::Read, async { *counter.read().await }).await);
+    complete!("client2").await;
+
+    let clients: Vec<String> = history.operations().into_iter().map(|operation| operation.client).collect();
+    assert_eq!(vec!["client1", "client2", ""], clients);
+    history.assert_linearizable(Counter::default());
+}
+
+#[tokitest::test]
+async fn test_lost_update() {
+    let counter = Arc::new(RwLock::new(0));
+    let history = History::<Counter>::new();
+
+    for client in ["client1", "client2"] {
+        let (c, h) = (counter.clone(), history.clone());
+        spawn!(client, async {
+            record_op!(h, CounterOp::Increment, call!(increment(c))).await;
+        });
+    }
+
+    run_to!("client1", "read").await;
+    run_to!("client2", "read").await;
+    complete!("client1").await;
+    complete!("client2").await;
+    record_op!(history, CounterOp::Read, async { *counter.read().await }).await;
+
+    // Both increments returned 1, the read does not matter
+    let error = history.check(Counter::default()).unwrap_err();
+    let minimal: Vec<String> = error.operations.iter().map(ToString::to_string).collect();
+    assert_eq!(vec![
+        "client1: Increment invoked at 0, returned 1 at 2",
+        "client2: Increment invoked at 1, returned 1 at 3",
+    ], minimal);
+}
+
+#[tokitest::test]
+async fn test_pending_operation() {
+    let counter = Arc::new(RwLock::new(0));
+    let history = History::<Counter>::new();
+
+    let (c, h) = (counter.clone(), history.clone());
+    spawn!("client1", async {
+        record_op!(h, CounterOp::Increment, call!(increment(c))).await;
+    });
+    run_to!("client1", "read").await;
+    crash!("client1").await;
+
+    // The crashed increment never returned, it may or may not have taken effect
+    record_op!(history, CounterOp::Read, async { 0 }).await;
+    record_op!(history, CounterOp::Read, async { 1 }).await;
+    history.assert_linearizable(Counter::default());
+    assert!(history.operations()[0].result.is_none());
+
+    record_op!(history, CounterOp::Read, async { 0 }).await;
+    assert!(history.check(Counter::default()).is_err());
+}
+
+#[test]
+#[should_panic(expected = "History is not linearizable, minimal non-linearizable subhistory:\n  <main>: Increment invoked at 0, returned 1 at 1\n  <main>: Read invoked at 2, returned 0 at 3")]
+fn test_stale_read() {
+    let history = History::<Counter>::new();
+    let increment = history.invoke(CounterOp::Increment);
+    history.complete(increment, 1);
+    let read = history.invoke(CounterOp::Read);
+    history.complete(read, 0);
+
+    history.assert_linearizable(Counter::default());
+}
@@ -0,0 +1,112 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokitest::{label, spawn, call, run_to, complete, invariant};
+use tokitest::sync::{Mutex, Named, Shared};
+
+/// Accounts whose total is only consistent outside of a transfer
+struct Bank {
+    lock: Mutex<()>,
+    alice: Shared<i32>,
+    bob: Shared<i32>,
+}
+
+impl Bank {
+    fn new() -> Arc<Bank> {
+        Arc::new(Bank { lock: Mutex::named("bank", ()), alice: Shared::named("alice", 100), bob: Shared::named("bob", 0) })
+    }
+
+    fn total(&self) -> i32 {
+        self.alice.get() + self.bob.get()
+    }
+}
+
+#[tokitest::testable]
+async fn transfer(bank: Arc<Bank>, amount: i32) {
+    let _guard = bank.lock.lock().await;
+    bank.alice.write(|alice| *alice -= amount);
+    label!("debited");
+    bank.bob.write(|bob| *bob += amount);
+    label!("credited");
+}
+
+#[tokitest::testable]
+async fn refund(bank: Arc<Bank>, amount: i32) {
+    let _guard = bank.lock.lock().await;
+    label!("refunding");
+    bank.alice.write(|alice| *alice += amount);
+    bank.bob.write(|bob| *bob -= amount);
+}
+
+#[tokitest::test]
+async fn test_invariant_holds() {
+    let bank = Bank::new();
+    let bc = bank.clone();
+    invariant!(move || bc.alice.get() >= 0 && bc.bob.get() >= 0).await;
+
+    for (node, amount) in [("node1", 10), ("node2", 20)] {
+        let bank = bank.clone();
+        spawn!(node, async move {
+            call!(transfer(bank, amount)).await;
+        });
+    }
+    complete!("node1").await;
+    complete!("node2").await;
+
+    // Reads by the invariant are not accesses of the thread that reached the label
+    assert!(tokitest_main_controller.take_data_races().await.is_empty());
+}
+
+#[tokitest::test]
+#[should_panic(expected = "tokitest: invariant `total is 100` violated when node1 reached invariant_test::transfer::debited")]
+async fn test_violation_between_run_to_steps() {
+    let bank = Bank::new();
+    let bc = bank.clone();
+    invariant!("total is 100", move || bc.total() == 100).await;
+
+    let bc = bank.clone();
+    spawn!("node1", async move {
+        call!(transfer(bc, 10)).await;
+    });
+
+    // The total is 100 again once node1 is at "credited", but it was not at "debited"
+    run_to!("node1", "credited").await;
+    assert_eq!(100, bank.total());
+    complete!("node1").await;
+}
+
+#[tokitest::test]
+#[should_panic(expected = "violated when node1 reached invariant_test::test_violation_report::END, recent trace:\n  \
+    node1: invariant_test::test_violation_report::INIT\n  \
+    node1: tokitest::sync::lock requested:bank\n  \
+    node1: tokitest::sync::lock acquired:bank\n  \
+    node1: invariant_test::refund::refunding\n  \
+    node1: tokitest::sync::lock released:bank\n  \
+    node1: invariant_test::test_violation_report::END")]
+async fn test_violation_report() {
+    let bank = Bank::new();
+    let bc = bank.clone();
+    invariant!(move || bc.bob.get() >= 0).await;
+
+    let bc = bank.clone();
+    spawn!("node1", async move {
+        call!(refund(bc, 10)).await;
+    });
+    complete!("node1").await;
+}
+
+#[tokitest::test]
+#[should_panic(expected = "tokitest: invariant `bc.bob.get() >= 0` violated when node1 reached invariant_test::test_violation_fails_test_when_dropped::INIT")]
+async fn test_violation_fails_test_when_dropped() {
+    let bank = Bank::new();
+    bank.bob.set(-10);
+    let bc = bank.clone();
+    invariant!(move || bc.bob.get() >= 0).await;
+
+    let bc = bank.clone();
+    spawn!("node1", async move {
+        call!(refund(bc, 10)).await;
+    });
+    // node1 parks at its first label, the test never waits for it
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+}
@@ -1,3 +1,5 @@
+#![cfg(tokitest)]
+
 use tokio::join;
 use tokio::task::JoinSet;
 use tokitest::{label, run_to, complete, spawn_join_set};
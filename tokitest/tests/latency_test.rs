@@ -0,0 +1,105 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::{Duration, Instant};
+use tokitest::{label, spawn, call, complete, network_call, latency, isolate};
+use tokitest::controller::MainController;
+
+async fn mock_rpc(to: &str) -> String {
+    format!("{}: ok", to)
+}
+
+async fn mock_rpc_error(to: &str) -> String {
+    format!("{}: unreachable", to)
+}
+
+#[tokitest::testable]
+async fn timed_calls(peers: Vec<&'static str>, elapsed: Arc<RwLock<Vec<Duration>>>) {
+    for to in peers {
+        let start = Instant::now();
+        network_call!(to = to, mock_rpc(to), mock_rpc_error(to)).await;
+        elapsed.write().await.push(start.elapsed());
+    }
+    label!("calls done");
+}
+
+#[tokitest::test(start_paused = true)]
+async fn test_node_latency() {
+    let elapsed = Arc::new(RwLock::new(Vec::new()));
+
+    let ec = elapsed.clone();
+    spawn!("node1", async {
+        call!(timed_calls(vec!["node2", "node3"], ec)).await;
+    });
+
+    latency!("node1", Duration::from_millis(10)).await;
+    latency!("node2", Duration::from_millis(100)).await;
+
+    complete!("node1").await;
+    // Latency of the caller and destination add up
+    assert_eq!(vec![Duration::from_millis(110), Duration::from_millis(10)], *elapsed.read().await);
+}
+
+#[tokitest::test(start_paused = true)]
+async fn test_link_latency() {
+    let elapsed = Arc::new(RwLock::new(Vec::new()));
+
+    let ec = elapsed.clone();
+    spawn!("node1", async {
+        spawn!("worker", async {
+            call!(timed_calls(vec!["node2", "node3", "node2"], ec)).await;
+        });
+        label!("spawned worker");
+    });
+
+    latency!("node2", Duration::from_millis(100)).await;
+    latency!("node1" -> "node2", Duration::from_millis(30)).await;
+
+    complete!("node1").await;
+    complete!("node1.worker").await;
+    assert_eq!(
+        vec![Duration::from_millis(30), Duration::ZERO, Duration::from_millis(30)],
+        *elapsed.read().await
+    );
+}
+
+#[tokitest::test(start_paused = true)]
+async fn test_unreachable_calls_are_not_delayed() {
+    let elapsed = Arc::new(RwLock::new(Vec::new()));
+
+    let ec = elapsed.clone();
+    spawn!("node1", async {
+        call!(timed_calls(vec!["node2"], ec)).await;
+    });
+
+    latency!("node1", Duration::from_secs(1)).await;
+    isolate!("node1").await;
+
+    complete!("node1").await;
+    assert_eq!(vec![Duration::ZERO], *elapsed.read().await);
+}
+
+async fn jittered_delays(seed: u64) -> Vec<Duration> {
+    let main_controller = MainController::builder().with_seed(seed).build();
+    main_controller.latency("node1", Duration::from_millis(100), Duration::from_millis(50)).await;
+    let tokitest_thread_controller = main_controller.nest().with_id("node1").build().await;
+
+    let mut delays = Vec::new();
+    for _ in 0..8 {
+        let start = Instant::now();
+        network_call!(to = "node2", mock_rpc("node2"), mock_rpc_error("node2")).await;
+        delays.push(start.elapsed());
+    }
+    delays
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_jitter_follows_seed() {
+    let delays = jittered_delays(1).await;
+    assert!(delays.iter().all(|d| *d >= Duration::from_millis(100) && *d <= Duration::from_millis(150)));
+    assert!(delays.iter().any(|d| *d != delays[0]));
+
+    assert_eq!(delays, jittered_delays(1).await);
+    assert_ne!(delays, jittered_delays(2).await);
+}
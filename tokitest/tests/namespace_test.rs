@@ -0,0 +1,102 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use regex::Regex;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, RegexLabel, StringLabel};
+use tokitest::trace::TraceEvent;
+
+mod worker {
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tokitest::label;
+
+    #[tokitest::testable]
+    pub async fn run(data: Arc<RwLock<Vec<&'static str>>>) {
+        label!("start");
+        data.write().await.push("worker");
+    }
+}
+
+mod client {
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tokitest::{label, call};
+
+    #[tokitest::testable]
+    pub async fn run(data: Arc<RwLock<Vec<&'static str>>>) {
+        label!("start");
+        data.write().await.push("client");
+        call!(super::worker::run(data)).await;
+    }
+}
+
+#[tokitest::test]
+async fn test_qualified_run_to() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    let dc = data.clone();
+    spawn!("thread1", async {
+        call!(client::run(dc)).await;
+    });
+
+    // The short name stops at the first "start", which is the client's
+    run_to!("thread1", "start").await;
+    assert!(data.read().await.is_empty());
+
+    // The qualified name skips over labels with the same name in other functions
+    run_to!("thread1", "namespace_test::worker::run::start").await;
+    assert_eq!(vec!["client"], *data.read().await);
+
+    complete!("thread1").await;
+    assert_eq!(vec!["client", "worker"], *data.read().await);
+}
+
+#[tokitest::test]
+async fn test_qualified_regex() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    let dc = data.clone();
+    spawn!("thread1", async {
+        call!(client::run(dc)).await;
+    });
+
+    run_to!("thread1", RegexLabel::qualified(Regex::new(r"^namespace_test::worker::").unwrap())).await;
+    assert_eq!(vec!["client"], *data.read().await);
+
+    // Unqualified patterns only see the label's name
+    run_to!("thread1", RegexLabel::new(Regex::new(r"^END$").unwrap())).await;
+    assert_eq!(vec!["client", "worker"], *data.read().await);
+}
+
+#[tokitest::test]
+async fn test_trace_is_qualified() {
+    let data = Arc::new(RwLock::new(Vec::new()));
+
+    let dc = data.clone();
+    spawn!("thread1", async {
+        call!(client::run(dc)).await;
+        label!("done");
+    });
+
+    run_to!("thread1", StringLabel::new("done")).await;
+
+    let labels: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .filter_map(|event| match event {
+            TraceEvent::Label { label, .. } => Some(label.qualified()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec![
+        "namespace_test::test_trace_is_qualified::INIT",
+        "namespace_test::client::run::start",
+        "namespace_test::worker::run::start",
+        "namespace_test::test_trace_is_qualified::done",
+    ], labels);
+
+    assert_eq!(
+        "thread1: namespace_test::client::run::start",
+        tokitest_main_controller.trace().await[1].to_string()
+    );
+    complete!("thread1").await;
+}
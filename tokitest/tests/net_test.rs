@@ -0,0 +1,123 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, run_to, complete, partition, heal, hold, pending_messages, release, drop_message};
+use tokitest::net::SimNetwork;
+
+#[tokitest::test]
+async fn test_ping_pong() {
+    let network = SimNetwork::<String>::new();
+    let node1 = network.endpoint("node1");
+    let mut node2 = network.endpoint("node2");
+    let received = Arc::new(RwLock::new(Vec::new()));
+
+    spawn!("node1", async {
+        node1.send("node2", "ping".to_string()).await;
+    });
+    let rc = received.clone();
+    spawn!("node2", async {
+        let (from, message) = node2.recv().await;
+        rc.write().await.push(format!("{}: {}", from, message));
+        label!("handled");
+    });
+
+    run_to!("node1", "send to node2").await;
+    // The message is not sent until node1 leaves the checkpoint
+    complete!("node1").await;
+    run_to!("node2", "recv from node1").await;
+    assert!(received.read().await.is_empty());
+    complete!("node2").await;
+    assert_eq!(vec!["node1: ping"], *received.read().await);
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter().map(ToString::to_string).collect();
+    assert!(trace.contains(&"node1: tokitest::net::send to node2".to_string()));
+    assert!(trace.contains(&"node1: network call to node2 delivered after 0ns".to_string()));
+    assert!(trace.contains(&"node2: tokitest::net::recv from node1".to_string()));
+}
+
+#[tokitest::test]
+async fn test_partition_drops_messages() {
+    let network = SimNetwork::<u32>::new();
+    let node1 = network.endpoint("node1");
+    let mut node2 = network.endpoint("node2");
+    let received = Arc::new(RwLock::new(Vec::new()));
+
+    spawn!("node1", async {
+        node1.send("node2", 1).await;
+        node1.send("node2", 2).await;
+    });
+    let rc = received.clone();
+    spawn!("node2", async {
+        let (_, message) = node2.recv().await;
+        rc.write().await.push(message);
+    });
+
+    partition!(["node1"], ["node2"]).await;
+    run_to!("node1", "send to node2").await;
+    run_to!("node1", "send to node2").await;
+    heal!().await;
+    complete!("node1").await;
+
+    complete!("node2").await;
+    assert_eq!(vec![2], *received.read().await);
+}
+
+#[tokitest::test]
+async fn test_hold_and_reorder() {
+    let network = SimNetwork::<u32>::with_payloads();
+    let node1 = network.endpoint("node1");
+    let mut node2 = network.endpoint("node2");
+    let received = Arc::new(RwLock::new(Vec::new()));
+
+    spawn!("node1", async {
+        for message in 1..=4 {
+            node1.send("node2", message).await;
+        }
+    });
+    let rc = received.clone();
+    spawn!("node2", async {
+        for _ in 0..3 {
+            let (_, message) = node2.recv().await;
+            rc.write().await.push(message);
+        }
+    });
+
+    hold!("node1" -> "node2").await;
+    complete!("node1").await;
+
+    let held = pending_messages!("node1" -> "node2").await;
+    assert_eq!(4, held.len());
+    assert_eq!("#2 node1 -> node2 sent by node1: 3", held[2].to_string());
+
+    assert!(release!(held[0]).await);
+    assert!(release!(held[1]).await);
+    // Jumps ahead of the two messages already delivered
+    assert!(release!(held[3], next).await);
+    assert!(drop_message!(held[2]).await);
+    assert!(!release!(held[2]).await);
+    assert!(pending_messages!("node1" -> "node2").await.is_empty());
+
+    complete!("node2").await;
+    assert_eq!(vec![4, 1, 2], *received.read().await);
+}
+
+#[tokio::test]
+async fn test_without_controller() {
+    // Outside of a testable thread, endpoints deliver directly without checkpoints
+    let network = SimNetwork::<&str>::new();
+    let node1 = network.endpoint("node1");
+    let mut node2 = network.endpoint("node2");
+
+    let handle = tokio::spawn(async move { node2.recv().await });
+    node1.send("node2", "hello").await;
+    assert_eq!(("node1".to_string(), "hello"), handle.await.unwrap());
+}
+
+#[test]
+#[should_panic(expected = "SimNetwork endpoint node1 already exists")]
+fn test_duplicate_endpoint() {
+    let network = SimNetwork::<()>::new();
+    let _first = network.endpoint("node1");
+    let _second = network.endpoint("node1");
+}
@@ -0,0 +1,188 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, network_call, isolate, heal, cut, partition};
+
+async fn mock_rpc(from: &str, to: &str) -> String {
+    format!("{}->{}: ok", from, to)
+}
+
+async fn mock_rpc_error(from: &str, to: &str) -> String {
+    format!("{}->{}: unreachable", from, to)
+}
+
+#[tokitest::testable]
+async fn broadcast(from: &'static str, peers: Vec<&'static str>, results: Arc<RwLock<Vec<String>>>) {
+    for to in peers {
+        let result = network_call!(to = to, mock_rpc(from, to), mock_rpc_error(from, to)).await;
+        results.write().await.push(result);
+    }
+    label!("broadcast done");
+}
+
+#[tokitest::test]
+async fn test_split_brain() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(broadcast("node1", vec!["node2", "node3"], rc)).await;
+    });
+    let rc = results.clone();
+    spawn!("node3", async {
+        call!(broadcast("node3", vec!["node1", "node2"], rc)).await;
+    });
+
+    partition!(["node1", "node2"], ["node3"]).await;
+
+    complete!("node1").await;
+    assert_eq!(vec!["node1->node2: ok", "node1->node3: unreachable"], *results.read().await);
+
+    results.write().await.clear();
+    complete!("node3").await;
+    assert_eq!(vec!["node3->node1: unreachable", "node3->node2: unreachable"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_one_way_cut() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(broadcast("node1", vec!["node2"], rc)).await;
+    });
+    let rc = results.clone();
+    spawn!("node2", async {
+        call!(broadcast("node2", vec!["node1"], rc)).await;
+    });
+
+    cut!("node1" -> "node2").await;
+
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(vec!["node1->node2: unreachable", "node2->node1: ok"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_nested_threads_share_links() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        spawn!("worker", async {
+            call!(broadcast("node1.worker", vec!["node2", "node2.worker"], rc)).await;
+        });
+        label!("spawned worker");
+    });
+
+    cut!("node1" -> "node2").await;
+    run_to!("node1", "spawned worker").await;
+    complete!("node1.worker").await;
+    assert_eq!(vec!["node1.worker->node2: unreachable", "node1.worker->node2.worker: unreachable"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_heal() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(broadcast("node1", vec!["node2", "node3"], rc)).await;
+    });
+    let rc = results.clone();
+    spawn!("node2", async {
+        call!(broadcast("node2", vec!["node1", "node3"], rc)).await;
+    });
+    let rc = results.clone();
+    spawn!("node3", async {
+        call!(broadcast("node3", vec!["node1", "node2"], rc)).await;
+    });
+
+    cut!("node1" -> "node2").await;
+    cut!("node1" -> "node3").await;
+    heal!("node1" -> "node3").await;
+    complete!("node1").await;
+    assert_eq!(vec!["node1->node2: unreachable", "node1->node3: ok"], *results.read().await);
+
+    // Isolating a destination makes it unreachable
+    results.write().await.clear();
+    isolate!("node1").await;
+    cut!("node3" -> "node2").await;
+    run_to!("node2", "broadcast done").await;
+    assert_eq!(vec!["node2->node1: unreachable", "node2->node3: ok"], *results.read().await);
+
+    // Heals the isolation of node1 and the cut from node3
+    heal!().await;
+    results.write().await.clear();
+    complete!("node3").await;
+    assert_eq!(vec!["node3->node1: ok", "node3->node2: ok"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_partition_replaces_previous() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(broadcast("node1", vec!["node2", "node3"], rc)).await;
+    });
+
+    partition!(["node1"], ["node2", "node3"]).await;
+    partition!(["node1", "node2"], ["node3"]).await;
+
+    complete!("node1").await;
+    assert_eq!(vec!["node1->node2: ok", "node1->node3: unreachable"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_heal_after_partition() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(broadcast("node1", vec!["node2", "node3"], rc)).await;
+    });
+    let rc = results.clone();
+    spawn!("node3", async {
+        call!(broadcast("node3", vec!["node1", "node2"], rc)).await;
+    });
+
+    partition!(["node1"], ["node2"], ["node3"]).await;
+    // node3 leaves the partition, node1 and node2 stay apart
+    heal!("node3").await;
+
+    complete!("node1").await;
+    assert_eq!(vec!["node1->node2: unreachable", "node1->node3: ok"], *results.read().await);
+
+    results.write().await.clear();
+    complete!("node3").await;
+    assert_eq!(vec!["node3->node1: ok", "node3->node2: ok"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_nodes_match_by_id_not_prefix() {
+    let results = Arc::new(RwLock::new(Vec::<String>::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        spawn!("worker", async {
+            call!(broadcast("node1.worker", vec!["node2"], rc)).await;
+        });
+        label!("spawned worker");
+    });
+    let rc = results.clone();
+    spawn!("node10", async {
+        call!(broadcast("node10", vec!["node2"], rc)).await;
+    });
+
+    isolate!("node10").await;
+    cut!("node1.worker" -> "node2").await;
+    // Heals the cut from node1.worker, but not node10
+    heal!("node1").await;
+
+    run_to!("node1", "spawned worker").await;
+    complete!("node1.worker").await;
+    complete!("node10").await;
+    assert_eq!(vec!["node1.worker->node2: ok", "node10->node2: unreachable"], *results.read().await);
+}
@@ -0,0 +1,110 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, network_call, hold, pending_messages, release, drop_message};
+use tokitest::net::SimNetwork;
+
+async fn mock_rpc(to: &str) -> String {
+    format!("{}: ok", to)
+}
+
+async fn mock_rpc_error(to: &str) -> String {
+    format!("{}: dropped", to)
+}
+
+#[tokitest::testable]
+async fn request_vote(to: &'static str, results: Arc<RwLock<Vec<String>>>) {
+    let result = network_call!(to = to, mock_rpc(to), mock_rpc_error(to)).await;
+    results.write().await.push(result);
+    label!("vote counted");
+}
+
+#[tokitest::test]
+async fn test_hold_network_call() {
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(request_vote("node2", rc.clone())).await;
+        call!(request_vote("node2", rc.clone())).await;
+        call!(request_vote("node3", rc)).await;
+    });
+
+    hold!("node1" -> "node2").await;
+
+    run_to!("node1", "held to node2").await;
+    let pending = pending_messages!("node1" -> "node2").await;
+    assert_eq!(1, pending.len());
+    assert_eq!("#0 node1 -> node2 sent by node1", pending[0].to_string());
+    assert!(drop_message!(pending[0]).await);
+    assert!(!release!(pending[0]).await);
+
+    run_to!("node1", "held to node2").await;
+    let pending = pending_messages!("node1" -> "node2").await;
+    assert!(release!(pending[0]).await);
+
+    // Links that are not held are not affected
+    complete!("node1").await;
+    assert_eq!(vec!["node2: dropped", "node2: ok", "node3: ok"], *results.read().await);
+    assert!(pending_messages!("node1" -> "node2").await.is_empty());
+}
+
+#[tokitest::test]
+async fn test_reorder_sim_network_messages() {
+    let network = SimNetwork::<u32>::new();
+    let node1 = network.endpoint("node1");
+    let mut node2 = network.endpoint("node2");
+    let received = Arc::new(RwLock::new(Vec::new()));
+
+    spawn!("node1", async {
+        for message in 1..=3 {
+            node1.send("node2", message).await;
+        }
+    });
+    let rc = received.clone();
+    spawn!("node2", async {
+        for _ in 0..2 {
+            let (_, message) = node2.recv().await;
+            rc.write().await.push(message);
+        }
+    });
+
+    hold!("node1" -> "node2").await;
+    // Held messages do not block the sender
+    complete!("node1").await;
+
+    let pending = pending_messages!("node1" -> "node2").await;
+    assert_eq!(3, pending.len());
+    release!(pending[2]).await;
+    drop_message!(pending[1]).await;
+    release!(pending[0]).await;
+
+    complete!("node2").await;
+    assert_eq!(vec![3, 1], *received.read().await);
+}
+
+#[tokitest::test]
+async fn test_pending_messages_by_link() {
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        spawn!("worker", async {
+            call!(request_vote("node2", rc)).await;
+        });
+        label!("spawned worker");
+    });
+
+    hold!("node1" -> "node2").await;
+    run_to!("node1", "spawned worker").await;
+    run_to!("node1.worker", "held to node2").await;
+
+    assert!(pending_messages!("node3" -> "node2").await.is_empty());
+    let pending = pending_messages!("node1" -> "node2").await;
+    assert_eq!("node1.worker", pending[0].thread);
+    release!(pending[0]).await;
+
+    complete!("node1.worker").await;
+    assert_eq!(vec!["node2: ok"], *results.read().await);
+}
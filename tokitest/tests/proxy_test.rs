@@ -0,0 +1,196 @@
+#![cfg(tokitest)]
+
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::{timeout, Duration};
+use tokitest::{run_to, resume, isolate, heal, cut, partition};
+use tokitest::proxy::{TcpProxy, UdpProxy};
+
+async fn tcp_echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = [0; 1024];
+                while let Ok(n) = stream.read(&mut buffer).await {
+                    if n == 0 || stream.write_all(&buffer[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+async fn udp_echo_server() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buffer = [0; 1024];
+        while let Ok((n, from)) = socket.recv_from(&mut buffer).await {
+            let _ = socket.send_to(&buffer[..n], from).await;
+        }
+    });
+    addr
+}
+
+async fn echo(stream: &mut TcpStream, message: &[u8]) -> Vec<u8> {
+    stream.write_all(message).await.unwrap();
+    let mut buffer = vec![0; message.len()];
+    stream.read_exact(&mut buffer).await.unwrap();
+    buffer
+}
+
+/// Nothing arrives on the stream for a while
+async fn assert_silent(stream: &mut TcpStream) {
+    let mut buffer = [0; 16];
+    assert!(timeout(Duration::from_millis(100), stream.read(&mut buffer)).await.is_err());
+}
+
+#[tokitest::test]
+async fn test_tcp_forwarding() {
+    let proxy = TcpProxy::start(&tokitest_main_controller, "node2", tcp_echo_server().await).await.unwrap();
+    assert_eq!("proxy-node2", proxy.thread_id());
+
+    let mut stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    assert_eq!(b"ping".to_vec(), echo(&mut stream, b"ping").await);
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter().map(ToString::to_string).collect();
+    assert_eq!(vec![
+        "proxy-node2: tokitest::proxy::accept",
+        "proxy-node2: tokitest::proxy::read from client",
+        "proxy-node2: network call to node2 delivered after 0ns",
+        "proxy-node2: tokitest::proxy::write to server",
+        "proxy-node2: tokitest::proxy::read from server",
+        "proxy-node2: network call to proxy-node2 delivered after 0ns",
+        "proxy-node2: tokitest::proxy::write to client",
+    ], trace);
+}
+
+#[tokitest::test]
+async fn test_tcp_isolation() {
+    let proxy = TcpProxy::start(&tokitest_main_controller, "node2", tcp_echo_server().await).await.unwrap();
+    let mut stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    assert_eq!(b"before".to_vec(), echo(&mut stream, b"before").await);
+
+    // Open connections stall while the node is isolated
+    isolate!("node2").await;
+    stream.write_all(b"during").await.unwrap();
+    assert_silent(&mut stream).await;
+
+    // New connections are closed
+    let mut refused = TcpStream::connect(proxy.addr()).await.unwrap();
+    let mut buffer = [0; 16];
+    assert_eq!(0, refused.read(&mut buffer).await.unwrap_or(0));
+
+    heal!("node2").await;
+    let mut buffer = [0; 6];
+    stream.read_exact(&mut buffer).await.unwrap();
+    assert_eq!(b"during", &buffer);
+}
+
+#[tokitest::test]
+async fn test_tcp_cut_link() {
+    let proxy = TcpProxy::start_link(&tokitest_main_controller, "node1", "node2", tcp_echo_server().await).await.unwrap();
+    let mut stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    assert_eq!(b"before".to_vec(), echo(&mut stream, b"before").await);
+
+    // Requests stall on the cut link, and the reply waits for the reverse link
+    cut!("node1" -> "node2").await;
+    stream.write_all(b"during").await.unwrap();
+    assert_silent(&mut stream).await;
+
+    heal!("node1" -> "node2").await;
+    cut!("node2" -> "node1").await;
+    assert_silent(&mut stream).await;
+
+    heal!().await;
+    let mut buffer = [0; 6];
+    stream.read_exact(&mut buffer).await.unwrap();
+    assert_eq!(b"during", &buffer);
+}
+
+#[tokitest::test]
+async fn test_tcp_partition() {
+    let proxy = TcpProxy::start_link(&tokitest_main_controller, "node1", "node2", tcp_echo_server().await).await.unwrap();
+
+    // New connections across the partition are closed
+    partition!(["node1"], ["node2"]).await;
+    let mut refused = TcpStream::connect(proxy.addr()).await.unwrap();
+    let mut buffer = [0; 16];
+    assert_eq!(0, refused.read(&mut buffer).await.unwrap_or(0));
+
+    partition!(["node1", "node2"], ["node3"]).await;
+    let mut stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    assert_eq!(b"ping".to_vec(), echo(&mut stream, b"ping").await);
+}
+
+#[tokitest::test]
+async fn test_tcp_faults() {
+    let proxy = TcpProxy::start(&tokitest_main_controller, "node2", tcp_echo_server().await).await.unwrap();
+    let mut stream = TcpStream::connect(proxy.addr()).await.unwrap();
+
+    proxy.stall();
+    stream.write_all(b"stalled").await.unwrap();
+    assert_silent(&mut stream).await;
+    proxy.resume();
+    let mut buffer = [0; 7];
+    stream.read_exact(&mut buffer).await.unwrap();
+    assert_eq!(b"stalled", &buffer);
+
+    proxy.drop_bytes(true);
+    stream.write_all(b"dropped").await.unwrap();
+    assert_silent(&mut stream).await;
+    proxy.drop_bytes(false);
+
+    proxy.delay(Duration::from_millis(50));
+    let start = std::time::Instant::now();
+    assert_eq!(b"delayed".to_vec(), echo(&mut stream, b"delayed").await);
+    // Delayed once on the way to the server and once on the way back
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokitest::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_run_to_proxy_label() {
+    let proxy = TcpProxy::start(&tokitest_main_controller, "node2", tcp_echo_server().await).await.unwrap();
+    let addr = proxy.addr();
+    let client = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        echo(&mut stream, b"ping").await
+    });
+
+    // The proxy parks at the label, so the request is isolated right before it is forwarded
+    run_to!("proxy-node2", "read from client").await;
+    isolate!("node2").await;
+    resume!("proxy-node2").await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!client.is_finished());
+
+    heal!().await;
+    assert_eq!(b"ping".to_vec(), client.await.unwrap());
+}
+
+#[tokitest::test]
+async fn test_udp() {
+    let proxy = UdpProxy::start(&tokitest_main_controller, "node2", udp_echo_server().await).await.unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(proxy.addr()).await.unwrap();
+    let mut buffer = [0; 16];
+
+    socket.send(b"ping").await.unwrap();
+    let n = socket.recv(&mut buffer).await.unwrap();
+    assert_eq!(b"ping", &buffer[..n]);
+
+    // Datagrams are lost while the node is isolated
+    isolate!("node2").await;
+    socket.send(b"lost").await.unwrap();
+    assert!(timeout(Duration::from_millis(100), socket.recv(&mut buffer)).await.is_err());
+
+    heal!("node2").await;
+    socket.send(b"pong").await.unwrap();
+    let n = socket.recv(&mut buffer).await.unwrap();
+    assert_eq!(b"pong", &buffer[..n]);
+}
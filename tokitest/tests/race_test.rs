@@ -0,0 +1,131 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokitest::{label, spawn, call, complete, run_to};
+use tokitest::sync::{mpsc, Mutex, Named, Shared};
+
+#[tokitest::testable]
+async fn increment(counter: Arc<Shared<i32>>) {
+    label!("start");
+    let value = counter.get();
+    counter.set(value + 1);
+}
+
+#[tokitest::testable]
+async fn locked_increment(lock: Arc<Mutex<()>>, counter: Arc<Shared<i32>>) {
+    let _guard = lock.lock().await;
+    counter.write(|counter| *counter += 1);
+}
+
+#[tokitest::test]
+async fn test_unsynchronized_writes() {
+    let counter = Arc::new(Shared::named("counter", 0));
+
+    for node in ["node1", "node2"] {
+        let counter = counter.clone();
+        spawn!(node, async {
+            call!(increment(counter)).await;
+        });
+    }
+
+    // The threads run together, although no update is lost in this schedule
+    tokio::join!(complete!("node1"), complete!("node2"));
+    assert_eq!(2, counter.get());
+
+    let races = tokitest_main_controller.take_data_races().await;
+    assert_eq!(1, races.len());
+    assert_eq!(
+        "data race on counter:\n  \
+        node1 wrote after race_test::increment::start\n  \
+        node2 read after race_test::increment::start",
+        races[0].to_string()
+    );
+}
+
+#[tokitest::test]
+async fn test_handshakes_order_threads() {
+    let counter = Arc::new(Shared::named("counter", 0));
+
+    for node in ["node1", "node2"] {
+        let counter = counter.clone();
+        spawn!(node, async {
+            call!(increment(counter)).await;
+        });
+    }
+
+    // node2 is only resumed once the test saw node1 return
+    complete!("node1").await;
+    run_to!("node2", "start").await;
+    complete!("node2").await;
+    assert_eq!(2, counter.get());
+    assert!(tokitest_main_controller.take_data_races().await.is_empty());
+}
+
+#[tokitest::test]
+async fn test_accesses_under_lock() {
+    let lock = Arc::new(Mutex::named("lock", ()));
+    let counter = Arc::new(Shared::named("counter", 0));
+
+    for node in ["node1", "node2"] {
+        let (lock, counter) = (lock.clone(), counter.clone());
+        spawn!(node, async {
+            call!(locked_increment(lock, counter)).await;
+        });
+    }
+
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(2, counter.get());
+    assert!(tokitest_main_controller.take_data_races().await.is_empty());
+}
+
+#[tokitest::test]
+async fn test_channel_handoff() {
+    let counter = Arc::new(Shared::named("counter", 0));
+    let (sender, mut receiver) = mpsc::named_channel("handoff", 1);
+
+    let written = counter.clone();
+    spawn!("producer", async move {
+        written.set(42);
+        sender.send(()).await.unwrap();
+    });
+    let read = counter.clone();
+    spawn!("consumer", async move {
+        receiver.recv().await.unwrap();
+        assert_eq!(42, read.get());
+    });
+
+    complete!("producer").await;
+    complete!("consumer").await;
+    assert!(tokitest_main_controller.take_data_races().await.is_empty());
+}
+
+#[tokitest::test]
+async fn test_read_only_sharing() {
+    let config = Arc::new(Shared::named("config", "primary"));
+
+    for node in ["node1", "node2"] {
+        let config = config.clone();
+        spawn!(node, async move {
+            assert_eq!("primary", config.get());
+        });
+    }
+
+    complete!("node1").await;
+    complete!("node2").await;
+    assert!(tokitest_main_controller.take_data_races().await.is_empty());
+}
+
+#[tokitest::test]
+#[should_panic(expected = "tokitest: data race on counter:")]
+async fn test_data_race_fails_test() {
+    let counter = Arc::new(Shared::named("counter", 0));
+
+    for node in ["node1", "node2"] {
+        let counter = counter.clone();
+        spawn!(node, async {
+            call!(increment(counter)).await;
+        });
+    }
+    tokio::join!(complete!("node1"), complete!("node2"));
+}
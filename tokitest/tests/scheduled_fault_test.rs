@@ -0,0 +1,94 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokitest::{label, spawn, call, run_to, complete, network_call, on_label, heal, StringLabel, RepeatedLabel};
+
+async fn mock_rpc(to: &str) -> String {
+    format!("{}: ok", to)
+}
+
+async fn mock_rpc_error(to: &str) -> String {
+    format!("{}: unreachable", to)
+}
+
+#[tokitest::testable]
+async fn replicate(to: &'static str, rounds: usize, results: Arc<RwLock<Vec<String>>>) {
+    for _ in 0..rounds {
+        let result = network_call!(to = to, mock_rpc(to), mock_rpc_error(to)).await;
+        results.write().await.push(result);
+        label!("before commit");
+    }
+}
+
+#[tokitest::test]
+async fn test_isolate_on_label() {
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(replicate("node2", 3, rc)).await;
+    });
+
+    on_label!("node1", "before commit", isolate("node1")).await;
+
+    // The thread is never stopped at the label by the test
+    complete!("node1").await;
+    assert_eq!(vec!["node2: ok", "node2: unreachable", "node2: unreachable"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_partition_on_repeated_label() {
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(replicate("node3", 4, rc)).await;
+    });
+
+    on_label!("node1", RepeatedLabel::new(StringLabel::new("before commit"), 2), partition(["node1", "node2"], ["node3"])).await;
+
+    complete!("node1").await;
+    assert_eq!(vec!["node3: ok", "node3: ok", "node3: unreachable", "node3: unreachable"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_fault_applies_once() {
+    let results = Arc::new(RwLock::new(Vec::new()));
+
+    let rc = results.clone();
+    spawn!("node1", async {
+        call!(replicate("node2", 3, rc)).await;
+    });
+
+    on_label!("node1", "before commit", cut("node1" -> "node2")).await;
+    on_label!("node1", RepeatedLabel::new(StringLabel::new("before commit"), 2), heal()).await;
+
+    run_to!("node1", RepeatedLabel::new(StringLabel::new("before commit"), 2)).await;
+    assert_eq!(vec!["node2: ok", "node2: unreachable"], *results.read().await);
+
+    // The cut is not applied again at the third "before commit"
+    complete!("node1").await;
+    assert_eq!(vec!["node2: ok", "node2: unreachable", "node2: ok"], *results.read().await);
+}
+
+#[tokitest::test]
+async fn test_fault_in_trace() {
+    spawn!("node1", async {
+        label!("before commit");
+    });
+
+    on_label!("node1", "scheduled_fault_test::test_fault_in_trace::before commit", isolate("node2")).await;
+    on_label!("node1", "END", heal("node2")).await;
+    complete!("node1").await;
+    heal!().await;
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter().map(ToString::to_string).collect();
+    assert_eq!(vec![
+        "node1: scheduled_fault_test::test_fault_in_trace::INIT",
+        "node1: scheduled_fault_test::test_fault_in_trace::before commit",
+        "node1: isolate node2 at scheduled_fault_test::test_fault_in_trace::before commit",
+        "node1: scheduled_fault_test::test_fault_in_trace::END",
+        "node1: heal node2 at scheduled_fault_test::test_fault_in_trace::END",
+    ], trace);
+}
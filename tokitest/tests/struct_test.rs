@@ -1,3 +1,5 @@
+#![cfg(tokitest)]
+
 use std::sync::Arc;
 use tokio::{sync::RwLock};
 use tokio::time::{sleep, Duration};
@@ -0,0 +1,148 @@
+#![cfg(tokitest)]
+
+use std::sync::{Arc, LazyLock};
+use regex::Regex;
+use tokitest::{label, spawn, run_to, complete, RegexLabel};
+use tokitest::sync::{Mutex, MutexGuard, Named, OwnedMutexGuard, OwnedRwLockWriteGuard, RwLock, RwLockWriteGuard};
+
+#[tokitest::test]
+async fn test_lock_checkpoints() {
+    let balance = Arc::new(Mutex::named("balance", 100));
+
+    for (node, amount) in [("node1", 10), ("node2", 20)] {
+        let bc = balance.clone();
+        spawn!(node, async {
+            *bc.lock().await -= amount;
+        });
+    }
+
+    // node1 holds the lock while node2 is about to take it
+    run_to!("node1", "lock acquired:balance").await;
+    run_to!("node2", "lock requested:balance").await;
+    assert!(balance.try_lock().is_err());
+    complete!("node1").await;
+    complete!("node2").await;
+    assert_eq!(70, *balance.lock().await);
+
+    let trace: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| !event.ends_with("INIT"))
+        .collect();
+    assert_eq!(vec![
+        "node1: tokitest::sync::lock requested:balance",
+        "node1: tokitest::sync::lock acquired:balance",
+        "node2: tokitest::sync::lock requested:balance",
+        "node1: tokitest::sync::lock released:balance",
+        "node1: sync_test::test_lock_checkpoints::END",
+        "node2: tokitest::sync::lock acquired:balance",
+        "node2: tokitest::sync::lock released:balance",
+        "node2: sync_test::test_lock_checkpoints::END",
+    ], trace);
+}
+
+#[tokitest::test]
+async fn test_rwlock_checkpoints() {
+    let config = Arc::new(RwLock::named("config", String::from("v1")));
+
+    let cc = config.clone();
+    spawn!("reader", async {
+        let first = cc.read().await.clone();
+        label!("read once");
+        let second = cc.try_read().unwrap().clone();
+        assert_eq!(first, second);
+    });
+    let cc = config.clone();
+    spawn!("writer", async {
+        *cc.write().await = String::from("v2");
+    });
+
+    run_to!("reader", "read once").await;
+    run_to!("writer", RegexLabel::new(Regex::new("lock (requested|acquired):config").unwrap())).await;
+    complete!("reader").await;
+    complete!("writer").await;
+    assert_eq!("v2", *config.read().await);
+
+    let reader: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| event.starts_with("reader: tokitest::sync"))
+        .collect();
+    assert_eq!(vec![
+        "reader: tokitest::sync::lock requested:config",
+        "reader: tokitest::sync::lock acquired:config",
+        "reader: tokitest::sync::lock released:config",
+        "reader: tokitest::sync::lock acquired:config",
+        "reader: tokitest::sync::lock released:config",
+    ], reader);
+}
+
+#[tokitest::test]
+async fn test_default_names() {
+    let mutex = Mutex::new(0);
+    let rwlock: RwLock<i32> = RwLock::default();
+    assert!(mutex.name().starts_with("tests/sync_test.rs:"));
+    assert!(rwlock.name().starts_with("tests/sync_test.rs:"));
+    assert_ne!(mutex.name(), rwlock.name());
+
+    // The root test thread places no checkpoints
+    *mutex.lock().await += 1;
+    assert_eq!(1, mutex.into_inner());
+    assert!(tokitest_main_controller.trace().await.is_empty());
+}
+
+static COUNTER: LazyLock<Mutex<i32>> = LazyLock::new(|| Mutex::new(0));
+
+#[tokitest::test]
+async fn test_owned_and_mapped_guards() {
+    let balances = Arc::new(Mutex::named("balances", (100, 200)));
+    let ledger = Arc::new(RwLock::named("ledger", vec![1, 2]));
+
+    let (bc, lc) = (balances.clone(), ledger.clone());
+    spawn!("node1", async move {
+        let balances = bc.lock_owned().await;
+        label!("holding");
+        // The mapped guard is released at the end of the statement
+        *OwnedMutexGuard::map(balances, |(first, _)| first) -= 10;
+        let mut entries = lc.write_owned().await;
+        entries.push(3);
+        let entries = OwnedRwLockWriteGuard::downgrade(entries);
+        assert_eq!(3, entries.len());
+    });
+
+    run_to!("node1", "holding").await;
+    assert!(balances.clone().try_lock_owned().is_err());
+    complete!("node1").await;
+
+    let mut second = MutexGuard::map(balances.lock().await, |(_, second)| second);
+    *second += 10;
+    drop(second);
+    assert_eq!((90, 210), *balances.lock().await);
+    let first = RwLockWriteGuard::downgrade_map(ledger.write().await, |entries| &entries[0]);
+    assert_eq!(1, *first);
+    drop(first);
+
+    let node1: Vec<String> = tokitest_main_controller.trace().await.iter()
+        .map(ToString::to_string)
+        .filter(|event| event.starts_with("node1: tokitest::sync"))
+        .collect();
+    assert_eq!(vec![
+        "node1: tokitest::sync::lock requested:balances",
+        "node1: tokitest::sync::lock acquired:balances",
+        "node1: tokitest::sync::lock released:balances",
+        "node1: tokitest::sync::lock requested:ledger",
+        "node1: tokitest::sync::lock acquired:ledger",
+        "node1: tokitest::sync::lock released:ledger",
+    ], node1);
+}
+
+#[tokitest::test]
+async fn test_static_and_blocking_locks() {
+    assert!(COUNTER.name().starts_with("tests/sync_test.rs:"));
+    *COUNTER.lock().await += 1;
+
+    let value = tokio::task::spawn_blocking(|| {
+        let mut counter = COUNTER.blocking_lock();
+        *counter += 1;
+        *counter
+    }).await.unwrap();
+    assert_eq!(2, value);
+}
@@ -0,0 +1,89 @@
+#![cfg(tokitest)]
+
+use std::sync::Arc;
+use tokitest::{label, spawn, call, run_to, complete, assert_trace};
+use tokitest::sync::{Mutex, Named};
+use tokitest::temporal::{at, eventually, never};
+
+#[tokitest::testable]
+async fn commit(log: Arc<Mutex<Vec<i32>>>, entry: i32) {
+    let mut log = log.lock().await;
+    label!("commit");
+    log.push(entry);
+    label!("write");
+}
+
+#[tokitest::testable]
+async fn unlocked_commit(log: Arc<std::sync::Mutex<Vec<i32>>>, entry: i32) {
+    label!("commit");
+    log.lock().unwrap().push(entry);
+    label!("write");
+}
+
+#[tokitest::test]
+async fn test_mutual_exclusion() {
+    let log = Arc::new(Mutex::named("log", Vec::new()));
+
+    for (node, entry) in [("node1", 1), ("node2", 2)] {
+        let log = log.clone();
+        spawn!(node, async move {
+            call!(commit(log, entry)).await;
+            label!("done");
+        });
+    }
+
+    run_to!("node1", "commit").await;
+    run_to!("node2", "lock requested:log").await;
+    complete!("node1").await;
+    complete!("node2").await;
+
+    assert_trace!(never("node1" at "commit" while "node2" at "commit")).await;
+    assert_trace!(always(before("lock acquired:log", "write"))).await;
+    assert_trace!(always(not("node1" at "write" while "node2" at "write"))).await;
+    assert_trace!(eventually("node2", "done")).await;
+}
+
+#[tokitest::test]
+async fn test_violation() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    for (node, entry) in [("node1", 1), ("node2", 2)] {
+        let log = log.clone();
+        spawn!(node, async move {
+            call!(unlocked_commit(log, entry)).await;
+        });
+    }
+
+    run_to!("node1", "commit").await;
+    run_to!("node2", "commit").await;
+    complete!("node1").await;
+    complete!("node2").await;
+
+    let trace = tokitest_main_controller.trace().await;
+    let violation = never(at("node1", "commit").and(at("node2", "commit"))).check(&trace).unwrap_err();
+    assert_eq!(Some(3), violation.index);
+    assert_eq!(
+        "trace violates never(\"node1\" at \"commit\" while \"node2\" at \"commit\") at event 3, recent trace:\n  \
+        node1: temporal_test::test_violation::INIT\n  \
+        node2: temporal_test::test_violation::INIT\n  \
+        node1: temporal_test::unlocked_commit::commit\n  \
+        node2: temporal_test::unlocked_commit::commit",
+        violation.to_string()
+    );
+
+    assert!(eventually(at("node1", "lock acquired:log")).check(&trace).is_err());
+}
+
+#[tokitest::test]
+#[should_panic(expected = "tokitest: trace violates always(before(\"lock acquired:log\", \"write\")) at event 2")]
+async fn test_assert_trace_fails_test() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let lc = log.clone();
+    spawn!("node1", async move {
+        call!(unlocked_commit(lc, 1)).await;
+    });
+    complete!("node1").await;
+
+    assert_trace!(always(before("lock acquired:log", "write"))).await;
+}